@@ -0,0 +1,42 @@
+//! Compares one-shot `encrypt` against a reused `Cipher`, allocating and in place
+
+#![forbid(unsafe_code)]
+
+use aes_gcm_siv_impl::{Cipher, TAG_LENGTH};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const KEY: [u8; 32] = [0x42; 32];
+const NONCE: [u8; 12] = [0x24; 12];
+
+// The one-shot baseline is the free function, deprecated with
+// `strict-deprecations`
+#[allow(deprecated)]
+fn bench_encrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encrypt");
+    let cipher = Cipher::new(&KEY).unwrap();
+    for size in [64usize, 1024, 16 * 1024] {
+        let plaintext = vec![0xa5u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("one_shot", size), &plaintext, |b, pt| {
+            b.iter(|| aes_gcm_siv_impl::encrypt(&KEY, &NONCE, black_box(pt), b"").unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("cipher", size), &plaintext, |b, pt| {
+            b.iter(|| cipher.encrypt(&NONCE, black_box(pt), b"").unwrap())
+        });
+
+        let mut buf = Vec::with_capacity(size + TAG_LENGTH);
+        group.bench_with_input(BenchmarkId::new("in_place", size), &plaintext, |b, pt| {
+            b.iter(|| {
+                buf.clear();
+                buf.extend_from_slice(black_box(pt));
+                cipher.encrypt_in_place(&NONCE, b"", &mut buf).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encrypt);
+criterion_main!(benches);
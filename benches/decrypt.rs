@@ -0,0 +1,37 @@
+//! Compares the allocating `Cipher::decrypt` against buffer-reusing
+//! `Cipher::decrypt_into_buf`
+
+#![forbid(unsafe_code)]
+
+use aes_gcm_siv_impl::Cipher;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const KEY: [u8; 32] = [0x42; 32];
+const NONCE: [u8; 12] = [0x24; 12];
+
+fn bench_decrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decrypt");
+    let cipher = Cipher::new(&KEY).unwrap();
+    for size in [64usize, 1024, 16 * 1024] {
+        let plaintext = vec![0xa5u8; size];
+        let ciphertext = cipher.encrypt(&NONCE, &plaintext, b"").unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("vec", size), &ciphertext, |b, ct| {
+            b.iter(|| cipher.decrypt(&NONCE, black_box(ct), b"").unwrap())
+        });
+
+        let mut buf = Vec::new();
+        group.bench_with_input(BenchmarkId::new("into_buf", size), &ciphertext, |b, ct| {
+            b.iter(|| {
+                cipher
+                    .decrypt_into_buf(&NONCE, black_box(ct), b"", &mut buf)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_decrypt);
+criterion_main!(benches);
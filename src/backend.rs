@@ -0,0 +1,136 @@
+//! Detection of the AES and POLYVAL implementations in use
+//!
+//! The underlying RustCrypto crates pick hardware instructions (AES-NI and
+//! CLMUL on x86/x86_64, the ARMv8 crypto extensions on aarch64 when built with
+//! `--cfg aes_armv8`) at runtime and silently fall back to a much slower
+//! constant-time software implementation otherwise. [`backend_info`] reports
+//! which one will be used, and [`BackendRequirement`] lets callers refuse to
+//! run on the slow path.
+
+use crate::ErrorClass;
+use std::fmt;
+use std::io;
+
+/// The implementations selected for the current CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackendInfo {
+    /// AES rounds run on dedicated CPU instructions
+    pub hardware_aes: bool,
+    /// POLYVAL runs on carry-less multiplication instructions
+    pub hardware_polyval: bool,
+}
+
+impl BackendInfo {
+    /// Whether both AES and POLYVAL are hardware accelerated
+    pub fn is_hardware_accelerated(&self) -> bool {
+        self.hardware_aes && self.hardware_polyval
+    }
+}
+
+impl fmt::Display for BackendInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = |hardware| if hardware { "hardware" } else { "software" };
+        write!(
+            f,
+            "AES: {}, POLYVAL: {}",
+            kind(self.hardware_aes),
+            kind(self.hardware_polyval)
+        )
+    }
+}
+
+/// Detect the AES and POLYVAL implementations for the current CPU
+pub fn backend_info() -> BackendInfo {
+    #[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(aes_force_soft)))]
+    let hardware_aes = std::arch::is_x86_feature_detected!("aes");
+    #[cfg(all(target_arch = "aarch64", aes_armv8, not(aes_force_soft)))]
+    let hardware_aes = std::arch::is_aarch64_feature_detected!("aes");
+    #[cfg(not(any(
+        all(any(target_arch = "x86", target_arch = "x86_64"), not(aes_force_soft)),
+        all(target_arch = "aarch64", aes_armv8, not(aes_force_soft))
+    )))]
+    let hardware_aes = false;
+
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        not(polyval_force_soft)
+    ))]
+    let hardware_polyval = std::arch::is_x86_feature_detected!("pclmulqdq");
+    #[cfg(all(target_arch = "aarch64", polyval_armv8, not(polyval_force_soft)))]
+    let hardware_polyval = std::arch::is_aarch64_feature_detected!("aes");
+    #[cfg(not(any(
+        all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            not(polyval_force_soft)
+        ),
+        all(target_arch = "aarch64", polyval_armv8, not(polyval_force_soft))
+    )))]
+    let hardware_polyval = false;
+
+    BackendInfo {
+        hardware_aes,
+        hardware_polyval,
+    }
+}
+
+/// Errors returned by [`BackendRequirement::check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BackendError {
+    /// Hardware acceleration was required, but the backend is (at least
+    /// partly) the software implementation
+    HardwareAesUnavailable(BackendInfo),
+}
+
+impl BackendError {
+    /// The broad category of this error
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            BackendError::HardwareAesUnavailable(_) => ErrorClass::Resource,
+        }
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::HardwareAesUnavailable(info) => {
+                write!(f, "Hardware AES acceleration is not available ({})", info)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+impl From<BackendError> for io::Error {
+    fn from(err: BackendError) -> Self {
+        io::Error::new(io::ErrorKind::Unsupported, err)
+    }
+}
+
+/// What a caller requires of the backend before starting work
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendRequirement {
+    /// Any implementation is acceptable
+    #[default]
+    Any,
+    /// Both AES and POLYVAL must be hardware accelerated
+    HardwareAes,
+}
+
+impl BackendRequirement {
+    /// Check `info` against this requirement
+    ///
+    /// # Errors
+    /// Returns `BackendError::HardwareAesUnavailable` if hardware
+    /// acceleration is required but `info` reports a software implementation
+    pub fn check(self, info: &BackendInfo) -> Result<(), BackendError> {
+        match self {
+            BackendRequirement::HardwareAes if !info.is_hardware_accelerated() => {
+                Err(BackendError::HardwareAesUnavailable(*info))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,524 @@
+//! Fixed-size encrypted block storage with random-access rewrites
+//!
+//! A [`BlockCipherFile`] divides a file into [`BLOCK_SIZE`]-byte blocks that
+//! are encrypted independently, so any block can be read or rewritten without
+//! touching the others. Each block is encrypted under the nonce
+//!
+//! ```text
+//! file prefix (4 random bytes) || block index (u32 BE) || generation (u32 BE)
+//! ```
+//!
+//! where the generation counts the writes to that block. The generations are
+//! kept in an authenticated superblock, which is committed to disk *before*
+//! the block itself, so a generation is never reused even if a write is
+//! interrupted. A block whose ciphertext is one generation behind the
+//! superblock is reported as a torn write ([`BlockFileError::TornWrite`]);
+//! one that fails authentication at any generation as corrupt.
+//!
+//! # Layout
+//! A fixed header (magic, block size, block count, file prefix) written once
+//! when the file is created, then two superblock copies, written alternately
+//! so that an interrupted superblock update leaves the previous copy intact,
+//! then the blocks (`BLOCK_SIZE + TAG_LENGTH` bytes each). Each superblock
+//! copy holds its own generation and the block generation table, and is
+//! authenticated together with the fixed header.
+//!
+//! # Security Notes
+//! - The 4-byte file prefix is random, so files sharing a key should stay
+//!   well below 2^16 to keep prefix collisions (and therefore possible nonce
+//!   reuse across files) unlikely; use a key per file where possible
+//! - Block contents are confidential and authenticated; the block count and
+//!   how often each block was written are not confidential
+//! - Blocks that were never written read back as zeros
+
+#![deny(clippy::arithmetic_side_effects)]
+
+use crate::{Cipher, CryptoError, ErrorClass, NONCE_LENGTH, TAG_LENGTH};
+use rand::TryRngCore;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Size of a block's plaintext in bytes
+pub const BLOCK_SIZE: usize = 4096;
+
+/// Identifies a block file and its format version
+const MAGIC: &[u8; 8] = b"AGSVBLK1";
+
+/// AAD of every block ciphertext
+const BLOCK_AAD: &[u8] = b"aes-gcm-siv-impl/block-file/v1/block";
+
+/// Block index used in superblock nonces; never a valid block index
+const SUPERBLOCK_INDEX: u32 = u32::MAX;
+
+/// Length of the file prefix in bytes
+const PREFIX_LENGTH: usize = 4;
+
+/// Fixed header: magic, block size, block count, prefix
+const HEADER_LENGTH: usize = MAGIC.len() + 4 + 4 + PREFIX_LENGTH;
+
+/// Length of a superblock copy without its generation table: generation, tag
+const SUPERBLOCK_FIXED_LENGTH: u64 = 4 + TAG_LENGTH as u64;
+
+/// Length of an encrypted block on disk
+const SLOT_LENGTH: usize = BLOCK_SIZE + TAG_LENGTH;
+
+/// Errors returned by [`BlockCipherFile`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BlockFileError {
+    /// Reading or writing the underlying file failed
+    Io(io::Error),
+    /// The key was rejected (see [`CryptoError`])
+    Crypto(CryptoError),
+    /// The file is not a block file of this format
+    NotABlockFile,
+    /// Neither superblock copy authenticates: wrong key or corrupt file
+    CorruptSuperblock,
+    /// The block index is not below the block count
+    BlockOutOfRange {
+        /// Requested index
+        index: u32,
+        /// Number of blocks in the file
+        block_count: u32,
+    },
+    /// Data passed to [`BlockCipherFile::write_block`] is not `BLOCK_SIZE` bytes
+    InvalidBlockLength(usize),
+    /// The block holds the data of the previous write: the last write to it
+    /// was interrupted after the superblock was updated
+    TornWrite(u32),
+    /// The block fails authentication
+    CorruptBlock(u32),
+    /// The block (or the superblock) has been written `u32::MAX` times
+    GenerationExhausted,
+    /// The block count implies sizes this platform cannot address
+    LengthOverflow,
+}
+
+impl BlockFileError {
+    /// The broad category of this error
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            BlockFileError::Io(e) => crate::classify_io_error(e),
+            BlockFileError::Crypto(e) => e.classify(),
+            BlockFileError::CorruptSuperblock
+            | BlockFileError::TornWrite(_)
+            | BlockFileError::CorruptBlock(_) => ErrorClass::AuthFailure,
+            BlockFileError::NotABlockFile
+            | BlockFileError::BlockOutOfRange { .. }
+            | BlockFileError::InvalidBlockLength(_) => ErrorClass::InvalidInput,
+            BlockFileError::GenerationExhausted | BlockFileError::LengthOverflow => {
+                ErrorClass::Resource
+            }
+        }
+    }
+}
+
+impl fmt::Display for BlockFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockFileError::Io(e) => write!(f, "{}", e),
+            BlockFileError::Crypto(e) => write!(f, "{}", e),
+            BlockFileError::NotABlockFile => write!(f, "Not a block file"),
+            BlockFileError::CorruptSuperblock => {
+                write!(
+                    f,
+                    "Superblock failed authentication (wrong key or corrupt file)"
+                )
+            }
+            BlockFileError::BlockOutOfRange { index, block_count } => write!(
+                f,
+                "Block {} is out of range (the file has {} blocks)",
+                index, block_count
+            ),
+            BlockFileError::InvalidBlockLength(len) => write!(
+                f,
+                "Block data must be exactly {} bytes, got {}",
+                BLOCK_SIZE, len
+            ),
+            BlockFileError::TornWrite(index) => {
+                write!(f, "Block {}: the last write to it was interrupted", index)
+            }
+            BlockFileError::CorruptBlock(index) => {
+                write!(f, "Block {} failed authentication", index)
+            }
+            BlockFileError::GenerationExhausted => write!(f, "Write generation exhausted"),
+            BlockFileError::LengthOverflow => {
+                write!(f, "Block count too large for this platform")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BlockFileError::Io(e) => Some(e),
+            BlockFileError::Crypto(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BlockFileError {
+    fn from(err: io::Error) -> Self {
+        BlockFileError::Io(err)
+    }
+}
+
+impl From<CryptoError> for BlockFileError {
+    fn from(err: CryptoError) -> Self {
+        BlockFileError::Crypto(err)
+    }
+}
+
+/// Result type for block file operations
+pub type BlockFileResult<T> = Result<T, BlockFileError>;
+
+/// Sizes and offsets of a block file, derived from its block count
+///
+/// All arithmetic is checked and done in `u64`, converting to `usize` only
+/// for the in-memory superblock, so a hostile header cannot overflow an
+/// offset on any target.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    block_count: u32,
+    /// Length of one superblock copy: generation, table, tag
+    superblock_length: usize,
+    /// Offset of the first superblock copy
+    superblocks_offset: u64,
+    /// Offset of block 0
+    blocks_offset: u64,
+    /// Length of the whole file
+    file_length: u64,
+}
+
+impl Layout {
+    fn new(block_count: u32) -> BlockFileResult<Self> {
+        let superblock_length = u64::from(block_count)
+            .checked_mul(4)
+            .and_then(|table| table.checked_add(SUPERBLOCK_FIXED_LENGTH))
+            .ok_or(BlockFileError::LengthOverflow)?;
+        let superblocks_offset = HEADER_LENGTH as u64;
+        let blocks_offset = superblock_length
+            .checked_mul(2)
+            .and_then(|copies| copies.checked_add(superblocks_offset))
+            .ok_or(BlockFileError::LengthOverflow)?;
+        let file_length = u64::from(block_count)
+            .checked_mul(SLOT_LENGTH as u64)
+            .and_then(|blocks| blocks.checked_add(blocks_offset))
+            .ok_or(BlockFileError::LengthOverflow)?;
+        Ok(Layout {
+            block_count,
+            superblock_length: usize::try_from(superblock_length)
+                .map_err(|_| BlockFileError::LengthOverflow)?,
+            superblocks_offset,
+            blocks_offset,
+            file_length,
+        })
+    }
+
+    fn superblock_offset(&self, copy: u64) -> BlockFileResult<u64> {
+        (self.superblock_length as u64)
+            .checked_mul(copy)
+            .and_then(|offset| offset.checked_add(self.superblocks_offset))
+            .ok_or(BlockFileError::LengthOverflow)
+    }
+
+    fn block_offset(&self, index: u32) -> BlockFileResult<u64> {
+        u64::from(index)
+            .checked_mul(SLOT_LENGTH as u64)
+            .and_then(|offset| offset.checked_add(self.blocks_offset))
+            .ok_or(BlockFileError::LengthOverflow)
+    }
+}
+
+/// A file of independently encrypted, rewritable fixed-size blocks
+pub struct BlockCipherFile {
+    file: File,
+    cipher: Cipher,
+    prefix: [u8; PREFIX_LENGTH],
+    layout: Layout,
+    /// Writes to each block so far; 0 means never written
+    generations: Vec<u32>,
+    /// Writes to the superblock so far; selects the copy written next
+    superblock_generation: u32,
+}
+
+impl BlockCipherFile {
+    /// Create (or truncate) a block file with `block_count` zeroed blocks
+    ///
+    /// # Arguments
+    /// * `path` - Where to create the file
+    /// * `key` - The encryption key (must be 16 or 32 bytes)
+    /// * `block_count` - Number of blocks; fixed for the life of the file
+    ///
+    /// # Errors
+    /// Returns `BlockFileError::Crypto` if the key length is invalid,
+    /// `BlockFileError::BlockOutOfRange` if `block_count` is `u32::MAX`,
+    /// `BlockFileError::LengthOverflow` if it is too large for this platform,
+    /// or `BlockFileError::Io` if the file cannot be written
+    pub fn create<P: AsRef<Path>>(path: P, key: &[u8], block_count: u32) -> BlockFileResult<Self> {
+        let cipher = Cipher::new(key)?;
+        if block_count == SUPERBLOCK_INDEX {
+            return Err(BlockFileError::BlockOutOfRange {
+                index: block_count,
+                block_count: SUPERBLOCK_INDEX,
+            });
+        }
+        let layout = Layout::new(block_count)?;
+
+        let mut prefix = [0u8; PREFIX_LENGTH];
+        rand::rngs::OsRng.try_fill_bytes(&mut prefix).unwrap(); // CSPRNG
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut block_file = BlockCipherFile {
+            file,
+            cipher,
+            prefix,
+            layout,
+            generations: vec![0; block_count as usize],
+            superblock_generation: 0,
+        };
+        block_file.file.set_len(layout.file_length)?;
+        block_file.file.write_all(&block_file.header())?;
+        block_file.commit_superblock()?;
+        Ok(block_file)
+    }
+
+    /// Open an existing block file
+    ///
+    /// # Errors
+    /// Returns `BlockFileError::NotABlockFile` if the file has the wrong
+    /// format or is shorter than its header declares,
+    /// `BlockFileError::CorruptSuperblock` if neither superblock copy
+    /// authenticates under `key`, `BlockFileError::LengthOverflow` if the
+    /// declared block count is too large for this platform, or
+    /// `BlockFileError::Io` on read errors
+    pub fn open<P: AsRef<Path>>(path: P, key: &[u8]) -> BlockFileResult<Self> {
+        let cipher = Cipher::new(key)?;
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut header = [0u8; HEADER_LENGTH];
+        file.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => BlockFileError::NotABlockFile,
+            _ => BlockFileError::Io(e),
+        })?;
+        if &header[..MAGIC.len()] != MAGIC || read_u32(&header[8..]) != BLOCK_SIZE as u32 {
+            return Err(BlockFileError::NotABlockFile);
+        }
+        let block_count = read_u32(&header[12..]);
+        let mut prefix = [0u8; PREFIX_LENGTH];
+        prefix.copy_from_slice(&header[16..]);
+
+        // Check the declared size against the file before allocating for it
+        let layout = Layout::new(block_count)?;
+        if block_count == SUPERBLOCK_INDEX || file.metadata()?.len() < layout.file_length {
+            return Err(BlockFileError::NotABlockFile);
+        }
+
+        let mut block_file = BlockCipherFile {
+            file,
+            cipher,
+            prefix,
+            layout,
+            generations: Vec::new(),
+            superblock_generation: 0,
+        };
+
+        // Use the newest copy that authenticates
+        let newest = [0, 1]
+            .into_iter()
+            .filter_map(|copy| block_file.read_superblock(copy).transpose())
+            .collect::<BlockFileResult<Vec<_>>>()?
+            .into_iter()
+            .max_by_key(|(generation, _)| *generation);
+        let Some((superblock_generation, generations)) = newest else {
+            return Err(BlockFileError::CorruptSuperblock);
+        };
+        block_file.superblock_generation = superblock_generation;
+        block_file.generations = generations;
+        Ok(block_file)
+    }
+
+    /// Number of blocks in the file
+    pub fn block_count(&self) -> u32 {
+        self.layout.block_count
+    }
+
+    /// Number of times block `index` has been written (0 if never)
+    pub fn generation(&self, index: u32) -> Option<u32> {
+        self.generations.get(index as usize).copied()
+    }
+
+    /// Read and authenticate block `index`
+    ///
+    /// # Returns
+    /// The block's `BLOCK_SIZE` bytes of plaintext; zeros if it was never written
+    ///
+    /// # Errors
+    /// Returns `BlockFileError::TornWrite` if the block still holds the data
+    /// of the write before the last one, `BlockFileError::CorruptBlock` if it
+    /// fails authentication, or `BlockFileError::BlockOutOfRange`
+    pub fn read_block(&mut self, index: u32) -> BlockFileResult<Vec<u8>> {
+        let generation = self.check_index(index)?;
+        if generation == 0 {
+            return Ok(vec![0; BLOCK_SIZE]);
+        }
+
+        let mut slot = vec![0u8; SLOT_LENGTH];
+        self.file
+            .seek(SeekFrom::Start(self.layout.block_offset(index)?))?;
+        self.file.read_exact(&mut slot)?;
+
+        if let Ok(plaintext) = self
+            .cipher
+            .decrypt(&self.nonce(index, generation), &slot, BLOCK_AAD)
+        {
+            return Ok(plaintext);
+        }
+        // An interrupted first write leaves the never-written (zero) slot
+        let previous_write_survived = match generation.checked_sub(1) {
+            Some(0) | None => slot.iter().all(|&b| b == 0),
+            Some(previous) => self
+                .cipher
+                .decrypt(&self.nonce(index, previous), &slot, BLOCK_AAD)
+                .is_ok(),
+        };
+        if previous_write_survived {
+            Err(BlockFileError::TornWrite(index))
+        } else {
+            Err(BlockFileError::CorruptBlock(index))
+        }
+    }
+
+    /// Encrypt and write block `index`
+    ///
+    /// The new generation is committed to the superblock and synced before
+    /// the block is written, so an interrupted write is detected on the next
+    /// read of that block and never causes a nonce to be reused.
+    ///
+    /// # Errors
+    /// Returns `BlockFileError::InvalidBlockLength` unless `data` is exactly
+    /// `BLOCK_SIZE` bytes, `BlockFileError::BlockOutOfRange`,
+    /// `BlockFileError::GenerationExhausted`, or `BlockFileError::Io`
+    pub fn write_block(&mut self, index: u32, data: &[u8]) -> BlockFileResult<()> {
+        let previous = self.check_index(index)?;
+        if data.len() != BLOCK_SIZE {
+            return Err(BlockFileError::InvalidBlockLength(data.len()));
+        }
+        let generation = previous
+            .checked_add(1)
+            .ok_or(BlockFileError::GenerationExhausted)?;
+
+        self.generations[index as usize] = generation;
+        if let Err(e) = self.commit_superblock() {
+            self.generations[index as usize] = previous;
+            return Err(e);
+        }
+
+        let ciphertext = self
+            .cipher
+            .encrypt(&self.nonce(index, generation), data, BLOCK_AAD)?;
+        self.file
+            .seek(SeekFrom::Start(self.layout.block_offset(index)?))?;
+        self.file.write_all(&ciphertext)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    fn check_index(&self, index: u32) -> BlockFileResult<u32> {
+        self.generation(index)
+            .ok_or(BlockFileError::BlockOutOfRange {
+                index,
+                block_count: self.block_count(),
+            })
+    }
+
+    fn nonce(&self, index: u32, generation: u32) -> [u8; NONCE_LENGTH] {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        nonce[..PREFIX_LENGTH].copy_from_slice(&self.prefix);
+        nonce[PREFIX_LENGTH..PREFIX_LENGTH + 4].copy_from_slice(&index.to_be_bytes());
+        nonce[PREFIX_LENGTH + 4..].copy_from_slice(&generation.to_be_bytes());
+        nonce
+    }
+
+    /// Write the next superblock copy and sync it.
+    fn commit_superblock(&mut self) -> BlockFileResult<()> {
+        let superblock_generation = self
+            .superblock_generation
+            .checked_add(1)
+            .ok_or(BlockFileError::GenerationExhausted)?;
+
+        let mut superblock = Vec::with_capacity(self.layout.superblock_length);
+        superblock.extend_from_slice(&superblock_generation.to_le_bytes());
+        for generation in &self.generations {
+            superblock.extend_from_slice(&generation.to_le_bytes());
+        }
+        let tag = self.superblock_tag(superblock_generation, &superblock)?;
+        superblock.extend_from_slice(&tag);
+
+        let copy = u64::from(superblock_generation & 1);
+        self.file
+            .seek(SeekFrom::Start(self.layout.superblock_offset(copy)?))?;
+        self.file.write_all(&superblock)?;
+        self.file.sync_data()?;
+        self.superblock_generation = superblock_generation;
+        Ok(())
+    }
+
+    /// Read superblock copy `copy`, or `None` if it does not authenticate.
+    fn read_superblock(&mut self, copy: u64) -> BlockFileResult<Option<(u32, Vec<u32>)>> {
+        let mut superblock = vec![0u8; self.layout.superblock_length];
+        self.file
+            .seek(SeekFrom::Start(self.layout.superblock_offset(copy)?))?;
+        self.file.read_exact(&mut superblock)?;
+
+        let Some((body, tag)) = superblock.split_last_chunk::<TAG_LENGTH>() else {
+            return Ok(None);
+        };
+        let superblock_generation = read_u32(body);
+        let expected = self.superblock_tag(superblock_generation, body)?;
+        if !crate::confirm::constant_time_eq(&expected, tag) {
+            return Ok(None);
+        }
+
+        let generations = body[4..].chunks_exact(4).map(read_u32).collect();
+        Ok(Some((superblock_generation, generations)))
+    }
+
+    fn header(&self) -> [u8; HEADER_LENGTH] {
+        let mut header = [0u8; HEADER_LENGTH];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&(BLOCK_SIZE as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&self.block_count().to_le_bytes());
+        header[16..].copy_from_slice(&self.prefix);
+        header
+    }
+
+    /// Authentication tag over the fixed header and one superblock copy
+    fn superblock_tag(&self, superblock_generation: u32, body: &[u8]) -> BlockFileResult<Vec<u8>> {
+        let mut aad = self.header().to_vec();
+        aad.extend_from_slice(body);
+        let nonce = self.nonce(SUPERBLOCK_INDEX, superblock_generation);
+        Ok(self.cipher.encrypt(&nonce, b"", &aad)?)
+    }
+}
+
+/// Whether `data` starts like a block file (its magic)
+pub fn is_block_file(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Read a little-endian `u32` from the start of `bytes`
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut word = [0u8; 4];
+    word.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(word)
+}
@@ -0,0 +1,201 @@
+//! Opt-in bounded LRU cache of decrypted plaintexts
+//!
+//! [`DecryptCache`] remembers the plaintext of recently decrypted records so
+//! that read-heavy services decrypting the same hot records over and over
+//! skip the AEAD work on a hit.
+//!
+//! # Security Notes
+//! - Cached plaintext lives in memory until it is evicted, [`DecryptCache::clear`]
+//!   is called or the cache is dropped; it is zeroized at that point.
+//! - Entries are keyed by SHA-256 over the key fingerprint, nonce, AAD and
+//!   ciphertext, so a hit can never cross keys.
+//! - Failed decryptions are never cached.
+
+use crate::{Cipher, CryptoResult, KeyFingerprint};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use zeroize::Zeroizing;
+
+/// Digest identifying one `(key, nonce, ciphertext, aad)` combination
+type CacheKey = [u8; 32];
+
+struct Entry {
+    plaintext: Zeroizing<Vec<u8>>,
+    last_used: u64,
+}
+
+/// Hit/miss counters of a [`DecryptCache`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that had to decrypt
+    pub misses: u64,
+    /// Entries dropped to stay within the bounds
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// Fraction of lookups answered from the cache (0.0 when there were none)
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Decryption with a bounded LRU cache of plaintexts
+///
+/// The cache is bounded both by entry count and by the total number of
+/// cached plaintext bytes; the least recently used entries are evicted first.
+/// A plaintext larger than the byte budget is returned but not cached.
+pub struct DecryptCache {
+    cipher: Cipher,
+    fingerprint: KeyFingerprint,
+    max_entries: usize,
+    max_bytes: usize,
+    entries: HashMap<CacheKey, Entry>,
+    /// Last-use tick -> entry, oldest first
+    recency: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    cached_bytes: usize,
+    stats: CacheStats,
+}
+
+impl DecryptCache {
+    /// Create an empty cache decrypting with `cipher`
+    ///
+    /// # Arguments
+    /// * `cipher` - The cipher to decrypt with
+    /// * `fingerprint` - [`key_fingerprint`](crate::key_fingerprint) of the
+    ///   cipher's key, mixed into every cache key
+    /// * `max_entries` - Maximum number of cached plaintexts
+    /// * `max_bytes` - Maximum total size of cached plaintexts in bytes
+    pub fn new(
+        cipher: Cipher,
+        fingerprint: KeyFingerprint,
+        max_entries: usize,
+        max_bytes: usize,
+    ) -> Self {
+        DecryptCache {
+            cipher,
+            fingerprint,
+            max_entries,
+            max_bytes,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            cached_bytes: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Decrypt `ciphertext`, answering from the cache when possible
+    ///
+    /// Behaves exactly like [`Cipher::decrypt`] with this cache's cipher.
+    ///
+    /// # Errors
+    /// Returns `CryptoError::Auth` if authentication fails or
+    /// `CryptoError::InvalidNonceSize` if nonce is invalid
+    pub fn decrypt(
+        &mut self,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> CryptoResult<Vec<u8>> {
+        let cache_key = self.cache_key(nonce, ciphertext, aad);
+        self.tick += 1;
+
+        if let Some(entry) = self.entries.get_mut(&cache_key) {
+            self.recency.remove(&entry.last_used);
+            self.recency.insert(self.tick, cache_key);
+            entry.last_used = self.tick;
+            self.stats.hits += 1;
+            return Ok(entry.plaintext.to_vec());
+        }
+
+        self.stats.misses += 1;
+        let plaintext = self.cipher.decrypt(nonce, ciphertext, aad)?;
+        self.insert(cache_key, &plaintext);
+        Ok(plaintext)
+    }
+
+    /// Drop (and zeroize) every cached plaintext
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.cached_bytes = 0;
+    }
+
+    /// Number of cached plaintexts
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache holds no plaintexts
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Total size of the cached plaintexts in bytes
+    pub fn cached_bytes(&self) -> usize {
+        self.cached_bytes
+    }
+
+    /// Hit/miss counters since the cache was created
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Fingerprint of the key this cache decrypts with
+    pub fn key_fingerprint(&self) -> KeyFingerprint {
+        self.fingerprint
+    }
+
+    fn cache_key(&self, nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> CacheKey {
+        // Length-prefix the variable fields so distinct inputs never collide
+        Sha256::new()
+            .chain_update(self.fingerprint.as_bytes())
+            .chain_update((nonce.len() as u64).to_le_bytes())
+            .chain_update(nonce)
+            .chain_update((aad.len() as u64).to_le_bytes())
+            .chain_update(aad)
+            .chain_update(ciphertext)
+            .finalize()
+            .into()
+    }
+
+    fn insert(&mut self, cache_key: CacheKey, plaintext: &[u8]) {
+        if self.max_entries == 0 || plaintext.len() > self.max_bytes {
+            return;
+        }
+        // `cached_bytes <= max_bytes` always holds, so this cannot underflow
+        while self.entries.len() >= self.max_entries
+            || plaintext.len() > self.max_bytes - self.cached_bytes
+        {
+            self.evict_oldest();
+        }
+
+        self.cached_bytes += plaintext.len();
+        self.recency.insert(self.tick, cache_key);
+        self.entries.insert(
+            cache_key,
+            Entry {
+                plaintext: Zeroizing::new(plaintext.to_vec()),
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some((_, cache_key)) = self.recency.pop_first() {
+            if let Some(entry) = self.entries.remove(&cache_key) {
+                self.cached_bytes -= entry.plaintext.len();
+                self.stats.evictions += 1;
+            }
+        }
+    }
+}
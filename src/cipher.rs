@@ -0,0 +1,223 @@
+//! A reusable AES-GCM-SIV cipher bound to one key
+//!
+//! [`encrypt`](crate::encrypt) and [`decrypt`](crate::decrypt) validate the
+//! key and expand its AES key schedule on every call. A [`Cipher`] does that
+//! once, so callers encrypting or decrypting many messages under the same key
+//! pay only for the per-message work. The `*_in_place` methods additionally
+//! work in a caller-owned buffer, so a loop reusing one buffer does not
+//! allocate per message either.
+//!
+//! # Security Notes
+//! - The expanded key schedule is zeroized when the `Cipher` is dropped
+//! - AES-GCM-SIV still derives fresh per-nonce subkeys for every message;
+//!   only the key-generating key is cached
+
+use crate::{CryptoError, CryptoResult, KeySize, NONCE_LENGTH};
+use aes_gcm_siv::{
+    aead::{Aead, AeadInPlace, KeyInit, Payload},
+    Aes128GcmSiv, Aes256GcmSiv, Nonce,
+};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Boxed, so a `Cipher` is cheap to move despite the size of the key schedule
+#[derive(Clone)]
+enum Inner {
+    Aes128(Box<Aes128GcmSiv>),
+    Aes256(Box<Aes256GcmSiv>),
+}
+
+/// AES-GCM-SIV with a cached key schedule
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use aes_gcm_siv_impl::{generate_nonce, Cipher};
+///
+/// let cipher = Cipher::new(&[0x42; 32])?;
+/// let mut buf = Vec::new();
+/// for message in [&b"first"[..], b"second"] {
+///     let nonce = generate_nonce();
+///     buf.clear();
+///     buf.extend_from_slice(message);
+///     cipher.encrypt_in_place(&nonce, b"", &mut buf)?;
+///     cipher.decrypt_in_place(&nonce, b"", &mut buf)?;
+///     assert_eq!(buf, message);
+/// }
+/// # }
+/// # Ok::<(), aes_gcm_siv_impl::CryptoError>(())
+/// ```
+#[derive(Clone)]
+pub struct Cipher {
+    inner: Inner,
+}
+
+// The `zeroize` feature of `aes` (enabled in Cargo.toml) wipes the key
+// schedule when the AES state inside `Aes128GcmSiv`/`Aes256GcmSiv` is
+// dropped; fail the build if that ever stops being the case.
+const _: () = {
+    const fn assert_zeroize_on_drop<T: ZeroizeOnDrop>() {}
+    assert_zeroize_on_drop::<aes::Aes128>();
+    assert_zeroize_on_drop::<aes::Aes256>();
+};
+
+impl ZeroizeOnDrop for Cipher {}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cipher")
+            .field("key_size", &self.key_size())
+            .finish_non_exhaustive()
+    }
+}
+
+fn nonce_array(nonce: &[u8]) -> CryptoResult<&Nonce> {
+    if nonce.len() != NONCE_LENGTH {
+        return Err(CryptoError::InvalidNonceSize);
+    }
+    Ok(Nonce::from_slice(nonce))
+}
+
+impl Cipher {
+    /// Expand `key` into a cipher
+    ///
+    /// # Errors
+    /// Returns `CryptoError::InvalidKeySize` unless the key is 16 or 32 bytes
+    pub fn new(key: &[u8]) -> CryptoResult<Self> {
+        let inner = match KeySize::from_key_len(key.len())? {
+            KeySize::Aes128 => Inner::Aes128(Box::new(
+                Aes128GcmSiv::new_from_slice(key).map_err(|_| CryptoError::InvalidKeySize)?,
+            )),
+            KeySize::Aes256 => Inner::Aes256(Box::new(
+                Aes256GcmSiv::new_from_slice(key).map_err(|_| CryptoError::InvalidKeySize)?,
+            )),
+        };
+        Ok(Cipher { inner })
+    }
+
+    /// Size of the key this cipher was created from
+    pub fn key_size(&self) -> KeySize {
+        match self.inner {
+            Inner::Aes128(_) => KeySize::Aes128,
+            Inner::Aes256(_) => KeySize::Aes256,
+        }
+    }
+
+    /// Encrypt `plaintext`; same as [`encrypt`](crate::encrypt) with this
+    /// cipher's key
+    ///
+    /// # Errors
+    /// Returns `CryptoError::InvalidNonceSize` if nonce length is invalid
+    pub fn encrypt(&self, nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> CryptoResult<Vec<u8>> {
+        let nonce = nonce_array(nonce)?;
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        match &self.inner {
+            Inner::Aes128(cipher) => cipher.encrypt(nonce, payload),
+            Inner::Aes256(cipher) => cipher.encrypt(nonce, payload),
+        }
+        .map_err(|_| CryptoError::Auth)
+    }
+
+    /// Decrypt `ciphertext`; same as [`decrypt`](crate::decrypt) with this
+    /// cipher's key
+    ///
+    /// # Errors
+    /// Returns `CryptoError::Auth` if authentication fails or
+    /// `CryptoError::InvalidNonceSize` if nonce length is invalid
+    pub fn decrypt(&self, nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> CryptoResult<Vec<u8>> {
+        let nonce = nonce_array(nonce)?;
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        match &self.inner {
+            Inner::Aes128(cipher) => cipher.decrypt(nonce, payload),
+            Inner::Aes256(cipher) => cipher.decrypt(nonce, payload),
+        }
+        .map_err(|_| CryptoError::Auth)
+    }
+
+    /// Encrypt the plaintext in `buf` in place and append the tag
+    ///
+    /// `buf` grows by [`TAG_LENGTH`](crate::TAG_LENGTH) bytes, reallocating
+    /// only if its capacity is too small.
+    ///
+    /// # Errors
+    /// Returns `CryptoError::InvalidNonceSize` if nonce length is invalid;
+    /// `buf` is then left unchanged
+    pub fn encrypt_in_place(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut Vec<u8>,
+    ) -> CryptoResult<()> {
+        let nonce = nonce_array(nonce)?;
+        match &self.inner {
+            Inner::Aes128(cipher) => cipher.encrypt_in_place(nonce, aad, buf),
+            Inner::Aes256(cipher) => cipher.encrypt_in_place(nonce, aad, buf),
+        }
+        .map_err(|_| CryptoError::Auth)
+    }
+
+    /// Decrypt the ciphertext and tag in `buf` in place, leaving the plaintext
+    ///
+    /// # Security Notes
+    /// - On any error the whole buffer (including spare capacity) is
+    ///   zeroized and left empty, so no unauthenticated plaintext survives
+    ///
+    /// # Errors
+    /// Returns `CryptoError::Auth` if authentication fails or
+    /// `CryptoError::InvalidNonceSize` if nonce length is invalid
+    pub fn decrypt_in_place(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut Vec<u8>,
+    ) -> CryptoResult<()> {
+        let result = nonce_array(nonce).and_then(|nonce| {
+            match &self.inner {
+                Inner::Aes128(cipher) => cipher.decrypt_in_place(nonce, aad, buf),
+                Inner::Aes256(cipher) => cipher.decrypt_in_place(nonce, aad, buf),
+            }
+            .map_err(|_| CryptoError::Auth)
+        });
+        if result.is_err() {
+            buf.zeroize();
+        }
+        result
+    }
+
+    /// Decrypt `ciphertext` into `buf`; same as
+    /// [`decrypt_into_buf`](crate::decrypt_into_buf) with this cipher's key
+    ///
+    /// `buf` is cleared and refilled with the plaintext, growing only when
+    /// its capacity is too small.
+    ///
+    /// # Returns
+    /// The plaintext length, which is also `buf.len()` on success
+    ///
+    /// # Security Notes
+    /// - On any error the whole buffer (including spare capacity) is
+    ///   zeroized and left empty
+    ///
+    /// # Errors
+    /// Returns `CryptoError::Auth` if authentication fails or
+    /// `CryptoError::InvalidNonceSize` if nonce length is invalid
+    pub fn decrypt_into_buf(
+        &self,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+        buf: &mut Vec<u8>,
+    ) -> CryptoResult<usize> {
+        buf.clear();
+        buf.extend_from_slice(ciphertext);
+        self.decrypt_in_place(nonce, aad, buf)?;
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,122 @@
+//! Helpers for `gui-decrypt`, the subcommand a file manager launches when an
+//! encrypted file is double-clicked
+//!
+//! The output is written next to the input under a name that never
+//! overwrites an existing file, missing options are prompted for on the
+//! terminal, and the final status line stays on screen when the console
+//! window would otherwise close straight away.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// File extension of encrypted files, stripped from the output name
+pub const ENCRYPTED_EXTENSION: &str = "agsiv";
+
+/// Suffix appended to the output name when the input lacks the extension
+const DECRYPTED_SUFFIX: &str = "decrypted";
+
+/// Give up after this many existing `name (N).ext` files
+const MAX_ATTEMPTS: u32 = 1000;
+
+/// The output name for `input`: without the encrypted extension if it has
+/// one, otherwise with `.decrypted` appended. `attempt` > 0 inserts ` (N)`
+/// before the remaining extension.
+pub fn output_candidate(input: &Path, attempt: u32) -> PathBuf {
+    let base = if input
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(ENCRYPTED_EXTENSION))
+    {
+        input.with_extension("")
+    } else {
+        let mut name = input.as_os_str().to_owned();
+        name.push(".");
+        name.push(DECRYPTED_SUFFIX);
+        PathBuf::from(name)
+    };
+    if attempt == 0 {
+        return base;
+    }
+
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match base.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, attempt, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, attempt),
+    };
+    base.with_file_name(name)
+}
+
+/// Create the first output file for `input` that does not exist yet.
+///
+/// Uses `create_new`, so a file appearing concurrently is never overwritten.
+pub fn create_output(input: &Path) -> io::Result<(File, PathBuf)> {
+    for attempt in 0..MAX_ATTEMPTS {
+        let path = output_candidate(input, attempt);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("{}: {}", path.display(), e),
+                ))
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!(
+            "{} and {} numbered copies already exist",
+            output_candidate(input, 0).display(),
+            MAX_ATTEMPTS - 1
+        ),
+    ))
+}
+
+/// Whether the user can be prompted
+pub fn can_prompt() -> bool {
+    io::stdin().is_terminal()
+}
+
+/// Prompt for a line of input, echoed
+pub fn prompt_line(prompt: &str) -> io::Result<String> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// Prompt for the key without echoing it: either the hex key itself or the
+/// path of a file holding it
+pub fn prompt_key() -> io::Result<Vec<u8>> {
+    let answer = rpassword::prompt_password("Key (hex) or path to a key file: ")?;
+    let answer = answer.trim();
+    let hex_key = if Path::new(answer).is_file() {
+        std::fs::read_to_string(answer)?.trim().to_string()
+    } else {
+        answer.to_string()
+    };
+    hex::decode(&hex_key).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid key hex: {}", e),
+        )
+    })
+}
+
+/// Whether the process seems to own a console window that closes on exit,
+/// as when Explorer starts it for a file association.
+///
+/// Heuristic: interactive shells (cmd.exe) set `PROMPT` for their children;
+/// a console created just for this process has no parent shell to set it.
+pub fn launched_from_file_manager() -> bool {
+    cfg!(windows) && io::stdin().is_terminal() && std::env::var_os("PROMPT").is_none()
+}
+
+/// Keep the console open until the user presses Enter
+pub fn wait_for_enter() {
+    print!("Press Enter to close this window...");
+    let _ = io::stdout().flush();
+    let _ = io::stdin().lock().read_line(&mut String::new());
+}
@@ -0,0 +1,83 @@
+//! Keyring files for `--keyring`
+//!
+//! A keyring file lists hex keys under their key IDs and names the one new
+//! files are encrypted with:
+//!
+//! ```toml
+//! primary = "2026q4"
+//!
+//! [keys]
+//! 2026q3 = "000102030405060708090a0b0c0d0e0f000102030405060708090a0b0c0d0e0f"
+//! 2026q4 = "0f0e0d0c0b0a090807060504030201000f0e0d0c0b0a09080706050403020100"
+//! ```
+//!
+//! `primary` may be left out when the file holds a single key. The file
+//! holds secrets, so keep it readable only by its owner.
+
+use aes_gcm_siv_impl::Keyring;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyringFile {
+    primary: Option<String>,
+    #[serde(default)]
+    keys: BTreeMap<String, String>,
+}
+
+fn invalid_data(path: &Path, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+/// Load the keyring file at `path`
+///
+/// # Errors
+/// Fails if the file cannot be read or parsed, a key ID or key is invalid,
+/// the file holds no keys, or `primary` is missing (with several keys) or
+/// names no listed key.
+pub fn load_keyring(path: &Path) -> io::Result<Keyring> {
+    let text = Zeroizing::new(
+        fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+    );
+    let file: KeyringFile = toml::from_str(&text).map_err(|e| invalid_data(path, e))?;
+    let hex_keys: BTreeMap<String, Zeroizing<String>> = file
+        .keys
+        .into_iter()
+        .map(|(id, key)| (id, Zeroizing::new(key)))
+        .collect();
+
+    let mut keyring = Keyring::new();
+    for (id, hex_key) in &hex_keys {
+        let key = Zeroizing::new(
+            hex::decode(hex_key.trim())
+                .map_err(|e| invalid_data(path, format!("key `{}`: invalid hex: {}", id, e)))?,
+        );
+        keyring
+            .add(id, &key)
+            .map_err(|e| invalid_data(path, format!("key `{}`: {}", id, e)))?;
+    }
+
+    match file.primary {
+        Some(primary) => keyring
+            .set_primary(&primary)
+            .map_err(|_| invalid_data(path, format!("primary key `{}` is not listed", primary)))?,
+        None if keyring.len() > 1 => {
+            return Err(invalid_data(
+                path,
+                "several keys are listed; set `primary` to the one to encrypt with",
+            ))
+        }
+        None if keyring.is_empty() => return Err(invalid_data(path, "no keys are listed")),
+        None => {}
+    }
+    Ok(keyring)
+}
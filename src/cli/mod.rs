@@ -0,0 +1,51 @@
+//! Helpers for the command-line interface that are too large for `main.rs`
+
+pub mod gui_decrypt;
+pub mod keyring;
+pub mod nonce_history;
+pub mod pins;
+pub mod profiles;
+pub mod receipt;
+
+use aes_gcm_siv_impl::{classify_io_error, ErrorClass};
+use std::fmt;
+use std::io;
+
+/// An error message with an explicit [`ErrorClass`], for failures the CLI
+/// detects itself rather than receiving from the library
+#[derive(Debug)]
+struct ClassifiedError {
+    class: ErrorClass,
+    message: String,
+}
+
+impl fmt::Display for ClassifiedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ClassifiedError {}
+
+/// An `io::Error` carrying `message` that [`exit_code`] reports as `class`
+pub fn fail(class: ErrorClass, message: String) -> io::Error {
+    io::Error::other(ClassifiedError { class, message })
+}
+
+/// The process exit code for a failed command
+///
+/// This is the only place error classes are mapped to exit codes.
+pub fn exit_code(err: &io::Error) -> u8 {
+    let class = err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<ClassifiedError>())
+        .map(|e| e.class)
+        .unwrap_or_else(|| classify_io_error(err));
+    match class {
+        ErrorClass::Internal => 1,
+        ErrorClass::InvalidInput => 2,
+        ErrorClass::AuthFailure => 3,
+        ErrorClass::Io => 4,
+        ErrorClass::Resource => 5,
+    }
+}
@@ -0,0 +1,118 @@
+//! Local history of explicitly supplied nonces
+//!
+//! Records `(key fingerprint, nonce)` pairs, one per line, so that `encrypt`
+//! can refuse to reuse a nonce the user passed by hand. A lock file next to
+//! the history (`.<name>.lock`) is held from the check until the encryption
+//! has finished, and a pair is recorded only once the encryption succeeded.
+//! The history keeps only the newest entries once it exceeds its size bound.
+//!
+//! The history is never rewritten in place: the new contents go to
+//! `.<name>.partial`, which is synced and then renamed over it, so a crash
+//! leaves either the old or the new history, never a truncated one.
+
+use aes_gcm_siv_impl::KeyFingerprint;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// First line of every history file
+const HEADER: &str = "# aes-gcm-siv-impl nonce history v1: <key fingerprint> <nonce hex>";
+
+/// What [`use_nonce`] found
+#[derive(Debug, PartialEq, Eq)]
+pub enum NonceCheck {
+    /// The pair is not in the history
+    Fresh,
+    /// The pair was already in the history
+    Reused,
+}
+
+/// Check whether `(fingerprint, nonce)` is in the history at `path`, run
+/// `encrypt` with the outcome, and record the pair if it was new and
+/// `encrypt` succeeded, keeping at most `limit` entries.
+///
+/// A failed encryption leaves the history unchanged, so retrying it is not
+/// mistaken for reuse.
+pub fn use_nonce<T>(
+    path: &Path,
+    fingerprint: &KeyFingerprint,
+    nonce: &[u8],
+    limit: usize,
+    encrypt: impl FnOnce(NonceCheck) -> io::Result<T>,
+) -> io::Result<T> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    // Locked separately: the rename below replaces the history file itself
+    let lock = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(sibling(path, ".lock"))?;
+    lock.lock()?;
+
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut entries: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let entry = format!("{} {}", fingerprint, hex::encode(nonce));
+    if entries.contains(&entry.as_str()) {
+        return encrypt(NonceCheck::Reused);
+    }
+    let result = encrypt(NonceCheck::Fresh)?;
+
+    entries.push(&entry);
+    let keep_from = entries.len().saturating_sub(limit);
+
+    let mut updated = String::with_capacity(text.len() + entry.len() + HEADER.len() + 2);
+    updated.push_str(HEADER);
+    updated.push('\n');
+    for line in &entries[keep_from..] {
+        updated.push_str(line);
+        updated.push('\n');
+    }
+
+    replace(path, updated.as_bytes())?;
+    Ok(result)
+}
+
+/// `.<file name><suffix>` in the directory of `path`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Replace the contents of `path` with `contents` atomically
+fn replace(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let partial = sibling(path, ".partial");
+    let result = File::create(&partial).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&partial, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result?;
+
+    // Persist the rename itself (directories cannot be synced this way on
+    // Windows)
+    #[cfg(unix)]
+    {
+        let dir = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Trust-on-first-use pinning of key fingerprints
+//!
+//! A pin file maps a source name (e.g. a partner) to the fingerprint of the
+//! key its files are expected to decrypt under:
+//!
+//! ```toml
+//! [pins]
+//! partnerA = "f2319f5d91bfbf0a"
+//! ```
+//!
+//! The first successful decryption for a name records the fingerprint; later
+//! decryptions must match it. The file is locked while it is read and
+//! updated. It is not tamper-evident: anyone who can write it can change a
+//! pin.
+
+use aes_gcm_siv_impl::{ErrorClass, KeyFingerprint};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PinFile {
+    #[serde(default)]
+    pins: BTreeMap<String, String>,
+}
+
+/// What [`check_pin`] did
+#[derive(Debug, PartialEq, Eq)]
+pub enum PinOutcome {
+    /// No pin existed for the name; the fingerprint was recorded
+    Recorded,
+    /// The fingerprint matched the existing pin
+    Matched,
+    /// The pin differed and was replaced because `--update-pin` was given
+    Updated {
+        /// The fingerprint that was pinned before
+        previous: String,
+    },
+}
+
+fn invalid_data(path: &Path, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+fn read_locked(file: &mut File, path: &Path) -> io::Result<PinFile> {
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    if text.trim().is_empty() {
+        return Ok(PinFile::default());
+    }
+    toml::from_str(&text).map_err(|e| invalid_data(path, e))
+}
+
+fn write_locked(file: &mut File, pins: &PinFile) -> io::Result<()> {
+    let text = toml::to_string(pins).map_err(io::Error::other)?;
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()
+}
+
+/// Check `fingerprint` against the pin recorded for `name`, recording it if
+/// there is none.
+///
+/// # Errors
+/// Fails without changing the file if the pinned fingerprint differs and
+/// `update` is false; the message shows both fingerprints.
+pub fn check_pin(
+    path: &Path,
+    name: &str,
+    fingerprint: &KeyFingerprint,
+    update: bool,
+) -> io::Result<PinOutcome> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    file.lock()?;
+
+    let mut pins = read_locked(&mut file, path)?;
+    let fingerprint = fingerprint.to_string();
+    let outcome = match pins.pins.get(name) {
+        Some(pinned) if *pinned == fingerprint => return Ok(PinOutcome::Matched),
+        Some(pinned) if !update => {
+            return Err(super::fail(
+                ErrorClass::AuthFailure,
+                format!(
+                    "key fingerprint {} does not match the fingerprint {} pinned for `{}` in {}; \
+                     if the key was rotated on purpose, rerun with --update-pin",
+                    fingerprint,
+                    pinned,
+                    name,
+                    path.display()
+                ),
+            ));
+        }
+        Some(pinned) => PinOutcome::Updated {
+            previous: pinned.clone(),
+        },
+        None => PinOutcome::Recorded,
+    };
+
+    pins.pins.insert(name.to_string(), fingerprint);
+    write_locked(&mut file, &pins)?;
+    Ok(outcome)
+}
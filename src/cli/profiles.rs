@@ -0,0 +1,251 @@
+//! Named sets of default options
+//!
+//! `--profile NAME` loads defaults from `profiles.toml` in the configuration
+//! directory (`$XDG_CONFIG_HOME/aes-gcm-siv`, or `~/.config/aes-gcm-siv`):
+//!
+//! ```toml
+//! [profiles.backup]
+//! key-file = "/etc/backup/backup.key"
+//! algorithm = "aes256-gcm-siv"
+//! aad = "nightly-backup"
+//! ```
+//!
+//! Options given on the command line always win over the profile. Profiles
+//! may only *refer* to keys (`key-file`, `key-env`, `keyring-dir`); a profile
+//! that contains key material is rejected, as is any unknown setting.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings that would put a secret into the profile file
+const SECRET_SETTINGS: &[&str] = &["key", "password", "passphrase", "secret"];
+
+/// Settings a profile may contain
+const KNOWN_SETTINGS: &[&str] = &[
+    "key-file",
+    "key-env",
+    "keyring-dir",
+    "try-all-keys",
+    "algorithm",
+    "aad",
+    "aad-hex",
+    "aad-file",
+    "pin-file",
+    "pin-name",
+    "nonce-history",
+    "nonce-history-limit",
+    "require-hardware-aes",
+];
+
+/// Groups of settings of which a profile may set at most one
+const EXCLUSIVE_SETTINGS: &[&[&str]] = &[
+    &["key-file", "key-env", "keyring-dir"],
+    &["aad", "aad-hex", "aad-file"],
+];
+
+/// Default options for one profile; every field is optional
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyring_dir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub try_all_keys: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aad: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aad_hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aad_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce_history: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce_history_limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_hardware_aes: bool,
+}
+
+/// Location of the profiles file
+pub fn default_path() -> io::Result<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "cannot locate the profiles file: neither XDG_CONFIG_HOME nor HOME is set",
+            )
+        })?;
+    Ok(config_dir.join("aes-gcm-siv").join("profiles.toml"))
+}
+
+fn invalid_data(path: &Path, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+/// Whether `value` could be a hex-encoded AES key pasted in by mistake
+fn looks_like_raw_key(value: &str) -> bool {
+    matches!(value.len(), 32 | 64) && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_env_var_name(value: &str) -> bool {
+    let mut bytes = value.bytes();
+    bytes
+        .next()
+        .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+fn validate(path: &Path, name: &str, settings: &toml::Table) -> io::Result<()> {
+    for setting in settings.keys() {
+        if SECRET_SETTINGS.contains(&setting.as_str()) {
+            return Err(invalid_data(
+                path,
+                format!(
+                    "profile `{}` sets `{}`; profiles must not contain secrets. \
+                     Refer to the key with key-file or key-env instead",
+                    name, setting
+                ),
+            ));
+        }
+        if !KNOWN_SETTINGS.contains(&setting.as_str()) {
+            return Err(invalid_data(
+                path,
+                format!("profile `{}`: unknown setting `{}`", name, setting),
+            ));
+        }
+    }
+
+    for group in EXCLUSIVE_SETTINGS {
+        let set: Vec<&str> = group
+            .iter()
+            .copied()
+            .filter(|s| settings.contains_key(*s))
+            .collect();
+        if set.len() > 1 {
+            return Err(invalid_data(
+                path,
+                format!(
+                    "profile `{}` sets both `{}` and `{}`; they are mutually exclusive",
+                    name, set[0], set[1]
+                ),
+            ));
+        }
+    }
+
+    for setting in ["key-file", "key-env"] {
+        if let Some(value) = settings.get(setting).and_then(|v| v.as_str()) {
+            if looks_like_raw_key(value) {
+                return Err(invalid_data(
+                    path,
+                    format!(
+                        "profile `{}`: `{}` looks like a raw key; profiles must not \
+                         contain secrets, only a key file path or environment variable name",
+                        name, setting
+                    ),
+                ));
+            }
+        }
+    }
+    if let Some(value) = settings.get("key-env").and_then(|v| v.as_str()) {
+        if !is_env_var_name(value) {
+            return Err(invalid_data(
+                path,
+                format!(
+                    "profile `{}`: `key-env` must be an environment variable name, not `{}`",
+                    name, value
+                ),
+            ));
+        }
+    }
+    // A limit of 0 would keep no pairs and silently disable the check
+    if let Some(value) = settings.get("nonce-history-limit") {
+        if value.as_integer().is_some_and(|limit| limit < 1) {
+            return Err(invalid_data(
+                path,
+                format!(
+                    "profile `{}`: `nonce-history-limit` must be at least 1, not {}",
+                    name, value
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Load and validate every profile in `path`.
+///
+/// # Errors
+/// Fails if the file cannot be read, is not valid TOML, or any profile has an
+/// unknown setting, a secret, or conflicting settings; the message names the
+/// profile and the offending setting.
+pub fn load_profiles(path: &Path) -> io::Result<BTreeMap<String, Profile>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let mut root: toml::Table = toml::from_str(&text).map_err(|e| invalid_data(path, e))?;
+
+    if let Some(key) = root.keys().find(|key| *key != "profiles") {
+        return Err(invalid_data(
+            path,
+            format!(
+                "unknown top-level key `{}`; expected [profiles.NAME] tables",
+                key
+            ),
+        ));
+    }
+    let Some(profiles) = root.remove("profiles") else {
+        return Ok(BTreeMap::new());
+    };
+    let toml::Value::Table(profiles) = profiles else {
+        return Err(invalid_data(path, "`profiles` must be a table"));
+    };
+
+    let mut loaded = BTreeMap::new();
+    for (name, settings) in profiles {
+        let toml::Value::Table(settings) = settings else {
+            return Err(invalid_data(
+                path,
+                format!("profile `{}` must be a table", name),
+            ));
+        };
+        validate(path, &name, &settings)?;
+        let profile: Profile = toml::Value::Table(settings)
+            .try_into()
+            .map_err(|e| invalid_data(path, format!("profile `{}`: {}", name, e)))?;
+        loaded.insert(name, profile);
+    }
+    Ok(loaded)
+}
+
+/// Load the profile called `name` from `path`.
+pub fn load_profile(path: &Path, name: &str) -> io::Result<Profile> {
+    let mut profiles = load_profiles(path)?;
+    profiles.remove(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "no profile `{}` in {} (available: {})",
+                name,
+                path.display(),
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        )
+    })
+}
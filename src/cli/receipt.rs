@@ -0,0 +1,278 @@
+//! Sidecar receipts recording how a file was encrypted
+//!
+//! `encrypt --receipt` writes a JSON receipt with everything needed to audit
+//! or reproduce the operation except secrets: hashes and sizes of the
+//! ciphertext (and optionally the plaintext), the key fingerprint, algorithm,
+//! nonce, a hash of the AAD, a timestamp and the tool version.
+//! `verify --receipt` checks a file against it.
+//!
+//! The schema is versioned by `receipt_version`; readers refuse versions
+//! newer than [`RECEIPT_VERSION`].
+
+use aes_gcm_siv_impl::envelope::{inspect, is_envelope};
+use aes_gcm_siv_impl::{key_fingerprint, open, Cipher, KeySize};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version of the receipt schema written by this binary
+pub const RECEIPT_VERSION: u32 = 1;
+
+/// Format of the ciphertext: an envelope (see `aes_gcm_siv_impl::envelope`)
+const FORMAT_ENVELOPE: &str = "envelope";
+
+/// Format of the ciphertext: a bare AES-GCM-SIV message, no header
+const FORMAT_RAW: &str = "raw";
+
+/// Overrides the receipt timestamp, for reproducible output
+const SOURCE_DATE_EPOCH_VAR: &str = "SOURCE_DATE_EPOCH";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tool {
+    pub name: String,
+    pub version: String,
+}
+
+/// Size and (optional) SHA-256 of one file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileDigest {
+    pub path: PathBuf,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// A receipt as stored on disk
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Receipt {
+    pub receipt_version: u32,
+    pub tool: Tool,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    pub format: String,
+    pub algorithm: String,
+    pub key_fingerprint: String,
+    pub nonce: String,
+    pub aad_sha256: String,
+    /// The plaintext; its hash only with `--receipt-plaintext-hash`
+    pub input: FileDigest,
+    /// The ciphertext
+    pub output: FileDigest,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn timestamp() -> u64 {
+    std::env::var(SOURCE_DATE_EPOCH_VAR)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0)
+        })
+}
+
+/// What `encrypt` did, for [`Receipt::new`]
+pub struct Encryption<'a> {
+    pub input: &'a Path,
+    pub output: &'a Path,
+    pub key: &'a [u8],
+    pub nonce: &'a [u8],
+    pub aad: &'a [u8],
+    pub plaintext: &'a [u8],
+    pub ciphertext: &'a [u8],
+    /// Whether `ciphertext` is bare (`--raw`) rather than an envelope
+    pub raw: bool,
+}
+
+impl Receipt {
+    /// Describe an encryption; the plaintext hash is included only if asked for.
+    pub fn new(encryption: &Encryption<'_>, plaintext_hash: bool) -> io::Result<Self> {
+        let key_size = KeySize::from_key_len(encryption.key.len())?;
+        Ok(Receipt {
+            receipt_version: RECEIPT_VERSION,
+            tool: Tool {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            created_at: timestamp(),
+            format: if encryption.raw {
+                FORMAT_RAW
+            } else {
+                FORMAT_ENVELOPE
+            }
+            .to_string(),
+            algorithm: key_size.algorithm_name().to_string(),
+            key_fingerprint: key_fingerprint(encryption.key)?.to_string(),
+            nonce: hex::encode(encryption.nonce),
+            aad_sha256: sha256_hex(encryption.aad),
+            input: FileDigest {
+                path: encryption.input.to_path_buf(),
+                size: encryption.plaintext.len() as u64,
+                sha256: plaintext_hash.then(|| sha256_hex(encryption.plaintext)),
+            },
+            output: FileDigest {
+                path: encryption.output.to_path_buf(),
+                size: encryption.ciphertext.len() as u64,
+                sha256: Some(sha256_hex(encryption.ciphertext)),
+            },
+        })
+    }
+
+    /// Write the receipt as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        json.push('\n');
+        fs::write(path, json)
+    }
+
+    /// Read a receipt, refusing schema versions this binary does not know.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        match value.get("receipt_version").and_then(|v| v.as_u64()) {
+            Some(version) if version > u64::from(RECEIPT_VERSION) => {
+                return Err(invalid(format!(
+                    "receipt version {} is newer than this binary supports ({})",
+                    version, RECEIPT_VERSION
+                )))
+            }
+            Some(_) => {}
+            None => return Err(invalid("missing receipt_version".to_string())),
+        }
+        serde_json::from_value(value).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Check `ciphertext` (and, given the key, everything derived from it)
+    /// against the receipt.
+    ///
+    /// # Returns
+    /// One line per mismatching field; empty if everything matches
+    pub fn verify(&self, ciphertext: &[u8], key: Option<&[u8]>, aad: &[u8]) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        let envelope = is_envelope(ciphertext);
+        let format = if envelope {
+            FORMAT_ENVELOPE
+        } else {
+            FORMAT_RAW
+        };
+        check(&mut mismatches, "format", &self.format, format);
+        check(
+            &mut mismatches,
+            "output.size",
+            &self.output.size.to_string(),
+            &ciphertext.len().to_string(),
+        );
+        if let Some(expected) = &self.output.sha256 {
+            check(
+                &mut mismatches,
+                "output.sha256",
+                expected,
+                &sha256_hex(ciphertext),
+            );
+        }
+        // An envelope records the nonce and key size, so those can be
+        // checked without the key
+        if envelope {
+            match inspect(ciphertext) {
+                Ok(header) => {
+                    check(
+                        &mut mismatches,
+                        "algorithm",
+                        &self.algorithm,
+                        header.key_size.algorithm_name(),
+                    );
+                    check(
+                        &mut mismatches,
+                        "nonce",
+                        &self.nonce,
+                        &hex::encode(header.nonce),
+                    );
+                }
+                Err(e) => mismatches.push(format!("envelope header: {}", e)),
+            }
+        }
+
+        let Some(key) = key else {
+            return mismatches;
+        };
+        let algorithm = KeySize::from_key_len(key.len())
+            .map(|size| size.algorithm_name().to_string())
+            .unwrap_or_else(|e| e.to_string());
+        check(&mut mismatches, "algorithm", &self.algorithm, &algorithm);
+        let fingerprint = key_fingerprint(key)
+            .map(|fp| fp.to_string())
+            .unwrap_or_else(|e| e.to_string());
+        check(
+            &mut mismatches,
+            "key_fingerprint",
+            &self.key_fingerprint,
+            &fingerprint,
+        );
+        check(
+            &mut mismatches,
+            "aad_sha256",
+            &self.aad_sha256,
+            &sha256_hex(aad),
+        );
+
+        let plaintext = if envelope {
+            open(key, ciphertext, aad).map_err(|e| format!("decryption: {}", e))
+        } else {
+            hex::decode(&self.nonce)
+                .map_err(|e| format!("nonce: {}", e))
+                .and_then(|nonce| {
+                    Cipher::new(key)
+                        .and_then(|cipher| cipher.decrypt(&nonce, ciphertext, aad))
+                        .map_err(|e| format!("decryption with the recorded nonce: {}", e))
+                })
+        };
+        match plaintext {
+            Ok(plaintext) => {
+                check(
+                    &mut mismatches,
+                    "input.size",
+                    &self.input.size.to_string(),
+                    &plaintext.len().to_string(),
+                );
+                if let Some(expected) = &self.input.sha256 {
+                    check(
+                        &mut mismatches,
+                        "input.sha256",
+                        expected,
+                        &sha256_hex(&plaintext),
+                    );
+                }
+            }
+            Err(e) => mismatches.push(e),
+        }
+        mismatches
+    }
+}
+
+fn check(mismatches: &mut Vec<String>, field: &str, expected: &str, actual: &str) {
+    if expected != actual {
+        mismatches.push(format!(
+            "{}: receipt has {}, found {}",
+            field, expected, actual
+        ));
+    }
+}
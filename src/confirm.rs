@@ -0,0 +1,171 @@
+//! Two-party key confirmation
+//!
+//! Lets two parties that each hold "the key" check, in one round trip, that
+//! they actually hold the *same* key, e.g. to catch configuration drift before
+//! a long transfer.
+//!
+//! 1. The initiator calls [`key_confirmation_challenge`] and sends the
+//!    [`Challenge`] to the peer, keeping the [`ExpectedResponse`].
+//! 2. The peer calls [`key_confirmation_respond`] and sends back the
+//!    [`Response`].
+//! 3. The initiator checks it with [`ExpectedResponse::verify`].
+//!
+//! The response is an AES-GCM-SIV encryption of a fixed structured message
+//! containing the random challenge, under the challenge's random nonce and a
+//! confirmation subkey derived from the key with HKDF-SHA256. A response
+//! therefore cannot be replayed against a fresh challenge.
+//!
+//! # Security Notes
+//! - This confirms key equality only. It does not authenticate who the peer
+//!   is beyond "holds the key", and only the initiator learns the outcome.
+//! - The challenge is chosen by whoever sends it, so the responder never
+//!   encrypts it under the data key itself, only under the subkey, which no
+//!   data encryption uses
+
+use crate::{Cipher, CryptoResult, KeySize, NONCE_LENGTH, TAG_LENGTH};
+use alloc::vec::Vec;
+use hkdf::Hkdf;
+#[cfg(feature = "std")]
+use rand::TryRngCore;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+/// Length of the random challenge value in bytes
+const CHALLENGE_VALUE_LENGTH: usize = 16;
+
+/// Length of a serialized [`Challenge`] in bytes (nonce followed by value)
+pub const CHALLENGE_LENGTH: usize = NONCE_LENGTH + CHALLENGE_VALUE_LENGTH;
+
+/// Fixed label at the start of every confirmation message
+const MESSAGE_LABEL: &[u8; 16] = b"aes-gcm-siv-kcv2";
+
+/// Length of a serialized [`Response`] in bytes
+pub const RESPONSE_LENGTH: usize = MESSAGE_LABEL.len() + CHALLENGE_VALUE_LENGTH + TAG_LENGTH;
+
+/// AAD reserved for confirmation responses
+const RESPONSE_AAD: &[u8] = b"aes-gcm-siv-impl/key-confirmation/v2/response";
+
+/// HKDF info that derives the confirmation subkey from the key
+const SUBKEY_INFO: &[u8] = b"aes-gcm-siv-impl/key-confirmation/v2/subkey";
+
+/// A key confirmation challenge, sent from the initiator to the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Challenge {
+    nonce: [u8; NONCE_LENGTH],
+    value: [u8; CHALLENGE_VALUE_LENGTH],
+}
+
+impl Challenge {
+    /// Serialize the challenge for transmission
+    pub fn to_bytes(&self) -> [u8; CHALLENGE_LENGTH] {
+        let mut bytes = [0u8; CHALLENGE_LENGTH];
+        bytes[..NONCE_LENGTH].copy_from_slice(&self.nonce);
+        bytes[NONCE_LENGTH..].copy_from_slice(&self.value);
+        bytes
+    }
+
+    /// Deserialize a challenge received from the initiator
+    pub fn from_bytes(bytes: [u8; CHALLENGE_LENGTH]) -> Self {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        let mut value = [0u8; CHALLENGE_VALUE_LENGTH];
+        nonce.copy_from_slice(&bytes[..NONCE_LENGTH]);
+        value.copy_from_slice(&bytes[NONCE_LENGTH..]);
+        Challenge { nonce, value }
+    }
+}
+
+/// A peer's answer to a [`Challenge`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Response([u8; RESPONSE_LENGTH]);
+
+impl Response {
+    /// Serialize the response for transmission
+    pub fn to_bytes(&self) -> [u8; RESPONSE_LENGTH] {
+        self.0
+    }
+
+    /// Deserialize a response received from the peer
+    pub fn from_bytes(bytes: [u8; RESPONSE_LENGTH]) -> Self {
+        Response(bytes)
+    }
+}
+
+/// The response the initiator expects; kept locally, never sent
+#[derive(Debug, Clone)]
+pub struct ExpectedResponse(Response);
+
+impl ExpectedResponse {
+    /// Check the peer's response in constant time
+    ///
+    /// # Returns
+    /// `true` if and only if the peer holds the same key
+    pub fn verify(&self, response: &Response) -> bool {
+        constant_time_eq(&self.0 .0, &response.0)
+    }
+}
+
+/// Compare two byte strings without data-dependent timing
+///
+/// Only the lengths may leak; the contents never do.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// The key the responses are encrypted under, the same size as `key`
+fn confirmation_subkey(key: &[u8]) -> CryptoResult<Zeroizing<Vec<u8>>> {
+    let key_size = KeySize::from_key_len(key.len())?;
+    let mut subkey = Zeroizing::new(alloc::vec![0u8; key_size.key_len()]);
+    Hkdf::<Sha256>::new(None, key)
+        .expand(SUBKEY_INFO, &mut subkey)
+        .expect("at most 32 bytes, well within HKDF-SHA256's output limit");
+    Ok(subkey)
+}
+
+fn respond(key: &[u8], challenge: &Challenge) -> CryptoResult<Response> {
+    let subkey = confirmation_subkey(key)?;
+    let mut message = [0u8; MESSAGE_LABEL.len() + CHALLENGE_VALUE_LENGTH];
+    message[..MESSAGE_LABEL.len()].copy_from_slice(MESSAGE_LABEL);
+    message[MESSAGE_LABEL.len()..].copy_from_slice(&challenge.value);
+
+    let ciphertext = Cipher::new(&subkey)?.encrypt(&challenge.nonce, &message, RESPONSE_AAD)?;
+    let mut response = [0u8; RESPONSE_LENGTH];
+    response.copy_from_slice(&ciphertext);
+    Ok(Response(response))
+}
+
+/// Start a key confirmation
+///
+/// # Arguments
+/// * `key` - The initiator's key (must be 16 or 32 bytes)
+///
+/// # Returns
+/// The challenge to send to the peer and the response to expect back
+///
+/// # Errors
+/// Returns `CryptoError::InvalidKeySize` if key length is invalid
+#[cfg(feature = "std")]
+pub fn key_confirmation_challenge(key: &[u8]) -> CryptoResult<(Challenge, ExpectedResponse)> {
+    let mut os_rng = rand::rngs::OsRng;
+    let mut challenge = Challenge {
+        nonce: [0u8; NONCE_LENGTH],
+        value: [0u8; CHALLENGE_VALUE_LENGTH],
+    };
+    os_rng.try_fill_bytes(&mut challenge.nonce).unwrap(); // CSPRNG
+    os_rng.try_fill_bytes(&mut challenge.value).unwrap();
+
+    let expected = respond(key, &challenge)?;
+    Ok((challenge, ExpectedResponse(expected)))
+}
+
+/// Answer a key confirmation challenge
+///
+/// # Arguments
+/// * `key` - The peer's key (must be 16 or 32 bytes)
+/// * `challenge` - The challenge received from the initiator
+///
+/// # Errors
+/// Returns `CryptoError::InvalidKeySize` if key length is invalid
+pub fn key_confirmation_respond(key: &[u8], challenge: &Challenge) -> CryptoResult<Response> {
+    respond(key, challenge)
+}
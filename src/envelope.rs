@@ -0,0 +1,492 @@
+//! Self-describing ciphertext envelopes
+//!
+//! [`encrypt`] returns bare ciphertext, so the nonce (and which key size was
+//! used) has to be stored and passed back separately. [`seal`] instead
+//! returns an envelope that carries everything but the key and the AAD, and
+//! [`open`] reads it back:
+//!
+//! ```text
+//! magic "AGSVENV" | version (1) | minimum reader version (1)
+//!     | producer length (u8) and producer | key length (16 or 32) | flags
+//!     | [AAD length (u64 LE), if flag bit 0]
+//!     | [key ID length (u8) and key ID, if flag bit 1] | nonce (12) | ciphertext and tag
+//! ```
+//!
+//! The part up to and including the producer keeps this layout in every
+//! format version, so any reader can tell which version a newer envelope
+//! needs and which tool wrote it. An envelope is readable by every reader
+//! supporting at least its minimum reader version, even when its version is
+//! newer still; otherwise [`open`] returns
+//! [`EnvelopeError::UnsupportedVersion`] naming both.
+//!
+//! Flag bit 2 records that the caller's AAD was not empty, and [`open`]
+//! refuses to open such an envelope without AAD. The AAD length is recorded
+//! along with it, so a missing or wrong AAD is reported as such instead of
+//! as a bare authentication failure. The header is authenticated along with the AAD, so editing any
+//! of it fails authentication. Envelopes written by a
+//! [`Keyring`](crate::Keyring) also record the ID of the key, so the right
+//! key can be picked without trying them all.
+//!
+//! # Security Notes
+//! - The producer, the key length, the key ID and the AAD length are
+//!   visible to anyone holding the envelope; the AAD itself is not stored
+//! - [`seal`] picks a random nonce; with [`seal_with_nonce`] the caller is
+//!   responsible for never reusing one with the same key
+
+#[cfg(feature = "std")]
+use crate::generate_nonce;
+use crate::keyring::is_valid_key_id;
+use crate::{Cipher, CryptoError, ErrorClass, KeySize, NONCE_LENGTH};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Identifies an envelope
+const MAGIC: &[u8; 7] = b"AGSVENV";
+
+/// Envelope format version written by [`seal`], and the newest one this
+/// version reads
+pub const ENVELOPE_VERSION: u8 = 1;
+
+/// Producer recorded by [`seal`]: this crate's name and version
+pub const PRODUCER: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+/// Maximum length of a recorded producer in bytes
+pub const MAX_PRODUCER_LENGTH: usize = 64;
+
+/// Flag bit: the AAD length follows the flags
+const FLAG_AAD_LENGTH: u8 = 0x01;
+
+/// Flag bit: the key ID follows the AAD length (if any)
+const FLAG_KEY_ID: u8 = 0x02;
+
+/// Flag bit: the caller's AAD was not empty, so opening needs AAD
+const FLAG_AAD_REQUIRED: u8 = 0x04;
+
+/// Header length without the producer, the optional AAD length and key ID
+const FIXED_HEADER_LENGTH: usize = MAGIC.len() + 5 + NONCE_LENGTH;
+
+/// Errors returned by [`open`] and [`inspect`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EnvelopeError {
+    /// The input does not start with the envelope magic
+    NotAnEnvelope,
+    /// The envelope needs a newer format version than this one reads
+    UnsupportedVersion {
+        /// Minimum reader version recorded in the envelope
+        required: u8,
+        /// Producer recorded in the envelope
+        producer: String,
+    },
+    /// The header is cut short, or has an invalid key length, an invalid
+    /// key ID or unknown or inconsistent flags
+    Malformed,
+    /// The envelope was sealed with a key of a different size
+    KeySizeMismatch {
+        /// Key size recorded in the envelope
+        envelope: KeySize,
+        /// Size of the key passed to [`open`]
+        key: KeySize,
+    },
+    /// The AAD passed to [`open`] has a different length than the one the
+    /// envelope was sealed with
+    AadLengthMismatch {
+        /// AAD length recorded in the envelope
+        envelope: u64,
+        /// Length of the AAD passed to [`open`]
+        aad: u64,
+    },
+    /// The envelope records that it was sealed with non-empty AAD, but
+    /// none was passed to [`open`]
+    AadRequired,
+    /// Decryption failed (see [`CryptoError`])
+    Crypto(CryptoError),
+}
+
+impl EnvelopeError {
+    /// The broad category of this error
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            EnvelopeError::Crypto(e) => e.classify(),
+            EnvelopeError::AadLengthMismatch { .. } | EnvelopeError::AadRequired => {
+                ErrorClass::AuthFailure
+            }
+            EnvelopeError::NotAnEnvelope
+            | EnvelopeError::UnsupportedVersion { .. }
+            | EnvelopeError::Malformed
+            | EnvelopeError::KeySizeMismatch { .. } => ErrorClass::InvalidInput,
+        }
+    }
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::NotAnEnvelope => write!(f, "Not an envelope"),
+            EnvelopeError::UnsupportedVersion { required, producer } => write!(
+                f,
+                "Envelope requires format v{} (produced by {}); this version supports up to v{}",
+                required, producer, ENVELOPE_VERSION
+            ),
+            EnvelopeError::Malformed => write!(f, "Malformed envelope header"),
+            EnvelopeError::KeySizeMismatch { envelope, key } => write!(
+                f,
+                "Envelope was sealed with {} but the key is for {}",
+                envelope, key
+            ),
+            EnvelopeError::AadLengthMismatch { envelope, aad } => write!(
+                f,
+                "Envelope was sealed with {} bytes of AAD, but {} were given",
+                envelope, aad
+            ),
+            EnvelopeError::AadRequired => {
+                write!(f, "Envelope was sealed with AAD, but none was given")
+            }
+            EnvelopeError::Crypto(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EnvelopeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EnvelopeError::Crypto(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<CryptoError> for EnvelopeError {
+    fn from(err: CryptoError) -> Self {
+        EnvelopeError::Crypto(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<EnvelopeError> for std::io::Error {
+    fn from(err: EnvelopeError) -> Self {
+        let kind = match err.classify() {
+            ErrorClass::AuthFailure => std::io::ErrorKind::InvalidData,
+            ErrorClass::InvalidInput => std::io::ErrorKind::InvalidInput,
+            ErrorClass::Resource => std::io::ErrorKind::Unsupported,
+            ErrorClass::Io | ErrorClass::Internal => std::io::ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+/// The parameters recorded in an envelope header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeHeader {
+    /// Format version
+    pub version: u8,
+    /// Lowest format version a reader needs to support to read the envelope
+    pub min_reader_version: u8,
+    /// Name and version of the tool that wrote the envelope
+    pub producer: String,
+    /// Size of the key the envelope was sealed with
+    pub key_size: KeySize,
+    /// AAD length, if the envelope records one (it does when the AAD was
+    /// not empty)
+    pub aad_length: Option<u64>,
+    /// Whether the caller's AAD was not empty, so opening needs AAD
+    pub aad_required: bool,
+    /// ID of the key, if the envelope was sealed by a [`Keyring`](crate::Keyring)
+    pub key_id: Option<String>,
+    /// Nonce the payload was encrypted under
+    pub nonce: [u8; NONCE_LENGTH],
+}
+
+impl EnvelopeHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FIXED_HEADER_LENGTH + self.producer.len() + 8);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.min_reader_version);
+        // Valid producers are at most MAX_PRODUCER_LENGTH bytes
+        bytes.push(self.producer.len() as u8);
+        bytes.extend_from_slice(self.producer.as_bytes());
+        // 16 or 32, so the cast is lossless
+        bytes.push(self.key_size.key_len() as u8);
+        let mut flags = 0;
+        if self.aad_length.is_some() {
+            flags |= FLAG_AAD_LENGTH;
+        }
+        if self.key_id.is_some() {
+            flags |= FLAG_KEY_ID;
+        }
+        if self.aad_required {
+            flags |= FLAG_AAD_REQUIRED;
+        }
+        bytes.push(flags);
+        if let Some(length) = self.aad_length {
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        if let Some(key_id) = &self.key_id {
+            // Valid key IDs are at most MAX_KEY_ID_LENGTH bytes
+            bytes.push(key_id.len() as u8);
+            bytes.extend_from_slice(key_id.as_bytes());
+        }
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+
+    /// Parse the header at the start of `envelope`; returns it together
+    /// with its length
+    pub(crate) fn parse(envelope: &[u8]) -> Result<(Self, usize), EnvelopeError> {
+        if !envelope.starts_with(MAGIC) {
+            return Err(EnvelopeError::NotAnEnvelope);
+        }
+        let rest = &envelope[MAGIC.len()..];
+        let (&version, rest) = rest.split_first().ok_or(EnvelopeError::Malformed)?;
+        let (&min_reader_version, rest) = rest.split_first().ok_or(EnvelopeError::Malformed)?;
+        let (&length, rest) = rest.split_first().ok_or(EnvelopeError::Malformed)?;
+        let (producer, rest) = rest
+            .split_at_checked(length.into())
+            .ok_or(EnvelopeError::Malformed)?;
+        let producer = core::str::from_utf8(producer)
+            .ok()
+            .filter(|producer| is_valid_producer(producer))
+            .ok_or(EnvelopeError::Malformed)?
+            .to_string();
+        if min_reader_version == 0 || min_reader_version > version {
+            return Err(EnvelopeError::Malformed);
+        }
+        if min_reader_version > ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion {
+                required: min_reader_version,
+                producer,
+            });
+        }
+        let (&key_len, rest) = rest.split_first().ok_or(EnvelopeError::Malformed)?;
+        let key_size =
+            KeySize::from_key_len(key_len.into()).map_err(|_| EnvelopeError::Malformed)?;
+        let (&flags, mut rest) = rest.split_first().ok_or(EnvelopeError::Malformed)?;
+        if flags & !(FLAG_AAD_LENGTH | FLAG_KEY_ID | FLAG_AAD_REQUIRED) != 0 {
+            return Err(EnvelopeError::Malformed);
+        }
+        // Non-empty AAD always has its length recorded
+        let aad_required = flags & FLAG_AAD_REQUIRED != 0;
+        if aad_required && flags & FLAG_AAD_LENGTH == 0 {
+            return Err(EnvelopeError::Malformed);
+        }
+        let aad_length = if flags & FLAG_AAD_LENGTH != 0 {
+            let (length, tail) = rest.split_at_checked(8).ok_or(EnvelopeError::Malformed)?;
+            rest = tail;
+            Some(u64::from_le_bytes(length.try_into().expect("8 bytes")))
+        } else {
+            None
+        };
+        let key_id = if flags & FLAG_KEY_ID != 0 {
+            let (&length, tail) = rest.split_first().ok_or(EnvelopeError::Malformed)?;
+            let (key_id, tail) = tail
+                .split_at_checked(length.into())
+                .ok_or(EnvelopeError::Malformed)?;
+            rest = tail;
+            let key_id = core::str::from_utf8(key_id)
+                .ok()
+                .filter(|id| is_valid_key_id(id))
+                .ok_or(EnvelopeError::Malformed)?;
+            Some(key_id.to_string())
+        } else {
+            None
+        };
+        let nonce = rest
+            .get(..NONCE_LENGTH)
+            .ok_or(EnvelopeError::Malformed)?
+            .try_into()
+            .expect("NONCE_LENGTH bytes");
+        let header_length = envelope.len() - rest.len() + NONCE_LENGTH;
+        Ok((
+            EnvelopeHeader {
+                version,
+                min_reader_version,
+                producer,
+                key_size,
+                aad_length,
+                aad_required,
+                key_id,
+                nonce,
+            },
+            header_length,
+        ))
+    }
+}
+
+/// Whether `producer` can be recorded: 1 to [`MAX_PRODUCER_LENGTH`]
+/// printable ASCII characters, so it is safe to show in messages
+fn is_valid_producer(producer: &str) -> bool {
+    (1..=MAX_PRODUCER_LENGTH).contains(&producer.len())
+        && producer.bytes().all(|b| b.is_ascii_graphic() || b == b' ')
+}
+
+/// AEAD input: the header followed by the caller's AAD
+fn authenticated_data(header: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut data = header.to_vec();
+    data.extend_from_slice(aad);
+    data
+}
+
+/// Encrypt `plaintext` into an envelope under a random nonce
+///
+/// # Arguments
+/// * `key` - The encryption key (must be 16 or 32 bytes)
+/// * `plaintext` - The plaintext data to encrypt
+/// * `aad` - Additional authenticated data (not stored in the envelope)
+///
+/// # Returns
+/// The envelope: header, then ciphertext with authentication tag appended
+///
+/// # Errors
+/// Returns `CryptoError::InvalidKeySize` if key length is invalid
+#[cfg(feature = "std")]
+pub fn seal(key: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    seal_with_nonce(key, &generate_nonce(), plaintext, aad)
+}
+
+/// Encrypt `plaintext` into an envelope under a caller-chosen nonce
+///
+/// # Security Notes
+/// - Never reuse a nonce with the same key; prefer [`seal`]
+///
+/// # Errors
+/// Returns `CryptoError` if key or nonce length is invalid
+pub fn seal_with_nonce(
+    key: &[u8],
+    nonce: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    seal_with_header(key, None, !aad.is_empty(), nonce, plaintext, aad)
+}
+
+/// [`seal_with_nonce`], recording `key_id` (already validated) and whether
+/// the caller's AAD was non-empty in the header
+///
+/// `aad_required` is passed separately for callers that prepend their own
+/// data to the caller's AAD.
+pub(crate) fn seal_with_header(
+    key: &[u8],
+    key_id: Option<&str>,
+    aad_required: bool,
+    nonce: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    seal_with_cipher(
+        &Cipher::new(key)?,
+        key_id,
+        aad_required,
+        nonce,
+        plaintext,
+        aad,
+    )
+}
+
+/// [`seal_with_header`] under an already-built [`Cipher`]
+pub(crate) fn seal_with_cipher(
+    cipher: &Cipher,
+    key_id: Option<&str>,
+    aad_required: bool,
+    nonce: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let nonce: [u8; NONCE_LENGTH] = nonce
+        .try_into()
+        .map_err(|_| CryptoError::InvalidNonceSize)?;
+    let header = EnvelopeHeader {
+        version: ENVELOPE_VERSION,
+        min_reader_version: ENVELOPE_VERSION,
+        producer: PRODUCER.to_string(),
+        key_size: cipher.key_size(),
+        aad_length: (!aad.is_empty()).then_some(aad.len() as u64),
+        aad_required,
+        key_id: key_id.map(str::to_string),
+        nonce,
+    }
+    .to_bytes();
+
+    let ciphertext = cipher.encrypt(&nonce, plaintext, &authenticated_data(&header, aad))?;
+    let mut envelope = header;
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// Decrypt an envelope written by [`seal`]
+///
+/// # Arguments
+/// * `key` - The encryption key (must be 16 or 32 bytes)
+/// * `envelope` - The envelope
+/// * `aad` - Additional authenticated data (must match what was used for sealing)
+///
+/// # Returns
+/// The decrypted plaintext
+///
+/// # Errors
+/// Returns `EnvelopeError::NotAnEnvelope`, `UnsupportedVersion` or
+/// `Malformed` for a bad header, `KeySizeMismatch`, `AadRequired` or
+/// `AadLengthMismatch` when the key or AAD cannot be the right one, and
+/// `Crypto` if authentication fails
+pub fn open(key: &[u8], envelope: &[u8], aad: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+    let parsed = EnvelopeHeader::parse(envelope)?;
+    open_parsed(&Cipher::new(key)?, envelope, parsed, aad)
+}
+
+/// [`open`] under an already-built [`Cipher`]
+pub(crate) fn open_with_cipher(
+    cipher: &Cipher,
+    envelope: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, EnvelopeError> {
+    open_parsed(cipher, envelope, EnvelopeHeader::parse(envelope)?, aad)
+}
+
+/// Check the key and AAD against the parsed header of `envelope`, then
+/// decrypt it
+fn open_parsed(
+    cipher: &Cipher,
+    envelope: &[u8],
+    (header, header_length): (EnvelopeHeader, usize),
+    aad: &[u8],
+) -> Result<Vec<u8>, EnvelopeError> {
+    if cipher.key_size() != header.key_size {
+        return Err(EnvelopeError::KeySizeMismatch {
+            envelope: header.key_size,
+            key: cipher.key_size(),
+        });
+    }
+    if header.aad_required && aad.is_empty() {
+        return Err(EnvelopeError::AadRequired);
+    }
+    let expected_aad_length = header.aad_length.unwrap_or(0);
+    if expected_aad_length != aad.len() as u64 {
+        return Err(EnvelopeError::AadLengthMismatch {
+            envelope: expected_aad_length,
+            aad: aad.len() as u64,
+        });
+    }
+
+    let (header_bytes, ciphertext) = envelope.split_at(header_length);
+    Ok(cipher.decrypt(
+        &header.nonce,
+        ciphertext,
+        &authenticated_data(header_bytes, aad),
+    )?)
+}
+
+/// Read the header of an envelope without decrypting it
+///
+/// # Errors
+/// Returns `EnvelopeError::NotAnEnvelope`, `UnsupportedVersion` or
+/// `Malformed` for a bad header
+pub fn inspect(envelope: &[u8]) -> Result<EnvelopeHeader, EnvelopeError> {
+    EnvelopeHeader::parse(envelope).map(|(header, _)| header)
+}
+
+/// Whether `data` starts like an envelope (its magic), of any version
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
@@ -0,0 +1,344 @@
+//! Password-based encryption with keys derived by Argon2id
+//!
+//! [`derive_key`] turns a password and a random salt into an AES-128 or
+//! AES-256 key with Argon2id (RFC 9106). [`encrypt_with_password`] does that
+//! with a fresh salt and seals the data in an [envelope](crate::envelope),
+//! recording the KDF parameters and salt in front of it so that
+//! [`decrypt_with_password`] needs nothing but the password:
+//!
+//! ```text
+//! magic "AGSVPWD" | version (1) | memory KiB (u32 LE) | iterations (u32 LE)
+//!     | parallelism (u32 LE) | salt (16) | envelope
+//! ```
+//!
+//! The header is authenticated as part of the envelope's AAD.
+//!
+//! # Security Notes
+//! - A password-encrypted file is only as strong as the password: the KDF
+//!   slows down guessing but cannot make a weak password safe
+//! - The parameters are read from the file, so [`decrypt_with_password`]
+//!   refuses any above [`MAX_MEMORY_KIB`], [`MAX_ITERATIONS`] and
+//!   [`MAX_PARALLELISM`] rather than let a hostile file exhaust memory or CPU
+
+use crate::envelope::{self, EnvelopeError};
+#[cfg(feature = "std")]
+use crate::generate_nonce;
+use crate::{ErrorClass, KeySize};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use argon2::{Algorithm, Argon2, Params, Version};
+use core::fmt;
+#[cfg(feature = "std")]
+use rand::TryRngCore;
+use zeroize::Zeroizing;
+
+/// Length of the random salt in bytes
+pub const SALT_LENGTH: usize = 16;
+
+/// Largest memory cost accepted, in KiB (1 GiB)
+pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+
+/// Largest number of iterations accepted
+pub const MAX_ITERATIONS: u32 = 64;
+
+/// Largest degree of parallelism accepted
+pub const MAX_PARALLELISM: u32 = 16;
+
+/// Identifies password-encrypted data
+const MAGIC: &[u8; 7] = b"AGSVPWD";
+
+/// Format version written by [`encrypt_with_password`]
+pub const PASSWORD_FORMAT_VERSION: u8 = 1;
+
+/// Header: magic, version, three parameters, salt
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 3 * 4 + SALT_LENGTH;
+
+/// Argon2id cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    /// Number of passes over the memory
+    pub iterations: u32,
+    /// Degree of parallelism (lanes)
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// 64 MiB and 3 iterations, as recommended by RFC 9106, in one lane
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    fn argon2(&self, key_size: KeySize) -> Result<Argon2<'static>, KdfError> {
+        if self.memory_kib > MAX_MEMORY_KIB
+            || self.iterations > MAX_ITERATIONS
+            || self.parallelism > MAX_PARALLELISM
+        {
+            return Err(KdfError::InvalidParams(format!(
+                "at most {} KiB of memory, {} iterations and {} lanes are supported",
+                MAX_MEMORY_KIB, MAX_ITERATIONS, MAX_PARALLELISM
+            )));
+        }
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(key_size.key_len()),
+        )
+        .map_err(|e| KdfError::InvalidParams(e.to_string()))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// Errors returned by the password-based functions
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KdfError {
+    /// The KDF parameters are out of range
+    InvalidParams(String),
+    /// The password is empty
+    EmptyPassword,
+    /// The input does not start with the password-encryption magic
+    NotPasswordEncrypted,
+    /// The input was written by a newer format version
+    UnsupportedVersion(u8),
+    /// The header is cut short
+    Malformed,
+    /// Sealing or opening the envelope failed; a wrong password fails
+    /// authentication here
+    Envelope(EnvelopeError),
+}
+
+impl KdfError {
+    /// The broad category of this error
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            KdfError::Envelope(e) => e.classify(),
+            KdfError::InvalidParams(_)
+            | KdfError::EmptyPassword
+            | KdfError::NotPasswordEncrypted
+            | KdfError::UnsupportedVersion(_)
+            | KdfError::Malformed => ErrorClass::InvalidInput,
+        }
+    }
+}
+
+impl fmt::Display for KdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KdfError::InvalidParams(reason) => write!(f, "Invalid KDF parameters: {}", reason),
+            KdfError::EmptyPassword => write!(f, "The password is empty"),
+            KdfError::NotPasswordEncrypted => write!(f, "Not password-encrypted data"),
+            KdfError::UnsupportedVersion(version) => write!(
+                f,
+                "Password format version {} is newer than this version supports ({})",
+                version, PASSWORD_FORMAT_VERSION
+            ),
+            KdfError::Malformed => write!(f, "Malformed password-encryption header"),
+            KdfError::Envelope(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KdfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KdfError::Envelope(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<EnvelopeError> for KdfError {
+    fn from(err: EnvelopeError) -> Self {
+        KdfError::Envelope(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<KdfError> for std::io::Error {
+    fn from(err: KdfError) -> Self {
+        let kind = match err.classify() {
+            ErrorClass::AuthFailure => std::io::ErrorKind::InvalidData,
+            ErrorClass::InvalidInput => std::io::ErrorKind::InvalidInput,
+            ErrorClass::Resource => std::io::ErrorKind::Unsupported,
+            ErrorClass::Io | ErrorClass::Internal => std::io::ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+/// Derive a key from a password with Argon2id
+///
+/// # Arguments
+/// * `password` - The password
+/// * `salt` - A random salt, unique per derived key ([`SALT_LENGTH`] bytes
+///   recommended, at least 8)
+/// * `params` - Argon2id cost parameters
+/// * `key_size` - Size of the key to derive
+///
+/// # Returns
+/// The key, zeroized when dropped
+///
+/// # Errors
+/// Returns `KdfError::EmptyPassword` for an empty password and
+/// `KdfError::InvalidParams` if the parameters or the salt are out of range
+pub fn derive_key(
+    password: &[u8],
+    salt: &[u8],
+    params: &KdfParams,
+    key_size: KeySize,
+) -> Result<Zeroizing<Vec<u8>>, KdfError> {
+    if password.is_empty() {
+        return Err(KdfError::EmptyPassword);
+    }
+    let mut key = Zeroizing::new(alloc::vec![0u8; key_size.key_len()]);
+    params
+        .argon2(key_size)?
+        .hash_password_into(password, salt, &mut key)
+        .map_err(|e| KdfError::InvalidParams(e.to_string()))?;
+    Ok(key)
+}
+
+#[cfg(feature = "std")]
+fn header_bytes(params: &KdfParams, salt: &[u8; SALT_LENGTH]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+    header.extend_from_slice(MAGIC);
+    header.push(PASSWORD_FORMAT_VERSION);
+    header.extend_from_slice(&params.memory_kib.to_le_bytes());
+    header.extend_from_slice(&params.iterations.to_le_bytes());
+    header.extend_from_slice(&params.parallelism.to_le_bytes());
+    header.extend_from_slice(salt);
+    header
+}
+
+fn parse_header(data: &[u8]) -> Result<(KdfParams, &[u8], &[u8]), KdfError> {
+    if !data.starts_with(MAGIC) {
+        return Err(KdfError::NotPasswordEncrypted);
+    }
+    if data.len() < HEADER_LENGTH {
+        return Err(KdfError::Malformed);
+    }
+    let (header, envelope) = data.split_at(HEADER_LENGTH);
+    let version = header[MAGIC.len()];
+    if version == 0 {
+        return Err(KdfError::Malformed);
+    }
+    if version > PASSWORD_FORMAT_VERSION {
+        return Err(KdfError::UnsupportedVersion(version));
+    }
+    let field = |index: usize| {
+        let start = MAGIC.len() + 1 + 4 * index;
+        u32::from_le_bytes(header[start..start + 4].try_into().expect("4 bytes"))
+    };
+    let params = KdfParams {
+        memory_kib: field(0),
+        iterations: field(1),
+        parallelism: field(2),
+    };
+    Ok((params, header, envelope))
+}
+
+/// AAD of the envelope: the header followed by the caller's AAD
+fn envelope_aad(header: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut data = header.to_vec();
+    data.extend_from_slice(aad);
+    data
+}
+
+/// Encrypt `plaintext` under a key derived from `password`
+///
+/// # Arguments
+/// * `password` - The password
+/// * `plaintext` - The plaintext data to encrypt
+/// * `aad` - Additional authenticated data (not stored)
+/// * `params` - Argon2id cost parameters, recorded in the output
+/// * `key_size` - Size of the derived key
+///
+/// # Returns
+/// The header (parameters and a fresh random salt) followed by an envelope
+///
+/// # Errors
+/// Returns `KdfError::EmptyPassword` for an empty password and
+/// `KdfError::InvalidParams` if the parameters are out of range
+#[cfg(feature = "std")]
+pub fn encrypt_with_password(
+    password: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    params: &KdfParams,
+    key_size: KeySize,
+) -> Result<Vec<u8>, KdfError> {
+    let mut salt = [0u8; SALT_LENGTH];
+    rand::rngs::OsRng.try_fill_bytes(&mut salt).unwrap(); // CSPRNG
+    let key = derive_key(password, &salt, params, key_size)?;
+
+    let mut data = header_bytes(params, &salt);
+    // The envelope's AAD always starts with the header, so whether the
+    // caller's part is empty is recorded explicitly
+    let sealed = envelope::seal_with_header(
+        &key,
+        None,
+        !aad.is_empty(),
+        &generate_nonce(),
+        plaintext,
+        &envelope_aad(&data, aad),
+    )
+    .map_err(EnvelopeError::from)?;
+    data.extend_from_slice(&sealed);
+    Ok(data)
+}
+
+/// Decrypt data written by [`encrypt_with_password`]
+///
+/// # Arguments
+/// * `password` - The password
+/// * `data` - The header and envelope
+/// * `aad` - Additional authenticated data (must match what was used for encryption)
+///
+/// # Returns
+/// The decrypted plaintext
+///
+/// # Errors
+/// Returns `KdfError::NotPasswordEncrypted`, `UnsupportedVersion` or
+/// `Malformed` for a bad header, `KdfError::InvalidParams` if the recorded
+/// parameters exceed the limits, and `KdfError::Envelope` if the password
+/// or the AAD is wrong
+pub fn decrypt_with_password(
+    password: &[u8],
+    data: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, KdfError> {
+    let (params, header, sealed) = parse_header(data)?;
+    let envelope_header = envelope::inspect(sealed)?;
+    if envelope_header.aad_required && aad.is_empty() {
+        return Err(EnvelopeError::AadRequired.into());
+    }
+    let key_size = envelope_header.key_size;
+    let salt = &header[HEADER_LENGTH - SALT_LENGTH..];
+    let key = derive_key(password, salt, &params, key_size)?;
+    envelope::open(&key, sealed, &envelope_aad(header, aad)).map_err(|err| match err {
+        // The envelope's AAD starts with the header; report the caller's part
+        EnvelopeError::AadLengthMismatch { envelope, aad } => {
+            KdfError::Envelope(EnvelopeError::AadLengthMismatch {
+                envelope: envelope.saturating_sub(HEADER_LENGTH as u64),
+                aad: aad - HEADER_LENGTH as u64,
+            })
+        }
+        err => err.into(),
+    })
+}
+
+/// Whether `data` starts like password-encrypted data (its magic)
+pub fn is_password_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
@@ -0,0 +1,289 @@
+//! Several keys under short IDs, for decrypting across key rotations
+//!
+//! A [`Keyring`] encrypts with its primary key and records that key's ID in
+//! the [envelope](crate::envelope) header. [`Keyring::decrypt`] reads the ID
+//! back and uses the matching key, so data encrypted before a rotation still
+//! decrypts once the new key is added, without trying every key in turn.
+//!
+//! # Security Notes
+//! - Key IDs are stored in the clear in every envelope; do not put anything
+//!   secret in them
+//! - The ID only selects the key; the envelope must still authenticate under
+//!   it, and an envelope whose ID names the wrong key simply fails
+
+use crate::envelope::{self, EnvelopeError};
+#[cfg(feature = "std")]
+use crate::generate_nonce;
+use crate::{key_fingerprint, CryptoError, ErrorClass, KeyFingerprint};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use zeroize::Zeroizing;
+
+/// Longest key ID in bytes
+pub const MAX_KEY_ID_LENGTH: usize = 32;
+
+/// Whether `id` can be used as a key ID: 1 to [`MAX_KEY_ID_LENGTH`] ASCII
+/// letters, digits, `-`, `_` or `.`
+pub fn is_valid_key_id(id: &str) -> bool {
+    (1..=MAX_KEY_ID_LENGTH).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+/// Errors returned by [`Keyring`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyringError {
+    /// The key ID is empty, too long or has characters other than ASCII
+    /// letters, digits, `-`, `_` and `.`
+    InvalidKeyId(String),
+    /// A key with this ID is already in the keyring
+    DuplicateKeyId(String),
+    /// The keyring has no key to encrypt with
+    Empty,
+    /// The envelope records no key ID, so it was not sealed by a keyring
+    MissingKeyId,
+    /// The envelope was sealed under a key ID this keyring does not hold
+    UnknownKeyId(String),
+    /// The key has an invalid size
+    Crypto(CryptoError),
+    /// The envelope is malformed or does not authenticate under the key its
+    /// ID names
+    Envelope(EnvelopeError),
+}
+
+impl KeyringError {
+    /// The broad category of this error
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            KeyringError::Crypto(e) => e.classify(),
+            KeyringError::Envelope(e) => e.classify(),
+            KeyringError::InvalidKeyId(_)
+            | KeyringError::DuplicateKeyId(_)
+            | KeyringError::Empty
+            | KeyringError::MissingKeyId
+            | KeyringError::UnknownKeyId(_) => ErrorClass::InvalidInput,
+        }
+    }
+}
+
+impl fmt::Display for KeyringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyringError::InvalidKeyId(id) => write!(
+                f,
+                "Invalid key ID `{}`: use 1 to {} ASCII letters, digits, `-`, `_` or `.`",
+                id, MAX_KEY_ID_LENGTH
+            ),
+            KeyringError::DuplicateKeyId(id) => write!(f, "Key ID `{}` is already in use", id),
+            KeyringError::Empty => write!(f, "The keyring holds no keys"),
+            KeyringError::MissingKeyId => write!(
+                f,
+                "The envelope records no key ID; it was not encrypted with a keyring"
+            ),
+            KeyringError::UnknownKeyId(id) => {
+                write!(
+                    f,
+                    "The envelope was encrypted under unknown key ID `{}`",
+                    id
+                )
+            }
+            KeyringError::Crypto(e) => write!(f, "{}", e),
+            KeyringError::Envelope(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KeyringError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KeyringError::Crypto(e) => Some(e),
+            KeyringError::Envelope(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<CryptoError> for KeyringError {
+    fn from(err: CryptoError) -> Self {
+        KeyringError::Crypto(err)
+    }
+}
+
+impl From<EnvelopeError> for KeyringError {
+    fn from(err: EnvelopeError) -> Self {
+        KeyringError::Envelope(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<KeyringError> for std::io::Error {
+    fn from(err: KeyringError) -> Self {
+        let kind = match err.classify() {
+            ErrorClass::AuthFailure => std::io::ErrorKind::InvalidData,
+            ErrorClass::InvalidInput => std::io::ErrorKind::InvalidInput,
+            ErrorClass::Resource => std::io::ErrorKind::Unsupported,
+            ErrorClass::Io | ErrorClass::Internal => std::io::ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+struct KeyringKey {
+    id: String,
+    key: Zeroizing<Vec<u8>>,
+    fingerprint: KeyFingerprint,
+}
+
+/// Keys tagged with IDs; encrypts with the primary key and decrypts with
+/// whichever key the envelope names
+///
+/// The primary key is the one added last, unless [`Keyring::set_primary`]
+/// picked another.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use aes_gcm_siv_impl::Keyring;
+///
+/// let mut keyring = Keyring::new();
+/// keyring.add("2026q3", &[0x11; 32])?;
+/// let old = keyring.encrypt(b"Secret message", b"")?;
+///
+/// // Rotate: new data uses the new key, old data still decrypts
+/// keyring.add("2026q4", &[0x22; 32])?;
+/// assert_eq!(keyring.decrypt(&old, b"")?, b"Secret message");
+/// # }
+/// # Ok::<(), aes_gcm_siv_impl::KeyringError>(())
+/// ```
+#[derive(Default)]
+pub struct Keyring {
+    keys: Vec<KeyringKey>,
+    primary: Option<usize>,
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field("ids", &self.ids().collect::<Vec<_>>())
+            .field("primary", &self.primary_id())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Keyring {
+    /// An empty keyring
+    pub fn new() -> Self {
+        Keyring::default()
+    }
+
+    fn find(&self, id: &str) -> Option<&KeyringKey> {
+        self.keys.iter().find(|key| key.id == id)
+    }
+
+    /// Add `key` under `id`
+    ///
+    /// # Errors
+    /// Returns `KeyringError::InvalidKeyId` or `DuplicateKeyId` for an
+    /// unusable ID and `KeyringError::Crypto` if key length is invalid
+    pub fn add(&mut self, id: &str, key: &[u8]) -> Result<(), KeyringError> {
+        if !is_valid_key_id(id) {
+            return Err(KeyringError::InvalidKeyId(id.to_string()));
+        }
+        if self.find(id).is_some() {
+            return Err(KeyringError::DuplicateKeyId(id.to_string()));
+        }
+        self.keys.push(KeyringKey {
+            id: id.to_string(),
+            key: Zeroizing::new(key.to_vec()),
+            fingerprint: key_fingerprint(key)?,
+        });
+        Ok(())
+    }
+
+    /// Make the key with `id` the one [`Keyring::encrypt`] uses
+    ///
+    /// # Errors
+    /// Returns `KeyringError::UnknownKeyId` if no key has this ID
+    pub fn set_primary(&mut self, id: &str) -> Result<(), KeyringError> {
+        let index = self
+            .keys
+            .iter()
+            .position(|key| key.id == id)
+            .ok_or_else(|| KeyringError::UnknownKeyId(id.to_string()))?;
+        self.primary = Some(index);
+        Ok(())
+    }
+
+    fn primary(&self) -> Option<&KeyringKey> {
+        match self.primary {
+            Some(index) => self.keys.get(index),
+            None => self.keys.last(),
+        }
+    }
+
+    /// ID of the key [`Keyring::encrypt`] uses, or `None` if the keyring is
+    /// empty
+    pub fn primary_id(&self) -> Option<&str> {
+        self.primary().map(|key| key.id.as_str())
+    }
+
+    /// The key IDs, in the order the keys were added
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|key| key.id.as_str())
+    }
+
+    /// Fingerprint of the key with `id`
+    pub fn fingerprint(&self, id: &str) -> Option<KeyFingerprint> {
+        self.find(id).map(|key| key.fingerprint)
+    }
+
+    /// Number of keys
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether the keyring holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Encrypt `plaintext` into an envelope under the primary key and a
+    /// random nonce, recording the key's ID
+    ///
+    /// # Errors
+    /// Returns `KeyringError::Empty` if the keyring holds no keys
+    #[cfg(feature = "std")]
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, KeyringError> {
+        let primary = self.primary().ok_or(KeyringError::Empty)?;
+        Ok(envelope::seal_with_header(
+            &primary.key,
+            Some(&primary.id),
+            !aad.is_empty(),
+            &generate_nonce(),
+            plaintext,
+            aad,
+        )?)
+    }
+
+    /// Decrypt an envelope written by [`Keyring::encrypt`] with the key its
+    /// header names
+    ///
+    /// # Errors
+    /// Returns `KeyringError::MissingKeyId` if the envelope records no key
+    /// ID, `KeyringError::UnknownKeyId` if this keyring has no key with that
+    /// ID, and `KeyringError::Envelope` if the envelope is malformed or fails
+    /// authentication under the named key
+    pub fn decrypt(&self, envelope: &[u8], aad: &[u8]) -> Result<Vec<u8>, KeyringError> {
+        let key_id = envelope::inspect(envelope)?
+            .key_id
+            .ok_or(KeyringError::MissingKeyId)?;
+        let key = self
+            .find(&key_id)
+            .ok_or(KeyringError::UnknownKeyId(key_id))?;
+        Ok(envelope::open(&key.key, envelope, aad)?)
+    }
+}
@@ -8,16 +8,81 @@
 //! - Nonces are 12 bytes (96 bits)
 //! - Maximum data size: 2^36 - 31 bytes
 //! - Uses constant-time implementations from RustCrypto
+//!
+//! # Formats
+//! Envelopes ([`envelope`]), password-encrypted data ([`kdf`]), streams
+//! (`stream`) and block files (`block_file`) each start with their own
+//! magic, and none is a prefix of another; `is_envelope`,
+//! `is_password_encrypted`, `is_stream` and `is_block_file` check the magic
+//! in full. Raw ciphertext from [`Cipher::encrypt`] has no header and cannot
+//! be recognised: it may by chance start like any of them.
+//!
+//! # Features
+//! - `std` (default): random nonces and keys from the operating system
+//!   (`generate_nonce`, `seal`, ...), `std::error::Error` impls and
+//!   `std::io` integration, and the file and stream APIs (`block_file`,
+//!   `cache`, `migrate`, `stream`, `backend`). Without it the crate is
+//!   `no_std` and needs only `alloc`, for embedded and
+//!   `wasm32-unknown-unknown` targets; nonces then have to come from the
+//!   caller.
+//! - `serde`: `Serialize` and `Deserialize` for [`SealedBlob`], as raw
+//!   bytes or, for human-readable formats, base64
+//! - `cli` (default): the `aes-gcm-siv-impl` command-line tool
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 
-use aes_gcm_siv::{
-    aead::{Aead, KeyInit, Payload},
-    Aes128GcmSiv, Aes256GcmSiv, Nonce,
-};
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use aes_gcm_siv::Nonce;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
 use rand::TryRngCore;
-use std::fmt;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "std")]
+use std::io;
+use zeroize::Zeroize;
+
+#[cfg(feature = "std")]
+pub mod backend;
+#[cfg(feature = "std")]
+pub mod block_file;
+#[cfg(feature = "std")]
+pub mod cache;
+pub mod cipher;
+pub mod confirm;
+pub mod envelope;
+pub mod kdf;
+pub mod keyring;
+#[cfg(feature = "std")]
+pub mod migrate;
+pub mod sealed_blob;
+#[cfg(feature = "std")]
+pub mod stream;
+
+#[cfg(feature = "std")]
+pub use backend::{backend_info, BackendError, BackendInfo, BackendRequirement};
+#[cfg(feature = "std")]
+pub use block_file::{BlockCipherFile, BlockFileError};
+#[cfg(feature = "std")]
+pub use cache::DecryptCache;
+pub use cipher::Cipher;
+#[cfg(feature = "std")]
+pub use confirm::key_confirmation_challenge;
+pub use confirm::key_confirmation_respond;
+#[cfg(feature = "std")]
+pub use envelope::seal;
+pub use envelope::{open, EnvelopeError};
+#[cfg(feature = "std")]
+pub use kdf::encrypt_with_password;
+pub use kdf::{decrypt_with_password, KdfError, KdfParams};
+pub use keyring::{Keyring, KeyringError};
+pub use sealed_blob::SealedBlob;
+#[cfg(feature = "std")]
+pub use stream::{DecryptingReader, DecryptorOptions, EncryptingWriter, StreamError};
 
 /// Fixed nonce length in bytes (12 bytes/96 bits)
 pub const NONCE_LENGTH: usize = 12;
@@ -34,8 +99,79 @@ pub enum KeySize {
     Aes256,
 }
 
+impl KeySize {
+    /// Determine the key size from a key length in bytes
+    ///
+    /// # Errors
+    /// Returns `CryptoError::InvalidKeySize` unless `len` is 16 or 32
+    pub fn from_key_len(len: usize) -> CryptoResult<Self> {
+        match len {
+            16 => Ok(KeySize::Aes128),
+            32 => Ok(KeySize::Aes256),
+            _ => Err(CryptoError::InvalidKeySize),
+        }
+    }
+
+    /// Key length in bytes
+    pub const fn key_len(self) -> usize {
+        match self {
+            KeySize::Aes128 => 16,
+            KeySize::Aes256 => 32,
+        }
+    }
+
+    /// Canonical algorithm name (`aes128-gcm-siv` or `aes256-gcm-siv`)
+    pub const fn algorithm_name(self) -> &'static str {
+        match self {
+            KeySize::Aes128 => "aes128-gcm-siv",
+            KeySize::Aes256 => "aes256-gcm-siv",
+        }
+    }
+}
+
+impl fmt::Display for KeySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.algorithm_name())
+    }
+}
+
+/// Length of a key fingerprint in bytes
+pub const FINGERPRINT_LENGTH: usize = 8;
+
+/// Domain separation prefix hashed in front of the key
+const FINGERPRINT_DOMAIN: &[u8] = b"aes-gcm-siv-impl key fingerprint v1\0";
+
+/// Short, non-secret identifier of a key
+///
+/// Displayed as 16 lowercase hex characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyFingerprint([u8; FINGERPRINT_LENGTH]);
+
+impl KeyFingerprint {
+    /// Raw fingerprint bytes
+    pub fn as_bytes(&self) -> &[u8; FINGERPRINT_LENGTH] {
+        &self.0
+    }
+}
+
+impl fmt::Display for KeyFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
 /// Error types for encryption/decryption operations
+///
+/// Like every error enum in this crate, `CryptoError` is
+/// `#[non_exhaustive]`: new failure modes can be added without a breaking
+/// release, so a `match` on it needs a wildcard arm. Prefer
+/// [`CryptoError::classify`] and the `is_*` helpers where the category is all
+/// that matters.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum CryptoError {
     /// Authentication failed during decryption
     Auth,
@@ -55,8 +191,123 @@ impl fmt::Display for CryptoError {
     }
 }
 
+impl CryptoError {
+    /// Short, stable snake_case code for this error
+    ///
+    /// Unlike the `Display` text these codes never change, so they are safe to
+    /// store in logs and match on in other languages. Each is at most 24 bytes.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            CryptoError::Auth => "auth_failed",
+            CryptoError::InvalidKeySize => "invalid_key_size",
+            CryptoError::InvalidNonceSize => "invalid_nonce_size",
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl std::error::Error for CryptoError {}
 
+/// Broad category of an error
+///
+/// Lets callers decide whether retrying can help, and gives the CLI a single
+/// mapping from errors to exit codes, without matching on individual
+/// variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// The data failed authentication; retrying with the same input won't help
+    AuthFailure,
+    /// A caller-supplied value (key, nonce, option, format) is malformed
+    InvalidInput,
+    /// Reading or writing failed; may succeed if retried
+    Io,
+    /// The environment lacks something the operation needs (hardware
+    /// support, randomness, memory); may succeed later or elsewhere
+    Resource,
+    /// A bug or an unexpected condition
+    Internal,
+}
+
+impl ErrorClass {
+    /// Whether retrying the operation might succeed
+    pub fn is_transient(self) -> bool {
+        matches!(self, ErrorClass::Io | ErrorClass::Resource)
+    }
+}
+
+impl CryptoError {
+    /// The broad category of this error
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            CryptoError::Auth => ErrorClass::AuthFailure,
+            CryptoError::InvalidKeySize | CryptoError::InvalidNonceSize => ErrorClass::InvalidInput,
+        }
+    }
+
+    /// Whether this is an authentication failure
+    pub fn is_auth(&self) -> bool {
+        self.classify() == ErrorClass::AuthFailure
+    }
+
+    /// Whether a caller-supplied value was malformed
+    pub fn is_invalid_input(&self) -> bool {
+        self.classify() == ErrorClass::InvalidInput
+    }
+
+    /// Whether retrying the operation might succeed
+    pub fn is_transient(&self) -> bool {
+        self.classify().is_transient()
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<CryptoError> for io::Error {
+    fn from(err: CryptoError) -> Self {
+        let kind = match err.classify() {
+            ErrorClass::AuthFailure => io::ErrorKind::InvalidData,
+            ErrorClass::InvalidInput => io::ErrorKind::InvalidInput,
+            ErrorClass::Resource => io::ErrorKind::Unsupported,
+            ErrorClass::Io | ErrorClass::Internal => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Classify an I/O error
+///
+/// An error of this crate ([`CryptoError`], [`EnvelopeError`], ...) wrapped in
+/// the I/O error (see their `From` impls) keeps its own class; anything else
+/// is classified by its [`io::ErrorKind`].
+#[cfg(feature = "std")]
+pub fn classify_io_error(err: &io::Error) -> ErrorClass {
+    if let Some(crypto) = err.get_ref().and_then(|e| e.downcast_ref::<CryptoError>()) {
+        return crypto.classify();
+    }
+    if let Some(envelope) = err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<EnvelopeError>())
+    {
+        return envelope.classify();
+    }
+    if let Some(kdf) = err.get_ref().and_then(|e| e.downcast_ref::<KdfError>()) {
+        return kdf.classify();
+    }
+    if let Some(keyring) = err.get_ref().and_then(|e| e.downcast_ref::<KeyringError>()) {
+        return keyring.classify();
+    }
+    if let Some(stream) = err.get_ref().and_then(|e| e.downcast_ref::<StreamError>()) {
+        return stream.classify();
+    }
+    if let Some(backend) = err.get_ref().and_then(|e| e.downcast_ref::<BackendError>()) {
+        return backend.classify();
+    }
+    match err.kind() {
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => ErrorClass::InvalidInput,
+        io::ErrorKind::OutOfMemory | io::ErrorKind::Unsupported => ErrorClass::Resource,
+        _ => ErrorClass::Io,
+    }
+}
+
 /// Result type for cryptographic operations
 pub type CryptoResult<T> = Result<T, CryptoError>;
 
@@ -75,49 +326,21 @@ pub type CryptoResult<T> = Result<T, CryptoError>;
 /// - Never reuse a nonce with the same key
 /// - The nonce should be randomly generated for each encryption operation
 ///
+/// Callers encrypting many messages under one key should use [`Cipher`],
+/// which expands the key once.
+///
 /// # Errors
 /// Returns `CryptoError` if key or nonce length is invalid
+#[cfg_attr(
+    feature = "strict-deprecations",
+    deprecated(note = "use `Cipher::new(key)?.encrypt(nonce, plaintext, aad)`")
+)]
 pub fn encrypt(key: &[u8], nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> CryptoResult<Vec<u8>> {
     if nonce.len() != NONCE_LENGTH {
         return Err(CryptoError::InvalidNonceSize);
     }
 
-    let key_size = match key.len() {
-        16 => KeySize::Aes128,
-        32 => KeySize::Aes256,
-        _ => return Err(CryptoError::InvalidKeySize),
-    };
-
-    let nonce_array = Nonce::from_slice(nonce);
-
-    match key_size {
-        KeySize::Aes128 => {
-            let cipher =
-                Aes128GcmSiv::new_from_slice(key).map_err(|_| CryptoError::InvalidKeySize)?;
-            cipher
-                .encrypt(
-                    nonce_array,
-                    Payload {
-                        msg: plaintext,
-                        aad,
-                    },
-                )
-                .map_err(|_| CryptoError::Auth)
-        }
-        KeySize::Aes256 => {
-            let cipher =
-                Aes256GcmSiv::new_from_slice(key).map_err(|_| CryptoError::InvalidKeySize)?;
-            cipher
-                .encrypt(
-                    nonce_array,
-                    Payload {
-                        msg: plaintext,
-                        aad,
-                    },
-                )
-                .map_err(|_| CryptoError::Auth)
-        }
-    }
+    Cipher::new(key)?.encrypt(nonce, plaintext, aad)
 }
 
 /// Decrypt ciphertext using AES-GCM-SIV
@@ -134,53 +357,96 @@ pub fn encrypt(key: &[u8], nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> Crypto
 /// # Errors
 /// Returns `CryptoError::Auth` if authentication fails or
 /// `CryptoError::InvalidKeySize` if key is invalid
+#[cfg_attr(
+    feature = "strict-deprecations",
+    deprecated(note = "use `Cipher::new(key)?.decrypt(nonce, ciphertext, aad)`")
+)]
 pub fn decrypt(key: &[u8], nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> CryptoResult<Vec<u8>> {
     if nonce.len() != NONCE_LENGTH {
         return Err(CryptoError::InvalidNonceSize);
     }
 
-    let key_size = match key.len() {
-        16 => KeySize::Aes128,
-        32 => KeySize::Aes256,
-        _ => return Err(CryptoError::InvalidKeySize),
-    };
+    Cipher::new(key)?.decrypt(nonce, ciphertext, aad)
+}
 
-    let nonce_array = Nonce::from_slice(nonce);
-
-    match key_size {
-        KeySize::Aes128 => {
-            let cipher =
-                Aes128GcmSiv::new_from_slice(key).map_err(|_| CryptoError::InvalidKeySize)?;
-            cipher
-                .decrypt(
-                    nonce_array,
-                    Payload {
-                        msg: ciphertext,
-                        aad,
-                    },
-                )
-                .map_err(|_| CryptoError::Auth)
-        }
-        KeySize::Aes256 => {
-            let cipher =
-                Aes256GcmSiv::new_from_slice(key).map_err(|_| CryptoError::InvalidKeySize)?;
-            cipher
-                .decrypt(
-                    nonce_array,
-                    Payload {
-                        msg: ciphertext,
-                        aad,
-                    },
-                )
-                .map_err(|_| CryptoError::Auth)
+/// Decrypt ciphertext into a caller-provided buffer using AES-GCM-SIV
+///
+/// `buf` is cleared and refilled with the plaintext, growing only when its
+/// capacity is too small, so a loop decrypting many records with the same
+/// buffer allocates at most a handful of times.
+///
+/// # Arguments
+/// * `key` - The encryption key (must be 16 or 32 bytes)
+/// * `nonce` - The nonce (must be 12 bytes)
+/// * `ciphertext` - The ciphertext data with authentication tag appended
+/// * `aad` - Additional authenticated data (must match what was used for encryption)
+/// * `buf` - Scratch buffer that receives the plaintext
+///
+/// # Returns
+/// The plaintext length, which is also `buf.len()` on success
+///
+/// # Security Notes
+/// - On any error the whole buffer (including spare capacity) is zeroized and
+///   left empty, so plaintext from a previous call never survives into an
+///   error path
+///
+/// Callers decrypting many records under one key should use
+/// [`Cipher::decrypt_into_buf`], which expands the key once.
+///
+/// # Errors
+/// Returns `CryptoError::Auth` if authentication fails,
+/// `CryptoError::InvalidKeySize` if key is invalid or
+/// `CryptoError::InvalidNonceSize` if nonce is invalid
+#[cfg_attr(
+    feature = "strict-deprecations",
+    deprecated(note = "use `Cipher::new(key)?.decrypt_into_buf(nonce, ciphertext, aad, buf)`")
+)]
+pub fn decrypt_into_buf(
+    key: &[u8],
+    nonce: &[u8],
+    ciphertext: &[u8],
+    aad: &[u8],
+    buf: &mut Vec<u8>,
+) -> CryptoResult<usize> {
+    let cipher = if nonce.len() != NONCE_LENGTH {
+        Err(CryptoError::InvalidNonceSize)
+    } else {
+        Cipher::new(key)
+    };
+    match cipher {
+        Ok(cipher) => cipher.decrypt_into_buf(nonce, ciphertext, aad, buf),
+        Err(e) => {
+            buf.zeroize();
+            Err(e)
         }
     }
 }
 
+/// Compute the fingerprint of a key
+///
+/// The fingerprint is the truncated SHA-256 of the key under a fixed domain
+/// separation prefix. It identifies a key without revealing it, but is only 64
+/// bits long and must not be relied on as a commitment.
+///
+/// # Errors
+/// Returns `CryptoError::InvalidKeySize` if key length is invalid
+pub fn key_fingerprint(key: &[u8]) -> CryptoResult<KeyFingerprint> {
+    KeySize::from_key_len(key.len())?;
+
+    let digest = Sha256::new()
+        .chain_update(FINGERPRINT_DOMAIN)
+        .chain_update(key)
+        .finalize();
+    let mut fingerprint = [0u8; FINGERPRINT_LENGTH];
+    fingerprint.copy_from_slice(&digest[..FINGERPRINT_LENGTH]);
+    Ok(KeyFingerprint(fingerprint))
+}
+
 /// Generate a random nonce suitable for AES-GCM-SIV
 ///
 /// # Returns
 /// A 12-byte random nonce
+#[cfg(feature = "std")]
 pub fn generate_nonce() -> Vec<u8> {
     let mut os_rng = rand::rngs::OsRng;
     let mut unique_seed = [0u8; NONCE_LENGTH];
@@ -188,3 +454,14 @@ pub fn generate_nonce() -> Vec<u8> {
 
     Nonce::from_slice(&unique_seed).to_vec()
 }
+
+/// Generate a random key
+///
+/// # Returns
+/// A 16- or 32-byte random key, zeroized when dropped
+#[cfg(feature = "std")]
+pub fn generate_key(key_size: KeySize) -> zeroize::Zeroizing<Vec<u8>> {
+    let mut key = zeroize::Zeroizing::new(alloc::vec![0u8; key_size.key_len()]);
+    rand::rngs::OsRng.try_fill_bytes(&mut key).unwrap(); // CSPRNG
+    key
+}
@@ -2,11 +2,20 @@
 
 #![forbid(unsafe_code)]
 
-use aes_gcm_siv_impl::{decrypt, encrypt, NONCE_LENGTH};
-use clap::{Parser, Subcommand};
+mod cli;
+
+use aes_gcm_siv_impl::{
+    backend_info, envelope, kdf, key_fingerprint, stream, BackendRequirement, Cipher,
+    DecryptingReader, EncryptingWriter, EnvelopeError, ErrorClass, KdfParams, KeyFingerprint,
+    KeySize, Keyring, KeyringError, NONCE_LENGTH,
+};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use cli::profiles::Profile;
 use std::fs;
 use std::io::{self, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use zeroize::Zeroizing;
 
 #[derive(Parser)]
 #[command(
@@ -17,6 +26,433 @@ use std::path::PathBuf;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+
+    /// Take default options from this profile in profiles.toml; options given
+    /// on the command line win
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+}
+
+/// Hardware acceleration requirements
+#[derive(Args)]
+struct BackendArgs {
+    /// Refuse to encrypt or decrypt unless AES and POLYVAL are hardware accelerated
+    #[arg(long, global = true)]
+    require_hardware_aes: bool,
+
+    /// Run on the (much slower) software implementation even with --require-hardware-aes
+    #[arg(long, global = true)]
+    allow_software_aes: bool,
+}
+
+impl BackendArgs {
+    fn apply_profile(&mut self, profile: &Profile) {
+        self.require_hardware_aes |= profile.require_hardware_aes;
+    }
+
+    fn requirement(&self) -> BackendRequirement {
+        if self.require_hardware_aes && !self.allow_software_aes {
+            BackendRequirement::HardwareAes
+        } else {
+            BackendRequirement::Any
+        }
+    }
+
+    /// Refuse to start if the requirement is not met by this CPU.
+    fn check(&self) -> io::Result<()> {
+        self.requirement().check(&backend_info()).map_err(|e| {
+            cli::fail(
+                e.classify(),
+                format!(
+                    "{}; refusing to start. Pass --allow-software-aes to run \
+                     on the software implementation anyway",
+                    e
+                ),
+            )
+        })
+    }
+}
+
+/// Additional authenticated data options.
+///
+/// At most one of these may be given. When none is given the AAD is empty,
+/// which is exactly equivalent to `--aad ""`.
+#[derive(Args)]
+#[group(multiple = false)]
+struct AadArgs {
+    /// Additional authenticated data as a UTF-8 string (empty if no AAD option is given)
+    #[arg(short, long, value_name = "STRING")]
+    aad: Option<String>,
+
+    /// Hex-encoded additional authenticated data (for binary AAD)
+    #[arg(long, value_name = "HEX")]
+    aad_hex: Option<String>,
+
+    /// Read additional authenticated data verbatim from a file
+    #[arg(long, value_name = "PATH")]
+    aad_file: Option<PathBuf>,
+}
+
+impl AadArgs {
+    /// Take the profile's AAD unless an AAD option was given.
+    fn apply_profile(&mut self, profile: &Profile) {
+        if self.aad.is_none() && self.aad_hex.is_none() && self.aad_file.is_none() {
+            self.aad = profile.aad.clone();
+            self.aad_hex = profile.aad_hex.clone();
+            self.aad_file = profile.aad_file.clone();
+        }
+    }
+
+    /// Resolve the AAD bytes; no option at all means empty AAD.
+    fn resolve(&self) -> io::Result<Vec<u8>> {
+        if let Some(aad) = &self.aad {
+            return Ok(aad.as_bytes().to_vec());
+        }
+        if let Some(aad_hex) = &self.aad_hex {
+            return decode_hex("AAD", aad_hex);
+        }
+        if let Some(path) = &self.aad_file {
+            return fs::read(path);
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// Input format options of commands that decrypt a file
+#[derive(Args)]
+struct RawArgs {
+    /// The input is bare ciphertext (from `encrypt --raw`), not an envelope
+    #[arg(long, requires = "nonce")]
+    raw: bool,
+
+    /// Hex-encoded nonce of raw input (24 characters for 96-bit nonce); implies --raw
+    #[arg(short, long)]
+    nonce: Option<String>,
+}
+
+impl RawArgs {
+    /// The nonce of raw input, or `None` if the input is an envelope.
+    fn nonce(&self) -> io::Result<Option<Vec<u8>>> {
+        self.nonce.as_deref().map(decode_nonce).transpose()
+    }
+}
+
+/// Key options; at most one may be given.
+#[derive(Args)]
+#[group(id = "key_args", multiple = false)]
+struct KeyArgs {
+    /// Hex-encoded key (32 or 64 characters for 128-bit or 256-bit key)
+    #[arg(short, long)]
+    key: Option<String>,
+
+    /// Read the hex-encoded key from a file
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+
+    /// Read the hex-encoded key from an environment variable
+    #[arg(long, value_name = "VAR")]
+    key_env: Option<String>,
+}
+
+impl KeyArgs {
+    fn is_empty(&self) -> bool {
+        self.key.is_none() && self.key_file.is_none() && self.key_env.is_none()
+    }
+
+    /// Take the profile's key reference unless a key option was given.
+    fn apply_profile(&mut self, profile: &Profile) {
+        if self.is_empty() {
+            self.key_file = profile.key_file.clone();
+            self.key_env = profile.key_env.clone();
+        }
+    }
+
+    /// Resolve the key bytes, or `None` if no key option is set.
+    fn resolve(&self) -> io::Result<Option<Vec<u8>>> {
+        if let Some(key) = &self.key {
+            return decode_hex("key", key).map(Some);
+        }
+        if let Some(path) = &self.key_file {
+            let text = fs::read_to_string(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            return decode_hex("key", text.trim()).map(Some);
+        }
+        if let Some(var) = &self.key_env {
+            let value = std::env::var(var).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("environment variable {} is not set", var),
+                )
+            })?;
+            return decode_hex("key", value.trim()).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Resolve the key bytes, failing if no key option is set.
+    fn require(&self) -> io::Result<Vec<u8>> {
+        self.resolve()?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no key given; pass --key, --key-file or --key-env, or use a profile that sets one",
+            )
+        })
+    }
+}
+
+/// Password options; at most one may be given.
+#[derive(Args)]
+#[group(id = "password_args", multiple = false, conflicts_with_all = ["key_args", "raw", "nonce"])]
+struct PasswordArgs {
+    /// Derive the key from a password prompted for on the terminal
+    #[arg(long)]
+    password: bool,
+
+    /// Derive the key from the password in this file (its first line)
+    #[arg(long, value_name = "PATH")]
+    password_file: Option<PathBuf>,
+}
+
+impl PasswordArgs {
+    /// Read the password, or `None` if no password option is set. With
+    /// `confirm` a prompted password has to be typed twice.
+    fn resolve(&self, confirm: bool) -> io::Result<Option<Zeroizing<String>>> {
+        if let Some(path) = &self.password_file {
+            let text = Zeroizing::new(
+                fs::read_to_string(path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+            );
+            let line = text.lines().next().unwrap_or_default();
+            return Ok(Some(Zeroizing::new(line.to_string())));
+        }
+        if !self.password {
+            return Ok(None);
+        }
+        if !cli::gui_decrypt::can_prompt() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--password needs a terminal to prompt on; use --password-file",
+            ));
+        }
+        let password = Zeroizing::new(rpassword::prompt_password("Password: ")?);
+        if confirm {
+            let again = Zeroizing::new(rpassword::prompt_password("Repeat password: ")?);
+            if *again != *password {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the passwords do not match",
+                ));
+            }
+        }
+        Ok(Some(password))
+    }
+}
+
+/// Argon2id cost options for `encrypt --password`
+#[derive(Args)]
+struct KdfArgs {
+    /// Memory the key derivation uses, in MiB [default: 64]
+    #[arg(long, value_name = "MIB", requires = "password_args")]
+    kdf_memory: Option<u32>,
+
+    /// Passes the key derivation makes over that memory [default: 3]
+    #[arg(long, value_name = "N", requires = "password_args")]
+    kdf_iterations: Option<u32>,
+}
+
+impl KdfArgs {
+    fn params(&self) -> io::Result<KdfParams> {
+        let mut params = KdfParams::default();
+        if let Some(mib) = self.kdf_memory {
+            params.memory_kib = mib.checked_mul(1024).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "--kdf-memory is too large")
+            })?;
+        }
+        if let Some(iterations) = self.kdf_iterations {
+            params.iterations = iterations;
+        }
+        Ok(params)
+    }
+}
+
+/// Trust-on-first-use key fingerprint pinning options
+#[derive(Args)]
+struct PinArgs {
+    /// Pin file recording the key fingerprint expected for each source
+    #[arg(long, value_name = "PATH")]
+    pin_file: Option<PathBuf>,
+
+    /// Name of the source whose pin to check (recorded on first use)
+    #[arg(long, value_name = "NAME")]
+    pin_name: Option<String>,
+
+    /// Replace the pin if the key fingerprint differs from it
+    #[arg(long)]
+    update_pin: bool,
+}
+
+impl PinArgs {
+    /// Fill in the pin options the command line left out from the profile,
+    /// then check that they are complete.
+    fn apply_profile(&mut self, profile: &Profile) -> io::Result<()> {
+        if self.pin_file.is_none() {
+            self.pin_file = profile.pin_file.clone();
+        }
+        if self.pin_name.is_none() {
+            self.pin_name = profile.pin_name.clone();
+        }
+        if self.pin_file.is_some() != self.pin_name.is_some()
+            || (self.update_pin && self.pin_file.is_none())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--pin-file and --pin-name must be given together (on the command line \
+                 or in the profile)",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check the fingerprint of the key that decrypted the input, if pinning
+    /// was requested.
+    fn check(&self, fingerprint: &KeyFingerprint) -> io::Result<()> {
+        let (Some(path), Some(name)) = (&self.pin_file, &self.pin_name) else {
+            return Ok(());
+        };
+        match cli::pins::check_pin(path, name, fingerprint, self.update_pin)? {
+            cli::pins::PinOutcome::Recorded => {
+                println!("Pinned key {} for `{}`", fingerprint, name)
+            }
+            cli::pins::PinOutcome::Matched => {}
+            cli::pins::PinOutcome::Updated { previous } => println!(
+                "Updated pin for `{}`: {} -> {}",
+                name, previous, fingerprint
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Nonce reuse protection for explicitly supplied nonces
+#[derive(Args)]
+struct NonceHistoryArgs {
+    /// Record (key fingerprint, nonce) pairs given with --nonce here and refuse repeats
+    #[arg(long, value_name = "PATH")]
+    nonce_history: Option<PathBuf>,
+
+    /// Maximum number of pairs kept in the history; the oldest are dropped first
+    /// [default: 10000]
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    nonce_history_limit: Option<usize>,
+
+    /// Encrypt even though the nonce was already used with this key
+    #[arg(long)]
+    i_know_what_im_doing: bool,
+}
+
+/// Number of (key fingerprint, nonce) pairs kept unless configured otherwise
+const DEFAULT_NONCE_HISTORY_LIMIT: usize = 10_000;
+
+impl NonceHistoryArgs {
+    fn apply_profile(&mut self, profile: &Profile) {
+        if self.nonce_history.is_none() {
+            self.nonce_history = profile.nonce_history.clone();
+        }
+        if self.nonce_history_limit.is_none() {
+            self.nonce_history_limit = profile.nonce_history_limit;
+        }
+    }
+
+    /// Run `encrypt` unless the explicitly supplied nonce was already used
+    /// with `key`, recording the pair once `encrypt` succeeded.
+    fn guard<T>(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        encrypt: impl FnOnce() -> io::Result<T>,
+    ) -> io::Result<T> {
+        let Some(path) = &self.nonce_history else {
+            return encrypt();
+        };
+        let fingerprint = key_fingerprint(key)?;
+        let limit = self
+            .nonce_history_limit
+            .unwrap_or(DEFAULT_NONCE_HISTORY_LIMIT);
+        cli::nonce_history::use_nonce(path, &fingerprint, nonce, limit, |check| {
+            if check == cli::nonce_history::NonceCheck::Reused {
+                if !self.i_know_what_im_doing {
+                    return Err(cli::fail(
+                        ErrorClass::InvalidInput,
+                        format!(
+                            "refusing to encrypt: nonce {} was already used with key {} \
+                         (recorded in {}). Reusing a nonce with the same key voids the \
+                         security guarantees of AES-GCM-SIV: identical plaintexts become \
+                         recognisable to anyone who sees both ciphertexts. Use a fresh \
+                         nonce, or pass --i-know-what-im-doing to override",
+                            hex::encode(nonce),
+                            fingerprint,
+                            path.display()
+                        ),
+                    ));
+                }
+                eprintln!(
+                    "warning: nonce {} was already used with key {}",
+                    hex::encode(nonce),
+                    fingerprint
+                );
+            }
+            encrypt()
+        })
+    }
+}
+
+/// Selectable AEAD algorithms
+#[derive(Clone, Copy, ValueEnum)]
+enum Algorithm {
+    /// AES-128-GCM-SIV (16-byte key)
+    #[value(name = "aes128-gcm-siv")]
+    Aes128GcmSiv,
+    /// AES-256-GCM-SIV (32-byte key)
+    #[value(name = "aes256-gcm-siv")]
+    Aes256GcmSiv,
+}
+
+impl Algorithm {
+    /// The command-line choice, else the profile's; `None` if neither picks
+    /// one, leaving it to the key length (or AES-256-GCM-SIV without a key).
+    fn resolve(choice: Option<Algorithm>, profile: &Profile) -> io::Result<Option<Algorithm>> {
+        if let Some(algorithm) = choice {
+            return Ok(Some(algorithm));
+        }
+        match &profile.algorithm {
+            Some(name) => Algorithm::from_str(name, false).map(Some).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("profile sets unknown algorithm `{}`", name),
+                )
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Key size for a new key or password-derived key
+    fn generated_key_size(algorithm: Option<Algorithm>) -> KeySize {
+        algorithm.map_or(KeySize::Aes256, Algorithm::key_size)
+    }
+
+    fn key_size(self) -> KeySize {
+        match self {
+            Algorithm::Aes128GcmSiv => KeySize::Aes128,
+            Algorithm::Aes256GcmSiv => KeySize::Aes256,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -29,17 +465,91 @@ enum Commands {
         /// Output file for ciphertext
         output: PathBuf,
 
-        /// Hex-encoded key (32 or 64 characters for 128-bit or 256-bit key)
-        #[arg(short, long)]
-        key: String,
+        #[command(flatten)]
+        key: KeyArgs,
+
+        #[command(flatten)]
+        password: PasswordArgs,
+
+        #[command(flatten)]
+        kdf: KdfArgs,
+
+        /// Encrypt with the primary key of this keyring file, recording its key ID
+        #[arg(
+            long,
+            value_name = "PATH",
+            conflicts_with_all = ["key_args", "password_args", "algorithm", "nonce", "stream", "raw", "receipt"]
+        )]
+        keyring: Option<PathBuf>,
 
         /// Hex-encoded nonce (24 characters for 96-bit nonce)
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "password_args")]
         nonce: Option<String>,
 
-        /// Additional authenticated data
-        #[arg(short, long)]
-        aad: Option<String>,
+        /// Encrypt in segments with bounded memory, for large files
+        ///
+        /// The output starts with a header holding the random nonce prefix,
+        /// so no nonce is given or printed. Decrypt with `decrypt --stream`.
+        #[arg(long, conflicts_with_all = ["nonce", "receipt", "password_args"])]
+        stream: bool,
+
+        /// Write bare ciphertext instead of an envelope
+        ///
+        /// The output then does not record the nonce, which must be kept and
+        /// passed to `decrypt --raw --nonce`. This is the format of versions
+        /// before envelopes.
+        #[arg(long, conflicts_with = "stream")]
+        raw: bool,
+
+        /// Algorithm to encrypt with; the key length must match it [default: from the key length, aes256-gcm-siv with --password]
+        #[arg(long, value_enum)]
+        algorithm: Option<Algorithm>,
+
+        #[command(flatten)]
+        aad: AadArgs,
+
+        #[command(flatten)]
+        nonce_history: NonceHistoryArgs,
+
+        /// Write a JSON receipt describing the encryption (no secrets) to this path
+        #[arg(long, value_name = "PATH", conflicts_with = "password_args")]
+        receipt: Option<PathBuf>,
+
+        /// Include the SHA-256 of the plaintext in the receipt
+        #[arg(long, requires = "receipt")]
+        receipt_plaintext_hash: bool,
+    },
+
+    /// Check an encrypted file against its receipt
+    ///
+    /// Without a key only the ciphertext size and hash are checked; with one
+    /// the key fingerprint, algorithm, AAD and plaintext are checked too.
+    Verify {
+        /// Encrypted file
+        input: PathBuf,
+
+        /// Receipt written by `encrypt --receipt`
+        #[arg(long, value_name = "PATH")]
+        receipt: PathBuf,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        /// Directory of `*.key` files; the key is picked by the fingerprint the
+        /// receipt records
+        #[arg(long, value_name = "DIR", conflicts_with = "key_args")]
+        keyring_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        aad: AadArgs,
+    },
+
+    /// Print what an envelope's header records, without decrypting it
+    ///
+    /// Needs no key: the header is readable by anyone holding the file.
+    Inspect {
+        /// Encrypted file
+        input: PathBuf,
     },
 
     /// Decrypt a file
@@ -50,111 +560,960 @@ enum Commands {
         /// Output file for plaintext
         output: PathBuf,
 
-        /// Hex-encoded key (32 or 64 characters for 128-bit or 256-bit key)
-        #[arg(short, long)]
-        key: String,
+        #[command(flatten)]
+        key: KeyArgs,
 
-        /// Hex-encoded nonce (24 characters for 96-bit nonce)
-        #[arg(short, long)]
-        nonce: String,
+        #[command(flatten)]
+        password: PasswordArgs,
 
-        /// Additional authenticated data
-        #[arg(short, long)]
-        aad: Option<String>,
+        /// Keyring file; the key is picked by the key ID the input records
+        #[arg(
+            long,
+            value_name = "PATH",
+            conflicts_with_all = ["key_args", "password_args", "keyring_dir", "raw", "nonce", "stream"]
+        )]
+        keyring: Option<PathBuf>,
+
+        /// Directory of `*.key` files (one hex key each) to choose the key from
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["key_args", "password_args"])]
+        keyring_dir: Option<PathBuf>,
+
+        /// Try every key in the keyring directory until one authenticates
+        #[arg(long)]
+        try_all_keys: bool,
+
+        #[command(flatten)]
+        raw: RawArgs,
+
+        /// Decrypt a file written by `encrypt --stream`, with bounded memory
+        ///
+        /// The output appears only once the whole input has authenticated.
+        #[arg(long, conflicts_with_all = ["raw", "nonce", "keyring_dir", "password_args"])]
+        stream: bool,
+
+        #[command(flatten)]
+        aad: AadArgs,
+
+        #[command(flatten)]
+        pin: PinArgs,
     },
 
     /// Generate a random nonce
     GenNonce,
+
+    /// Generate a random key
+    ///
+    /// The hex key is printed, or written to a new file readable only by its
+    /// owner.
+    GenKey {
+        /// Algorithm the key is for [default: aes256-gcm-siv]
+        #[arg(long, value_enum)]
+        algorithm: Option<Algorithm>,
+
+        /// Write the key to this file (which must not exist) instead of printing it
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Decrypt a file next to itself, prompting for what is missing
+    ///
+    /// Meant to be launched by a file manager for a double-clicked `.agsiv`
+    /// file: the plaintext is written alongside the input without
+    /// overwriting anything, and the key (and for raw input the nonce) is
+    /// prompted for on the terminal unless given as an option.
+    GuiDecrypt {
+        /// Encrypted file
+        input: PathBuf,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        /// The input is bare ciphertext (from `encrypt --raw`), not an envelope
+        #[arg(long)]
+        raw: bool,
+
+        /// Hex-encoded nonce of raw input (prompted for if omitted)
+        #[arg(short, long, requires = "raw")]
+        nonce: Option<String>,
+
+        #[command(flatten)]
+        aad: AadArgs,
+
+        /// Wait for Enter before exiting [default: only when launched from a file manager]
+        #[arg(long, conflicts_with = "no_wait")]
+        wait: bool,
+
+        /// Never wait for Enter before exiting
+        #[arg(long)]
+        no_wait: bool,
+    },
+
+    /// Inspect the profiles in profiles.toml
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+
+    /// Decrypt a file and hand the plaintext to a command on a pipe
+    ///
+    /// The plaintext never touches disk and never appears on a command
+    /// line: the command reads it from file descriptor `--fd` (e.g. via
+    /// `/dev/fd/3`). Exits with the command's exit status.
+    #[cfg(unix)]
+    Exec {
+        /// Input file to decrypt
+        #[arg(short, long)]
+        input: PathBuf,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        #[command(flatten)]
+        raw: RawArgs,
+
+        #[command(flatten)]
+        aad: AadArgs,
+
+        /// File descriptor the plaintext pipe is exposed on in the command
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(i32).range(3..))]
+        fd: i32,
+
+        /// Command to run, followed by its arguments
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// List the profile names
+    List,
+
+    /// Print the settings of a profile
+    Show {
+        /// Profile name
+        name: String,
+    },
+}
+
+fn decode_hex(what: &str, value: &str) -> io::Result<Vec<u8>> {
+    hex::decode(value).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid {} hex: {}", what, e),
+        )
+    })
+}
+
+fn decode_nonce(value: &str) -> io::Result<Vec<u8>> {
+    let nonce = decode_hex("nonce", value)?;
+    if nonce.len() != NONCE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Nonce must be exactly {} bytes", NONCE_LENGTH),
+        ));
+    }
+    Ok(nonce)
+}
+
+/// Point users of bare ciphertext at `--raw`, of streams at `--stream`, of
+/// password-encrypted input at `--password`, of input sealed with AAD at `--aad`, and of input from
+/// a newer format version at an upgrade.
+fn envelope_error(err: EnvelopeError, input: &[u8]) -> io::Error {
+    match err {
+        EnvelopeError::NotAnEnvelope if kdf::is_password_encrypted(input) => cli::fail(
+            ErrorClass::InvalidInput,
+            "input was encrypted with a password; pass --password or --password-file".to_string(),
+        ),
+        EnvelopeError::NotAnEnvelope if stream::is_stream(input) => cli::fail(
+            ErrorClass::InvalidInput,
+            "input is a stream (from `encrypt --stream`); decrypt it with `decrypt --stream` \
+             and the key given directly"
+                .to_string(),
+        ),
+        EnvelopeError::NotAnEnvelope => cli::fail(
+            ErrorClass::InvalidInput,
+            "input is not an envelope; bare ciphertext (from `encrypt --raw` or versions \
+             before envelopes) needs --raw --nonce HEX"
+                .to_string(),
+        ),
+        EnvelopeError::AadRequired => cli::fail(
+            ErrorClass::AuthFailure,
+            "input was encrypted with AAD; pass --aad, --aad-hex or --aad-file".to_string(),
+        ),
+        EnvelopeError::UnsupportedVersion { required, producer } => cli::fail(
+            ErrorClass::InvalidInput,
+            format!(
+                "this file requires format v{} (produced by {}); this binary supports up to v{} \
+                 -- upgrade aes-gcm-siv-impl to read it",
+                required,
+                producer,
+                envelope::ENVELOPE_VERSION
+            ),
+        ),
+        err => err.into(),
+    }
+}
+
+/// Name the keyring's IDs when the input's is not among them.
+fn keyring_error(err: KeyringError, keyring: &Keyring, path: &Path, input: &[u8]) -> io::Error {
+    match err {
+        KeyringError::Envelope(err) => envelope_error(err, input),
+        KeyringError::UnknownKeyId(id) => cli::fail(
+            ErrorClass::InvalidInput,
+            format!(
+                "input was encrypted under key ID `{}`, which {} does not list (it has: {})",
+                id,
+                path.display(),
+                keyring.ids().collect::<Vec<_>>().join(", ")
+            ),
+        ),
+        err => err.into(),
+    }
+}
+
+/// Decrypt `input`: an envelope, or bare ciphertext under `nonce` (`--raw`).
+fn open_input(key: &[u8], nonce: Option<&[u8]>, input: &[u8], aad: &[u8]) -> io::Result<Vec<u8>> {
+    match nonce {
+        Some(nonce) => Ok(Cipher::new(key)?.decrypt(nonce, input, aad)?),
+        None => aes_gcm_siv_impl::open(key, input, aad).map_err(|e| envelope_error(e, input)),
+    }
+}
+
+/// A key loaded from a keyring directory
+struct DirKey {
+    path: PathBuf,
+    fingerprint: KeyFingerprint,
+    key: Vec<u8>,
+}
+
+impl DirKey {
+    /// The key ID this key goes by: its file name without `.key`
+    fn id(&self) -> Option<&str> {
+        self.path.file_stem().and_then(|stem| stem.to_str())
+    }
+}
+
+/// Load every `*.key` file in `dir`.
+///
+/// Files holding the same key all stay listed, so each file name keeps
+/// working as a key ID; use [`distinct_keys`] to see each key once.
+/// Unreadable or malformed key files are skipped with a warning; only a
+/// missing directory or one without any usable key is an error.
+fn load_keyring_dir(dir: &Path) -> io::Result<Vec<DirKey>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", dir.display(), e)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "key"))
+        .collect();
+    paths.sort();
+
+    let mut keys: Vec<DirKey> = Vec::new();
+    for path in paths {
+        let key = match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| hex::decode(text.trim()).map_err(|e| e.to_string()))
+        {
+            Ok(key) => key,
+            Err(e) => {
+                eprintln!("warning: skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let fingerprint = match key_fingerprint(&key) {
+            Ok(fingerprint) => fingerprint,
+            Err(e) => {
+                eprintln!("warning: skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        keys.push(DirKey {
+            path,
+            fingerprint,
+            key,
+        });
+    }
+
+    if keys.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no usable *.key files in {}", dir.display()),
+        ));
+    }
+    Ok(keys)
+}
+
+/// The keys in `keys`, skipping files that repeat an earlier file's key
+fn distinct_keys(keys: &[DirKey]) -> impl Iterator<Item = &DirKey> {
+    keys.iter().enumerate().filter_map(|(i, dir_key)| {
+        (!keys[..i]
+            .iter()
+            .any(|k| k.fingerprint == dir_key.fingerprint))
+        .then_some(dir_key)
+    })
+}
+
+fn fingerprint_list(keys: &[DirKey]) -> String {
+    distinct_keys(keys)
+        .map(|k| k.fingerprint.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Decrypt `ciphertext` with a key chosen from a keyring directory.
+fn decrypt_with_keyring_dir<'k>(
+    keys: &'k [DirKey],
+    try_all_keys: bool,
+    nonce: Option<&[u8]>,
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> io::Result<(Vec<u8>, &'k DirKey)> {
+    let key_id = match nonce {
+        Some(_) => None,
+        None => {
+            envelope::inspect(ciphertext)
+                .map_err(|e| envelope_error(e, ciphertext))?
+                .key_id
+        }
+    };
+    // An envelope from a keyring records its key ID, which names the key
+    // file, so that key is the only candidate.
+    if let Some(id) = key_id {
+        let dir_key = keys
+            .iter()
+            .find(|dir_key| dir_key.id() == Some(id.as_str()))
+            .ok_or_else(|| {
+                cli::fail(
+                    ErrorClass::InvalidInput,
+                    format!(
+                        "input was encrypted under key ID `{}`, but there is no {}.key",
+                        id, id
+                    ),
+                )
+            })?;
+        let plaintext = open_input(&dir_key.key, None, ciphertext, aad)?;
+        return Ok((plaintext, dir_key));
+    }
+
+    // Raw input and envelopes sealed with --key record no key ID, so the
+    // only way to pick a key is to try them all; that must be asked for
+    // explicitly.
+    if !try_all_keys {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "input records no key ID; pass --try-all-keys to try each key \
+                 (available fingerprints: {})",
+                fingerprint_list(keys)
+            ),
+        ));
+    }
+
+    for dir_key in distinct_keys(keys) {
+        if let Ok(plaintext) = open_input(&dir_key.key, nonce, ciphertext, aad) {
+            return Ok((plaintext, dir_key));
+        }
+    }
+    Err(cli::fail(
+        ErrorClass::AuthFailure,
+        format!(
+            "no key in the keyring directory decrypts the input (available fingerprints: {})",
+            fingerprint_list(keys)
+        ),
+    ))
+}
+
+/// Encrypt `input` to `output` segment by segment (`encrypt --stream`).
+fn encrypt_stream(input: &Path, output: &Path, key: &[u8], aad: &[u8]) -> io::Result<()> {
+    let mut reader = fs::File::open(input)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", input.display(), e)))?;
+    let mut writer = EncryptingWriter::new(fs::File::create(output)?, key, aad)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.finish()?.sync_all()
+}
+
+/// Whether the file at `path` starts with a stream header.
+fn starts_like_stream(path: &Path) -> io::Result<bool> {
+    let mut start = Vec::with_capacity(stream::HEADER_LENGTH);
+    fs::File::open(path)
+        .and_then(|file| {
+            file.take(stream::HEADER_LENGTH as u64)
+                .read_to_end(&mut start)
+        })
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    Ok(stream::is_stream(&start))
+}
+
+/// Decrypt `input` to `output` segment by segment (`decrypt --stream`).
+///
+/// The plaintext goes to a temporary file next to `output` that is renamed
+/// into place only after the final segment authenticated and `commit`
+/// succeeded, so a corrupt or truncated input never leaves partial
+/// plaintext under the output name.
+fn decrypt_stream(
+    input: &Path,
+    output: &Path,
+    key: &[u8],
+    aad: &[u8],
+    commit: impl FnOnce() -> io::Result<()>,
+) -> io::Result<()> {
+    let file = fs::File::open(input)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", input.display(), e)))?;
+    let mut reader = DecryptingReader::new(file, key, aad)?;
+
+    let mut partial_name = std::ffi::OsString::from(".");
+    partial_name.push(output.file_name().unwrap_or_default());
+    partial_name.push(".partial");
+    let partial = output.with_file_name(partial_name);
+
+    let result = fs::File::create(&partial).and_then(|mut file| {
+        io::copy(&mut reader, &mut file)?;
+        file.sync_all()?;
+        commit()?;
+        fs::rename(&partial, output)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Run `command` with `plaintext` readable on a pipe at `fd`.
+///
+/// Returns the exit code to propagate. The write end is fed from a separate
+/// thread and closed once the plaintext is written, or as soon as the command
+/// exits without reading it, so neither side can block the other.
+#[cfg(unix)]
+fn exec_with_plaintext(plaintext: Vec<u8>, fd: i32, command: &[String]) -> io::Result<i32> {
+    use command_fds::{CommandFdExt, FdMapping};
+    use std::io::Write;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+    use zeroize::Zeroizing;
+
+    let plaintext = Zeroizing::new(plaintext);
+    let (reader, mut writer) = io::pipe()?;
+
+    let mut child = {
+        let mut cmd = Command::new(&command[0]);
+        cmd.args(&command[1..])
+            .fd_mappings(vec![FdMapping {
+                parent_fd: reader.into(),
+                child_fd: fd,
+            }])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        // Dropping `cmd` closes the parent's copy of the read end, so the
+        // writer sees a broken pipe if the child exits without reading.
+        cmd.spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", command[0], e)))?
+    };
+
+    let feeder = std::thread::spawn(move || match writer.write_all(&plaintext) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+        _ => Ok(()),
+    });
+
+    let status = child.wait()?;
+    feeder
+        .join()
+        .map_err(|_| io::Error::other("plaintext writer panicked"))??;
+
+    Ok(status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+/// Decrypt `input` next to itself for `gui-decrypt`, prompting for a key
+/// and nonce when they are not given.
+fn gui_decrypt(
+    input: &Path,
+    key: &KeyArgs,
+    raw: bool,
+    nonce: Option<String>,
+    aad: &AadArgs,
+) -> io::Result<()> {
+    use cli::gui_decrypt::{can_prompt, create_output, prompt_key, prompt_line};
+    use std::io::Write;
+
+    let missing = |what: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "no {} given and standard input is not a terminal to prompt on",
+                what
+            ),
+        )
+    };
+    let key_bytes = match key.resolve()? {
+        Some(key_bytes) => key_bytes,
+        None if can_prompt() => prompt_key()?,
+        None => return Err(missing("key")),
+    };
+    // Envelopes record their nonce; only raw input needs one
+    let nonce_bytes = match nonce {
+        _ if !raw => None,
+        Some(nonce) => Some(decode_nonce(&nonce)?),
+        None if can_prompt() => Some(decode_nonce(&prompt_line("Nonce (hex): ")?)?),
+        None => return Err(missing("nonce")),
+    };
+
+    let ciphertext = fs::read(input)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", input.display(), e)))?;
+    let plaintext = open_input(
+        &key_bytes,
+        nonce_bytes.as_deref(),
+        &ciphertext,
+        &aad.resolve()?,
+    )?;
+
+    let (mut file, output) = create_output(input)?;
+    file.write_all(&plaintext)?;
+    file.sync_all()?;
+    println!("Decrypted {} -> {}", input.display(), output.display());
+    Ok(())
+}
+
+/// Create `path` (which must not exist) holding `hex_key`, readable only by
+/// its owner on Unix.
+fn write_key_file(path: &Path, hex_key: &str) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    writeln!(file, "{}", hex_key)?;
+    file.sync_all()
+}
+
+/// `--version` text: the version and the AES and POLYVAL backends in use
+fn long_version() -> String {
+    format!("{} ({})", env!("CARGO_PKG_VERSION"), backend_info())
+}
+
+fn main() -> ExitCode {
+    let matches = Cli::command().long_version(long_version()).get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(cli::exit_code(&e))
+        }
+    }
 }
 
-fn main() -> io::Result<()> {
-    let cli = Cli::parse();
+fn run(mut cli: Cli) -> io::Result<()> {
+    let profile = match &cli.profile {
+        Some(name) => cli::profiles::load_profile(&cli::profiles::default_path()?, name)?,
+        None => Profile::default(),
+    };
+    cli.backend.apply_profile(&profile);
+    if !matches!(
+        cli.command,
+        Commands::GenNonce
+            | Commands::GenKey { .. }
+            | Commands::Inspect { .. }
+            | Commands::Profile { .. }
+    ) {
+        cli.backend.check()?;
+    }
 
     match cli.command {
         Commands::Encrypt {
             input,
             output,
-            key,
+            mut key,
+            password,
+            kdf,
+            keyring,
             nonce,
-            aad,
+            stream,
+            raw,
+            algorithm,
+            mut aad,
+            mut nonce_history,
+            receipt,
+            receipt_plaintext_hash,
         } => {
-            let key_bytes = hex::decode(&key).map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("Invalid key hex: {}", e),
-                )
-            })?;
+            aad.apply_profile(&profile);
+            let algorithm = Algorithm::resolve(algorithm, &profile)?;
+            if let Some(password) = password.resolve(true)? {
+                let key_size = Algorithm::generated_key_size(algorithm);
+                let mut plaintext = Vec::new();
+                fs::File::open(&input)?.read_to_end(&mut plaintext)?;
+                let ciphertext = aes_gcm_siv_impl::encrypt_with_password(
+                    password.as_bytes(),
+                    &plaintext,
+                    &aad.resolve()?,
+                    &kdf.params()?,
+                    key_size,
+                )?;
+                fs::write(&output, &ciphertext)?;
+                println!(
+                    "Encrypted {} -> {} ({}, password)",
+                    input.display(),
+                    output.display(),
+                    key_size
+                );
+                return Ok(());
+            }
+
+            if let Some(path) = keyring {
+                let keyring = cli::keyring::load_keyring(&path)?;
+                let mut plaintext = Vec::new();
+                fs::File::open(&input)?.read_to_end(&mut plaintext)?;
+                let ciphertext = keyring.encrypt(&plaintext, &aad.resolve()?)?;
+                fs::write(&output, &ciphertext)?;
+                println!(
+                    "Encrypted {} -> {} ({}, key {})",
+                    input.display(),
+                    output.display(),
+                    envelope::inspect(&ciphertext)?.key_size,
+                    keyring.primary_id().unwrap_or_default()
+                );
+                return Ok(());
+            }
+
+            key.apply_profile(&profile);
+            nonce_history.apply_profile(&profile);
+
+            let key_bytes = key.require()?;
+            let key_size = match algorithm {
+                Some(algorithm) => {
+                    let key_size = algorithm.key_size();
+                    if key_bytes.len() != key_size.key_len() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "{} requires a {}-byte key, but the key is {} bytes",
+                                key_size,
+                                key_size.key_len(),
+                                key_bytes.len()
+                            ),
+                        ));
+                    }
+                    key_size
+                }
+                None => KeySize::from_key_len(key_bytes.len())?,
+            };
 
+            if stream {
+                encrypt_stream(&input, &output, &key_bytes, &aad.resolve()?)?;
+                println!(
+                    "Encrypted {} -> {} ({}, streamed)",
+                    input.display(),
+                    output.display(),
+                    key_size
+                );
+                return Ok(());
+            }
+
+            let explicit_nonce = nonce.is_some();
             let nonce_bytes = match nonce {
-                Some(n) => hex::decode(&n).map_err(|e| {
-                    io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        format!("Invalid nonce hex: {}", e),
-                    )
-                })?,
+                Some(n) => decode_nonce(&n)?,
                 None => {
                     let random_nonce = aes_gcm_siv_impl::generate_nonce();
-                    println!("Generated nonce: {}", hex::encode(&random_nonce));
+                    // An envelope records the nonce; raw output does not
+                    if raw {
+                        println!("Generated nonce: {}", hex::encode(&random_nonce));
+                    }
                     random_nonce
                 }
             };
 
-            if nonce_bytes.len() != NONCE_LENGTH {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("Nonce must be exactly {} bytes", NONCE_LENGTH),
-                ));
+            let aad_bytes = aad.resolve()?;
+            let encrypt_file = || -> io::Result<(Vec<u8>, Vec<u8>)> {
+                let mut plaintext = Vec::new();
+                fs::File::open(&input)?.read_to_end(&mut plaintext)?;
+                let ciphertext = if raw {
+                    Cipher::new(&key_bytes)?.encrypt(&nonce_bytes, &plaintext, &aad_bytes)?
+                } else {
+                    envelope::seal_with_nonce(&key_bytes, &nonce_bytes, &plaintext, &aad_bytes)?
+                };
+                fs::write(&output, &ciphertext)?;
+                Ok((plaintext, ciphertext))
+            };
+            // An explicit nonce only counts as used once the ciphertext is
+            // written, so a failed run can be retried with it
+            let (plaintext, ciphertext) = if explicit_nonce {
+                nonce_history.guard(&key_bytes, &nonce_bytes, encrypt_file)?
+            } else {
+                encrypt_file()?
+            };
+            if let Some(path) = receipt {
+                let encryption = cli::receipt::Encryption {
+                    input: &input,
+                    output: &output,
+                    key: &key_bytes,
+                    nonce: &nonce_bytes,
+                    aad: &aad_bytes,
+                    plaintext: &plaintext,
+                    ciphertext: &ciphertext,
+                    raw,
+                };
+                cli::receipt::Receipt::new(&encryption, receipt_plaintext_hash)?.write(&path)?;
+                println!("Wrote receipt {}", path.display());
             }
-
-            let aad_bytes = aad.as_deref().unwrap_or("").as_bytes();
-            let mut plaintext = Vec::new();
-            fs::File::open(&input)?.read_to_end(&mut plaintext)?;
-
-            let ciphertext = encrypt(&key_bytes, &nonce_bytes, &plaintext, aad_bytes)
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-
-            fs::write(&output, ciphertext)?;
-            println!("Encrypted {} -> {}", input.display(), output.display());
+            println!(
+                "Encrypted {} -> {} ({})",
+                input.display(),
+                output.display(),
+                key_size
+            );
             Ok(())
         }
 
         Commands::Decrypt {
             input,
             output,
-            key,
-            nonce,
-            aad,
+            mut key,
+            password,
+            keyring,
+            mut keyring_dir,
+            mut try_all_keys,
+            raw,
+            stream,
+            mut aad,
+            mut pin,
         } => {
-            let key_bytes = hex::decode(&key).map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("Invalid key hex: {}", e),
-                )
-            })?;
+            aad.apply_profile(&profile);
+            pin.apply_profile(&profile)?;
+            if let Some(password) = password.resolve(false)? {
+                if pin.pin_file.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "a password-derived key differs for every file, so it cannot be pinned",
+                    ));
+                }
+                let mut ciphertext = Vec::new();
+                fs::File::open(&input)?.read_to_end(&mut ciphertext)?;
+                let plaintext = aes_gcm_siv_impl::decrypt_with_password(
+                    password.as_bytes(),
+                    &ciphertext,
+                    &aad.resolve()?,
+                )?;
+                fs::write(&output, plaintext)?;
+                println!(
+                    "Decrypted {} -> {} (password)",
+                    input.display(),
+                    output.display()
+                );
+                return Ok(());
+            }
 
-            let nonce_bytes = hex::decode(&nonce).map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("Invalid nonce hex: {}", e),
-                )
-            })?;
+            if let Some(path) = keyring {
+                let keyring = cli::keyring::load_keyring(&path)?;
+                let mut ciphertext = Vec::new();
+                fs::File::open(&input)?.read_to_end(&mut ciphertext)?;
+                let plaintext = keyring
+                    .decrypt(&ciphertext, &aad.resolve()?)
+                    .map_err(|e| keyring_error(e, &keyring, &path, &ciphertext))?;
+                // The envelope authenticated, so its header parses and names a key
+                let header = envelope::inspect(&ciphertext)?;
+                let key_id = header.key_id.unwrap_or_default();
+                let fingerprint = keyring
+                    .fingerprint(&key_id)
+                    .expect("the key that decrypted the input");
+                println!("Using key {} ({})", key_id, fingerprint);
+                pin.check(&fingerprint)?;
+
+                fs::write(&output, plaintext)?;
+                println!(
+                    "Decrypted {} -> {} ({})",
+                    input.display(),
+                    output.display(),
+                    header.key_size
+                );
+                return Ok(());
+            }
 
-            if nonce_bytes.len() != NONCE_LENGTH {
+            if key.is_empty() && keyring_dir.is_none() {
+                key.apply_profile(&profile);
+                keyring_dir = profile.keyring_dir.clone();
+            }
+            try_all_keys |= profile.try_all_keys;
+            if try_all_keys && keyring_dir.is_none() {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidInput,
-                    format!("Nonce must be exactly {} bytes", NONCE_LENGTH),
+                    "--try-all-keys needs --keyring-dir",
                 ));
             }
 
-            let aad_bytes = aad.as_deref().unwrap_or("").as_bytes();
+            // A stream says so in its header, so --stream can be left out
+            if stream || (raw.nonce.is_none() && starts_like_stream(&input)?) {
+                if keyring_dir.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "streams do not support keyring directories; give the key directly",
+                    ));
+                }
+                let key_bytes = key.require()?;
+                let key_size = KeySize::from_key_len(key_bytes.len())?;
+                let fingerprint = key_fingerprint(&key_bytes)?;
+                decrypt_stream(&input, &output, &key_bytes, &aad.resolve()?, || {
+                    pin.check(&fingerprint)
+                })?;
+                println!(
+                    "Decrypted {} -> {} ({}, streamed)",
+                    input.display(),
+                    output.display(),
+                    key_size
+                );
+                return Ok(());
+            }
+
+            let nonce_bytes = raw.nonce()?;
+            let aad_bytes = aad.resolve()?;
             let mut ciphertext = Vec::new();
             fs::File::open(&input)?.read_to_end(&mut ciphertext)?;
 
-            let plaintext = decrypt(&key_bytes, &nonce_bytes, &ciphertext, aad_bytes)
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+            let (plaintext, key_size, fingerprint) = match keyring_dir {
+                Some(dir) => {
+                    let keys = load_keyring_dir(&dir)?;
+                    let (plaintext, dir_key) = decrypt_with_keyring_dir(
+                        &keys,
+                        try_all_keys,
+                        nonce_bytes.as_deref(),
+                        &ciphertext,
+                        &aad_bytes,
+                    )?;
+                    println!(
+                        "Using key {} ({})",
+                        dir_key.fingerprint,
+                        dir_key.path.display()
+                    );
+                    let key_size = KeySize::from_key_len(dir_key.key.len())?;
+                    (plaintext, key_size, dir_key.fingerprint)
+                }
+                None => {
+                    let key_bytes = key.require()?;
+                    let key_size = KeySize::from_key_len(key_bytes.len())?;
+                    let plaintext =
+                        open_input(&key_bytes, nonce_bytes.as_deref(), &ciphertext, &aad_bytes)?;
+                    let fingerprint = key_fingerprint(&key_bytes)?;
+                    (plaintext, key_size, fingerprint)
+                }
+            };
+
+            pin.check(&fingerprint)?;
 
             fs::write(&output, plaintext)?;
-            println!("Decrypted {} -> {}", input.display(), output.display());
+            println!(
+                "Decrypted {} -> {} ({})",
+                input.display(),
+                output.display(),
+                key_size
+            );
+            Ok(())
+        }
+
+        Commands::Verify {
+            input,
+            receipt,
+            mut key,
+            mut keyring_dir,
+            mut aad,
+        } => {
+            if key.is_empty() && keyring_dir.is_none() {
+                key.apply_profile(&profile);
+                keyring_dir = profile.keyring_dir.clone();
+            }
+            aad.apply_profile(&profile);
+
+            let receipt = cli::receipt::Receipt::read(&receipt)?;
+            let ciphertext = fs::read(&input)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", input.display(), e)))?;
+            let key_bytes = match keyring_dir {
+                Some(dir) => {
+                    let keys = load_keyring_dir(&dir)?;
+                    let dir_key = keys
+                        .iter()
+                        .find(|k| k.fingerprint.to_string() == receipt.key_fingerprint)
+                        .ok_or_else(|| {
+                            cli::fail(
+                                ErrorClass::InvalidInput,
+                                format!(
+                                    "no key in {} has the receipt's fingerprint {} \
+                                     (available fingerprints: {})",
+                                    dir.display(),
+                                    receipt.key_fingerprint,
+                                    fingerprint_list(&keys)
+                                ),
+                            )
+                        })?;
+                    println!(
+                        "Using key {} ({})",
+                        dir_key.fingerprint,
+                        dir_key.path.display()
+                    );
+                    Some(dir_key.key.clone())
+                }
+                None => key.resolve()?,
+            };
+            let mismatches = receipt.verify(&ciphertext, key_bytes.as_deref(), &aad.resolve()?);
+            if !mismatches.is_empty() {
+                return Err(cli::fail(
+                    ErrorClass::AuthFailure,
+                    format!(
+                        "{} does not match its receipt:\n  {}",
+                        input.display(),
+                        mismatches.join("\n  ")
+                    ),
+                ));
+            }
+            if key_bytes.is_some() {
+                println!("{} matches its receipt", input.display());
+            } else {
+                println!(
+                    "{} matches its receipt (ciphertext only; pass a key to check the rest)",
+                    input.display()
+                );
+            }
+            Ok(())
+        }
+
+        Commands::Inspect { input } => {
+            let data = fs::read(&input)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", input.display(), e)))?;
+            let header = envelope::inspect(&data).map_err(|e| match e {
+                EnvelopeError::NotAnEnvelope if kdf::is_password_encrypted(&data) => cli::fail(
+                    ErrorClass::InvalidInput,
+                    format!(
+                        "{} is password-encrypted; only envelopes can be inspected",
+                        input.display()
+                    ),
+                ),
+                EnvelopeError::NotAnEnvelope => cli::fail(
+                    ErrorClass::InvalidInput,
+                    format!(
+                        "{} is not an envelope; bare ciphertext has no header to inspect",
+                        input.display()
+                    ),
+                ),
+                e => envelope_error(e, &data),
+            })?;
+            println!(
+                "format: envelope v{} (readable from v{})",
+                header.version, header.min_reader_version
+            );
+            println!("producer: {}", header.producer);
+            println!("algorithm: {}", header.key_size);
+            println!("key ID: {}", header.key_id.as_deref().unwrap_or("none"));
+            println!("nonce: {}", hex::encode(header.nonce));
+            match header.aad_length {
+                Some(length) if header.aad_required => {
+                    println!("AAD: required, {} bytes", length)
+                }
+                Some(length) => println!("AAD: {} bytes", length),
+                None => println!("AAD: none"),
+            }
             Ok(())
         }
 
@@ -163,5 +1522,95 @@ fn main() -> io::Result<()> {
             println!("{}", hex::encode(&nonce));
             Ok(())
         }
+
+        Commands::GenKey { algorithm, output } => {
+            let key_size = Algorithm::generated_key_size(Algorithm::resolve(algorithm, &profile)?);
+            let key = aes_gcm_siv_impl::generate_key(key_size);
+            let hex_key = Zeroizing::new(hex::encode(&*key));
+            match output {
+                Some(path) => {
+                    write_key_file(&path, &hex_key)?;
+                    println!(
+                        "Wrote {} key {} to {}",
+                        key_size,
+                        key_fingerprint(&key)?,
+                        path.display()
+                    );
+                }
+                None => println!("{}", &*hex_key),
+            }
+            Ok(())
+        }
+
+        Commands::GuiDecrypt {
+            input,
+            mut key,
+            raw,
+            nonce,
+            mut aad,
+            wait,
+            no_wait,
+        } => {
+            key.apply_profile(&profile);
+            aad.apply_profile(&profile);
+
+            let result = gui_decrypt(&input, &key, raw, nonce, &aad);
+            let wait = wait || (!no_wait && cli::gui_decrypt::launched_from_file_manager());
+            if wait {
+                if let Err(e) = &result {
+                    eprintln!("Error: {}", e);
+                }
+                cli::gui_decrypt::wait_for_enter();
+                if let Err(e) = &result {
+                    std::process::exit(cli::exit_code(e).into());
+                }
+            }
+            result
+        }
+
+        Commands::Profile { action } => {
+            let path = cli::profiles::default_path()?;
+            match action {
+                ProfileCommand::List => {
+                    if !path.exists() {
+                        eprintln!("no profiles file at {}", path.display());
+                        return Ok(());
+                    }
+                    for name in cli::profiles::load_profiles(&path)?.keys() {
+                        println!("{}", name);
+                    }
+                }
+                ProfileCommand::Show { name } => {
+                    let profile = cli::profiles::load_profile(&path, &name)?;
+                    print!("{}", toml::to_string(&profile).map_err(io::Error::other)?);
+                }
+            }
+            Ok(())
+        }
+
+        #[cfg(unix)]
+        Commands::Exec {
+            input,
+            mut key,
+            raw,
+            mut aad,
+            fd,
+            command,
+        } => {
+            key.apply_profile(&profile);
+            aad.apply_profile(&profile);
+
+            let key_bytes = key.require()?;
+            let nonce_bytes = raw.nonce()?;
+            let aad_bytes = aad.resolve()?;
+            let mut ciphertext = Vec::new();
+            fs::File::open(&input)?.read_to_end(&mut ciphertext)?;
+
+            let plaintext =
+                open_input(&key_bytes, nonce_bytes.as_deref(), &ciphertext, &aad_bytes)?;
+
+            let code = exec_with_plaintext(plaintext, fd, &command)?;
+            std::process::exit(code);
+        }
     }
 }
@@ -0,0 +1,280 @@
+//! Encrypt-then-swap migration of stored plaintext values to envelopes
+//!
+//! Migrating a column from plaintext to envelopes row by row has to survive
+//! crashes and reruns. [`migrate_value`] makes each row's step idempotent:
+//!
+//! 1. A value that already opens as an envelope under the cipher and AAD
+//!    is [`MigrationStep::AlreadyMigrated`] and is left alone.
+//! 2. Anything else is sealed into a [`Replacement`], whose
+//!    [`verify`](Replacement::verify) re-opens it and compares the result
+//!    with the original value.
+//! 3. Only a verified replacement is written back, in the caller's
+//!    transaction.
+//!
+//! A crash before the commit leaves the plaintext in place, to be migrated
+//! again on the next run; a crash after it leaves an envelope, which the
+//! next run skips. [`migrate_batch`] runs the steps over many rows, with a
+//! progress callback and a dry-run mode.
+//!
+//! # Plaintext that looks like an envelope
+//! A value is only taken to be migrated when it opens. A plaintext value
+//! that starts with the envelope magic by coincidence but does not parse
+//! as an envelope is migrated like any other. A value that does parse as an
+//! envelope but does not open (sealed under another key or AAD, corrupted,
+//! or needing a newer format version) is
+//! [`MigrationError::ForeignEnvelope`]: sealing it again would bury an
+//! envelope inside another, so it is left for the caller.
+
+use crate::envelope::{self, EnvelopeError};
+use crate::{Cipher, CryptoError, ErrorClass, SealedBlob};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Errors returned by [`migrate_value`] and [`Replacement::verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MigrationError {
+    /// The value parses as an envelope but does not open under the cipher
+    /// and AAD (see the [module docs](self))
+    ForeignEnvelope(EnvelopeError),
+    /// The replacement did not open to the original value
+    VerificationFailed,
+    /// Sealing the replacement failed (see [`CryptoError`])
+    Crypto(CryptoError),
+}
+
+impl MigrationError {
+    /// The broad category of this error
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            MigrationError::ForeignEnvelope(e) => e.classify(),
+            MigrationError::VerificationFailed => ErrorClass::Internal,
+            MigrationError::Crypto(e) => e.classify(),
+        }
+    }
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::ForeignEnvelope(e) => {
+                write!(f, "Value is an envelope that does not open: {}", e)
+            }
+            MigrationError::VerificationFailed => {
+                write!(f, "Replacement did not open to the original value")
+            }
+            MigrationError::Crypto(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::ForeignEnvelope(e) => Some(e),
+            MigrationError::VerificationFailed => None,
+            MigrationError::Crypto(e) => Some(e),
+        }
+    }
+}
+
+impl From<CryptoError> for MigrationError {
+    fn from(err: CryptoError) -> Self {
+        MigrationError::Crypto(err)
+    }
+}
+
+impl From<MigrationError> for std::io::Error {
+    fn from(err: MigrationError) -> Self {
+        let kind = match err.classify() {
+            ErrorClass::AuthFailure => std::io::ErrorKind::InvalidData,
+            ErrorClass::InvalidInput => std::io::ErrorKind::InvalidInput,
+            ErrorClass::Resource => std::io::ErrorKind::Unsupported,
+            ErrorClass::Io | ErrorClass::Internal => std::io::ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+/// What to do with one stored value
+#[derive(Debug)]
+pub enum MigrationStep {
+    /// The value already opens as an envelope; leave it alone
+    AlreadyMigrated,
+    /// The value is plaintext; verify the replacement, then write it back
+    Migrate(Replacement),
+}
+
+/// A sealed replacement for a plaintext value, not yet verified
+pub struct Replacement {
+    sealed: SealedBlob,
+    cipher: Cipher,
+    aad: Vec<u8>,
+    /// SHA-256 of the original value, so it need not be kept around
+    digest: [u8; 32],
+}
+
+impl fmt::Debug for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replacement")
+            .field("sealed", &self.sealed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Replacement {
+    /// Re-open the replacement and check that it gives back the original
+    /// value; call this before committing the replacement
+    ///
+    /// # Errors
+    /// Returns `MigrationError::VerificationFailed` if it does not
+    pub fn verify(&self) -> Result<(), MigrationError> {
+        match self.sealed.open(&self.cipher, &self.aad) {
+            Ok(plaintext) if Sha256::digest(&plaintext)[..] == self.digest => Ok(()),
+            _ => Err(MigrationError::VerificationFailed),
+        }
+    }
+
+    /// The envelope to store in place of the plaintext
+    pub fn sealed(&self) -> &SealedBlob {
+        &self.sealed
+    }
+
+    /// [`verify`](Replacement::verify), then hand out the envelope
+    ///
+    /// # Errors
+    /// Returns `MigrationError::VerificationFailed` if verification fails
+    pub fn into_verified(self) -> Result<SealedBlob, MigrationError> {
+        self.verify()?;
+        Ok(self.sealed)
+    }
+}
+
+/// Decide what to do with the stored value `current`
+///
+/// # Arguments
+/// * `cipher` - The cipher the column is migrated to
+/// * `current` - The stored value, plaintext or an envelope from an
+///   earlier run
+/// * `aad` - Additional authenticated data to seal the value with
+///
+/// # Errors
+/// Returns `MigrationError::ForeignEnvelope` for a value that parses as an
+/// envelope but does not open, and `Crypto` if sealing fails
+pub fn migrate_value(
+    cipher: &Cipher,
+    current: &[u8],
+    aad: &[u8],
+) -> Result<MigrationStep, MigrationError> {
+    if envelope::is_envelope(current) {
+        match SealedBlob::try_from(current) {
+            Ok(blob) => {
+                return match blob.open(cipher, aad) {
+                    Ok(_) => Ok(MigrationStep::AlreadyMigrated),
+                    Err(e) => Err(MigrationError::ForeignEnvelope(e)),
+                }
+            }
+            Err(e @ EnvelopeError::UnsupportedVersion { .. }) => {
+                return Err(MigrationError::ForeignEnvelope(e))
+            }
+            // The magic by coincidence: plaintext after all
+            Err(_) => {}
+        }
+    }
+
+    Ok(MigrationStep::Migrate(Replacement {
+        sealed: SealedBlob::seal(cipher, current, aad)?,
+        cipher: cipher.clone(),
+        aad: aad.to_vec(),
+        digest: Sha256::digest(current).into(),
+    }))
+}
+
+/// Options for [`migrate_batch`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MigrationOptions {
+    dry_run: bool,
+}
+
+impl MigrationOptions {
+    /// The default options: return the verified replacements
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seal and verify every row, but return no replacements, to check
+    /// what a migration would do before running it
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+/// Counts of the rows [`migrate_batch`] has processed so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MigrationProgress {
+    /// Rows looked at
+    pub processed: u64,
+    /// Rows sealed and verified
+    pub migrated: u64,
+    /// Rows that already were envelopes
+    pub already_migrated: u64,
+    /// Rows that failed
+    pub failed: u64,
+}
+
+/// Result of [`migrate_batch`]
+#[derive(Debug)]
+pub struct MigrationReport<K> {
+    /// Final counts
+    pub progress: MigrationProgress,
+    /// Verified envelopes to write back, by row key; empty in a dry run
+    pub replacements: Vec<(K, SealedBlob)>,
+    /// Rows that could not be migrated, by row key
+    pub failures: Vec<(K, MigrationError)>,
+}
+
+/// Run [`migrate_value`] and verification over `rows`
+///
+/// Every row is sealed with the same `aad`. A failing row does not stop the
+/// batch; it is recorded in [`MigrationReport::failures`]. `progress` is
+/// called after each row with the counts so far.
+pub fn migrate_batch<K, V, I>(
+    cipher: &Cipher,
+    rows: I,
+    aad: &[u8],
+    options: MigrationOptions,
+    mut progress: impl FnMut(&MigrationProgress),
+) -> MigrationReport<K>
+where
+    I: IntoIterator<Item = (K, V)>,
+    V: AsRef<[u8]>,
+{
+    let mut report = MigrationReport {
+        progress: MigrationProgress::default(),
+        replacements: Vec::new(),
+        failures: Vec::new(),
+    };
+    for (key, value) in rows {
+        let counts = &mut report.progress;
+        counts.processed += 1;
+        match migrate_value(cipher, value.as_ref(), aad).and_then(|step| match step {
+            MigrationStep::AlreadyMigrated => Ok(None),
+            MigrationStep::Migrate(replacement) => replacement.into_verified().map(Some),
+        }) {
+            Ok(None) => counts.already_migrated += 1,
+            Ok(Some(sealed)) => {
+                counts.migrated += 1;
+                if !options.dry_run {
+                    report.replacements.push((key, sealed));
+                }
+            }
+            Err(e) => {
+                counts.failed += 1;
+                report.failures.push((key, e));
+            }
+        }
+        progress(&report.progress);
+    }
+    report
+}
@@ -0,0 +1,200 @@
+//! Validated envelope bytes for storage layers
+//!
+//! Databases and ORMs store envelopes as opaque byte columns (`bytea`,
+//! `BLOB`, ...). [`SealedBlob`] wraps such a column value: it can only be
+//! built from bytes that parse as an envelope, so a truncated or foreign
+//! value is rejected when a row is loaded rather than when it is opened.
+//! Storage glue needs nothing beyond [`AsRef<[u8]>`](AsRef) for writing and
+//! [`TryFrom<Vec<u8>>`](TryFrom) for reading.
+//!
+//! With the `serde` feature, a `SealedBlob` serializes as raw bytes for
+//! binary formats and as a standard base64 string for human-readable ones
+//! (JSON, TOML, ...), and is validated again when deserialized.
+//!
+//! # Security Notes
+//! - Validation checks the structure only; a blob whose ciphertext was
+//!   altered or cut inside the payload still fails authentication when
+//!   opened
+
+use crate::envelope::{self, EnvelopeError, EnvelopeHeader};
+#[cfg(feature = "std")]
+use crate::generate_nonce;
+use crate::{Cipher, CryptoError, TAG_LENGTH};
+use alloc::vec::Vec;
+
+/// Envelope bytes known to have a well-formed header and room for a tag
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use aes_gcm_siv_impl::{Cipher, SealedBlob};
+///
+/// let cipher = Cipher::new(&[0x42; 32])?;
+/// let blob = SealedBlob::seal(&cipher, b"secret", b"users.email")?;
+///
+/// // Store `blob.as_ref()`, load the column back as a `Vec<u8>`
+/// let loaded = SealedBlob::try_from(blob.as_ref().to_vec())?;
+/// assert_eq!(loaded.open(&cipher, b"users.email")?, b"secret");
+/// # }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedBlob(Vec<u8>);
+
+impl SealedBlob {
+    /// Seal `plaintext` into an envelope under a random nonce
+    ///
+    /// # Errors
+    /// Returns `CryptoError` if encryption fails
+    #[cfg(feature = "std")]
+    pub fn seal(cipher: &Cipher, plaintext: &[u8], aad: &[u8]) -> Result<Self, CryptoError> {
+        Self::seal_with_nonce(cipher, &generate_nonce(), plaintext, aad)
+    }
+
+    /// Seal `plaintext` into an envelope under a caller-chosen nonce
+    ///
+    /// # Security Notes
+    /// - Never reuse a nonce with the same key; prefer [`SealedBlob::seal`]
+    ///
+    /// # Errors
+    /// Returns `CryptoError::InvalidNonceSize` if nonce is invalid
+    pub fn seal_with_nonce(
+        cipher: &Cipher,
+        nonce: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Self, CryptoError> {
+        envelope::seal_with_cipher(cipher, None, !aad.is_empty(), nonce, plaintext, aad).map(Self)
+    }
+
+    /// Decrypt the envelope
+    ///
+    /// # Errors
+    /// Returns the errors of [`open`](crate::open)
+    pub fn open(&self, cipher: &Cipher, aad: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        envelope::open_with_cipher(cipher, &self.0, aad)
+    }
+
+    /// The envelope header
+    pub fn header(&self) -> EnvelopeHeader {
+        envelope::inspect(&self.0).expect("validated on construction")
+    }
+
+    /// The envelope bytes
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for SealedBlob {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl TryFrom<Vec<u8>> for SealedBlob {
+    type Error = EnvelopeError;
+
+    /// Validate `bytes` as an envelope
+    ///
+    /// # Errors
+    /// Returns `EnvelopeError::NotAnEnvelope`, `UnsupportedVersion` or
+    /// `Malformed` for a bad header, and `Malformed` if the envelope is too
+    /// short to hold an authentication tag
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        let (_, header_length) = EnvelopeHeader::parse(&bytes)?;
+        if bytes.len() - header_length < TAG_LENGTH {
+            return Err(EnvelopeError::Malformed);
+        }
+        Ok(SealedBlob(bytes))
+    }
+}
+
+impl TryFrom<&[u8]> for SealedBlob {
+    type Error = EnvelopeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        SealedBlob::try_from(bytes.to_vec())
+    }
+}
+
+impl From<SealedBlob> for Vec<u8> {
+    fn from(blob: SealedBlob) -> Self {
+        blob.0
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::SealedBlob;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use core::fmt;
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{Serialize, Serializer};
+
+    impl Serialize for SealedBlob {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            if serializer.is_human_readable() {
+                serializer.serialize_str(&STANDARD.encode(&self.0))
+            } else {
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+    }
+
+    struct BlobVisitor;
+
+    impl BlobVisitor {
+        fn validate<E: de::Error>(bytes: Vec<u8>) -> Result<SealedBlob, E> {
+            SealedBlob::try_from(bytes).map_err(E::custom)
+        }
+    }
+
+    impl<'de> Visitor<'de> for BlobVisitor {
+        type Value = SealedBlob;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("envelope bytes or a base64 string of them")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<SealedBlob, E> {
+            let bytes = STANDARD
+                .decode(value)
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))?;
+            Self::validate(bytes)
+        }
+
+        fn visit_string<E: de::Error>(self, value: String) -> Result<SealedBlob, E> {
+            self.visit_str(&value)
+        }
+
+        fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<SealedBlob, E> {
+            Self::validate(value.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<SealedBlob, E> {
+            Self::validate(value)
+        }
+
+        /// Binary formats without a native byte type encode bytes as a sequence
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SealedBlob, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Self::validate(bytes)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SealedBlob {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            if deserializer.is_human_readable() {
+                deserializer.deserialize_str(BlobVisitor)
+            } else {
+                deserializer.deserialize_byte_buf(BlobVisitor)
+            }
+        }
+    }
+}
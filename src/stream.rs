@@ -0,0 +1,631 @@
+//! Segmented streaming encryption for inputs too large to hold in memory
+//!
+//! [`encrypt`](crate::encrypt) needs the whole message in memory and is
+//! limited to 2^36 - 31 bytes. This module instead splits a stream into
+//! fixed-size segments, each encrypted under its own nonce following the
+//! STREAM construction (Hoang, Reyhanitabar, Rogaway and Vizár, 2015):
+//!
+//! ```text
+//! nonce prefix (7 bytes) || segment counter (u32 BE) || last-segment flag (1 byte)
+//! ```
+//!
+//! The counter fixes the order of the segments and the flag, set only on
+//! the final one, marks the end: reordered, dropped, duplicated or corrupt
+//! segments fail authentication, and so does a stream cut short.
+//!
+//! [`StreamEncryptor`] and [`StreamDecryptor`] work one segment at a time.
+//! [`EncryptingWriter`] and [`DecryptingReader`] wrap them in `io::Write` and
+//! `io::Read` with a small header, so memory use is bounded by the segment
+//! size whatever the length of the stream.
+//!
+//! # Layout
+//! A header (magic, segment size as u32 LE, nonce prefix), then the
+//! segments. Every segment but the last holds exactly `segment_size`
+//! plaintext bytes (`segment_size + TAG_LENGTH` bytes written); the last one
+//! holds between 0 and `segment_size`. Every segment is authenticated with
+//! the header followed by the caller's AAD.
+//!
+//! # Security Notes
+//! - The nonce prefix is random and only 7 bytes, so keep the number of
+//!   streams encrypted under one key well below 2^28
+//! - [`DecryptingReader`] only returns plaintext of segments that
+//!   authenticated, but a later segment may still fail: treat the output as
+//!   untrusted until the reader has reached the end without an error
+//! - A stream holds at most 2^32 segments (256 TiB at the default size)
+
+use crate::{Cipher, CryptoError, ErrorClass, NONCE_LENGTH, TAG_LENGTH};
+use rand::TryRngCore;
+use std::fmt;
+use std::io::{self, Read, Write};
+use zeroize::Zeroizing;
+
+/// Length of the random per-stream part of the segment nonces
+pub const NONCE_PREFIX_LENGTH: usize = 7;
+
+/// Plaintext bytes per segment used by [`EncryptingWriter::new`]
+pub const DEFAULT_SEGMENT_SIZE: usize = 64 * 1024;
+
+/// Largest segment size accepted, which bounds what a hostile header can
+/// make [`DecryptingReader`] allocate
+pub const MAX_SEGMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Identifies a stream and its format version
+const MAGIC: &[u8; 8] = b"AGSVSTR1";
+
+/// Header: magic, segment size, nonce prefix
+pub const HEADER_LENGTH: usize = MAGIC.len() + 4 + NONCE_PREFIX_LENGTH;
+
+/// Errors returned by the streaming API
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StreamError {
+    /// Reading or writing the underlying stream failed
+    Io(io::Error),
+    /// The key was rejected (see [`CryptoError`])
+    Crypto(CryptoError),
+    /// The input does not start with a stream header of this format
+    NotAStream,
+    /// The segment size is zero or above [`MAX_SEGMENT_SIZE`]
+    InvalidSegmentSize(usize),
+    /// The segment with this index fails authentication
+    CorruptSegment(u32),
+    /// The stream ends without its final segment
+    Truncated,
+    /// The stream already holds the maximum number of segments
+    TooManySegments,
+}
+
+impl StreamError {
+    /// The broad category of this error
+    pub fn classify(&self) -> ErrorClass {
+        match self {
+            StreamError::Io(e) => crate::classify_io_error(e),
+            StreamError::Crypto(e) => e.classify(),
+            StreamError::CorruptSegment(_) | StreamError::Truncated => ErrorClass::AuthFailure,
+            StreamError::NotAStream | StreamError::InvalidSegmentSize(_) => {
+                ErrorClass::InvalidInput
+            }
+            StreamError::TooManySegments => ErrorClass::Resource,
+        }
+    }
+
+    /// A copy of a decryption error, so a failed stream keeps failing on
+    /// later reads
+    fn repeat(&self) -> Option<StreamError> {
+        match self {
+            StreamError::CorruptSegment(index) => Some(StreamError::CorruptSegment(*index)),
+            StreamError::Truncated => Some(StreamError::Truncated),
+            StreamError::TooManySegments => Some(StreamError::TooManySegments),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(e) => write!(f, "{}", e),
+            StreamError::Crypto(e) => write!(f, "{}", e),
+            StreamError::NotAStream => write!(f, "Not an encrypted stream"),
+            StreamError::InvalidSegmentSize(size) => write!(
+                f,
+                "Segment size must be between 1 and {} bytes, got {}",
+                MAX_SEGMENT_SIZE, size
+            ),
+            StreamError::CorruptSegment(index) => {
+                write!(f, "Segment {} failed authentication", index)
+            }
+            StreamError::Truncated => write!(f, "Stream is truncated"),
+            StreamError::TooManySegments => write!(f, "Stream segment limit reached"),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Io(e) => Some(e),
+            StreamError::Crypto(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(err: io::Error) -> Self {
+        StreamError::Io(err)
+    }
+}
+
+impl From<CryptoError> for StreamError {
+    fn from(err: CryptoError) -> Self {
+        StreamError::Crypto(err)
+    }
+}
+
+impl From<StreamError> for io::Error {
+    fn from(err: StreamError) -> Self {
+        let kind = match err {
+            StreamError::Io(e) => return e,
+            StreamError::Crypto(e) => return e.into(),
+            StreamError::CorruptSegment(_) | StreamError::Truncated => io::ErrorKind::InvalidData,
+            StreamError::NotAStream | StreamError::InvalidSegmentSize(_) => {
+                io::ErrorKind::InvalidInput
+            }
+            StreamError::TooManySegments => io::ErrorKind::Unsupported,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Result type for streaming operations
+pub type StreamResult<T> = Result<T, StreamError>;
+
+/// Nonce of segment `counter`
+fn segment_nonce(
+    prefix: &[u8; NONCE_PREFIX_LENGTH],
+    counter: u32,
+    last: bool,
+) -> [u8; NONCE_LENGTH] {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    nonce[..NONCE_PREFIX_LENGTH].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LENGTH..NONCE_LENGTH - 1].copy_from_slice(&counter.to_be_bytes());
+    nonce[NONCE_LENGTH - 1] = u8::from(last);
+    nonce
+}
+
+fn check_segment_size(segment_size: usize) -> StreamResult<()> {
+    if segment_size == 0 || segment_size > MAX_SEGMENT_SIZE {
+        return Err(StreamError::InvalidSegmentSize(segment_size));
+    }
+    Ok(())
+}
+
+/// Encrypts a stream one segment at a time
+///
+/// Segments may have any length; only the order of the calls matters. The
+/// final segment must be encrypted with [`StreamEncryptor::encrypt_last`],
+/// otherwise the stream cannot be decrypted.
+pub struct StreamEncryptor {
+    cipher: Cipher,
+    prefix: [u8; NONCE_PREFIX_LENGTH],
+    aad: Vec<u8>,
+    /// Counter of the next segment
+    counter: u32,
+}
+
+impl StreamEncryptor {
+    /// Start a stream
+    ///
+    /// # Arguments
+    /// * `key` - The encryption key (must be 16 or 32 bytes)
+    /// * `nonce_prefix` - Random prefix of the segment nonces
+    /// * `aad` - Additional authenticated data for every segment
+    ///
+    /// # Security Notes
+    /// - Never reuse a nonce prefix with the same key
+    ///
+    /// # Errors
+    /// Returns `CryptoError::InvalidKeySize` if key length is invalid
+    pub fn new(
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LENGTH],
+        aad: &[u8],
+    ) -> Result<Self, CryptoError> {
+        Ok(StreamEncryptor {
+            cipher: Cipher::new(key)?,
+            prefix: *nonce_prefix,
+            aad: aad.to_vec(),
+            counter: 0,
+        })
+    }
+
+    /// Encrypt the next segment, which is not the last one
+    ///
+    /// # Errors
+    /// Returns `StreamError::TooManySegments` once 2^32 - 1 segments were
+    /// encrypted, leaving only the final one, or `StreamError::Crypto` if
+    /// the segment is longer than AES-GCM-SIV allows
+    pub fn encrypt_next(&mut self, plaintext: &[u8]) -> StreamResult<Vec<u8>> {
+        // The last counter value is reserved for the final segment
+        let next = self
+            .counter
+            .checked_add(1)
+            .ok_or(StreamError::TooManySegments)?;
+        let nonce = segment_nonce(&self.prefix, self.counter, false);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext, &self.aad)?;
+        self.counter = next;
+        Ok(ciphertext)
+    }
+
+    /// Encrypt the final segment, ending the stream
+    ///
+    /// # Errors
+    /// Returns `StreamError::Crypto` if the segment is longer than
+    /// AES-GCM-SIV allows
+    pub fn encrypt_last(self, plaintext: &[u8]) -> StreamResult<Vec<u8>> {
+        let nonce = segment_nonce(&self.prefix, self.counter, true);
+        Ok(self.cipher.encrypt(&nonce, plaintext, &self.aad)?)
+    }
+}
+
+/// Decrypts a stream one segment at a time, in order
+///
+/// The caller does all reading, so whether to read the rest of the input
+/// after a failed segment (see [`DecryptorOptions::drain_on_failure`]) is
+/// up to it.
+pub struct StreamDecryptor {
+    cipher: Cipher,
+    prefix: [u8; NONCE_PREFIX_LENGTH],
+    aad: Vec<u8>,
+    counter: u32,
+}
+
+impl StreamDecryptor {
+    /// Start decrypting a stream
+    ///
+    /// # Arguments
+    /// * `key` - The encryption key (must be 16 or 32 bytes)
+    /// * `nonce_prefix` - The nonce prefix the stream was encrypted with
+    /// * `aad` - Additional authenticated data (must match what was used for encryption)
+    ///
+    /// # Errors
+    /// Returns `CryptoError::InvalidKeySize` if key length is invalid
+    pub fn new(
+        key: &[u8],
+        nonce_prefix: &[u8; NONCE_PREFIX_LENGTH],
+        aad: &[u8],
+    ) -> Result<Self, CryptoError> {
+        Ok(StreamDecryptor {
+            cipher: Cipher::new(key)?,
+            prefix: *nonce_prefix,
+            aad: aad.to_vec(),
+            counter: 0,
+        })
+    }
+
+    fn decrypt_at(&self, counter: u32, last: bool, ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let nonce = segment_nonce(&self.prefix, counter, last);
+        self.cipher.decrypt(&nonce, ciphertext, &self.aad).ok()
+    }
+
+    /// Decrypt the next segment, which is not the last one
+    ///
+    /// A failed segment is not consumed: the next call expects the same
+    /// segment again.
+    ///
+    /// # Errors
+    /// Returns `StreamError::CorruptSegment` if the segment fails
+    /// authentication (including when it is actually the final segment)
+    pub fn decrypt_next(&mut self, ciphertext: &[u8]) -> StreamResult<Vec<u8>> {
+        let next = self
+            .counter
+            .checked_add(1)
+            .ok_or(StreamError::TooManySegments)?;
+        let plaintext = self
+            .decrypt_at(self.counter, false, ciphertext)
+            .ok_or(StreamError::CorruptSegment(self.counter))?;
+        self.counter = next;
+        Ok(plaintext)
+    }
+
+    /// Decrypt the final segment, ending the stream
+    ///
+    /// # Errors
+    /// Returns `StreamError::Truncated` if the segment authenticates but is
+    /// not marked as the final one, or `StreamError::CorruptSegment` if it
+    /// fails authentication
+    pub fn decrypt_last(self, ciphertext: &[u8]) -> StreamResult<Vec<u8>> {
+        if let Some(plaintext) = self.decrypt_at(self.counter, true, ciphertext) {
+            return Ok(plaintext);
+        }
+        match self.decrypt_at(self.counter, false, ciphertext) {
+            Some(_) => Err(StreamError::Truncated),
+            None => Err(StreamError::CorruptSegment(self.counter)),
+        }
+    }
+}
+
+/// Parsed stream header
+struct Header {
+    bytes: [u8; HEADER_LENGTH],
+    segment_size: usize,
+    prefix: [u8; NONCE_PREFIX_LENGTH],
+}
+
+impl Header {
+    fn new(segment_size: usize, prefix: [u8; NONCE_PREFIX_LENGTH]) -> StreamResult<Self> {
+        check_segment_size(segment_size)?;
+        let mut bytes = [0u8; HEADER_LENGTH];
+        bytes[..MAGIC.len()].copy_from_slice(MAGIC);
+        // Below MAX_SEGMENT_SIZE, so the cast is lossless
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(segment_size as u32).to_le_bytes());
+        bytes[MAGIC.len() + 4..].copy_from_slice(&prefix);
+        Ok(Header {
+            bytes,
+            segment_size,
+            prefix,
+        })
+    }
+
+    fn parse(bytes: [u8; HEADER_LENGTH]) -> StreamResult<Self> {
+        if &bytes[..MAGIC.len()] != MAGIC {
+            return Err(StreamError::NotAStream);
+        }
+        let mut size = [0u8; 4];
+        size.copy_from_slice(&bytes[MAGIC.len()..MAGIC.len() + 4]);
+        let segment_size =
+            usize::try_from(u32::from_le_bytes(size)).map_err(|_| StreamError::NotAStream)?;
+        check_segment_size(segment_size)?;
+        let mut prefix = [0u8; NONCE_PREFIX_LENGTH];
+        prefix.copy_from_slice(&bytes[MAGIC.len() + 4..]);
+        Ok(Header {
+            bytes,
+            segment_size,
+            prefix,
+        })
+    }
+
+    /// AAD of every segment: the header followed by the caller's AAD
+    fn segment_aad(&self, aad: &[u8]) -> Vec<u8> {
+        let mut segment_aad = self.bytes.to_vec();
+        segment_aad.extend_from_slice(aad);
+        segment_aad
+    }
+}
+
+/// An `io::Write` adapter that encrypts everything written to it
+///
+/// Plaintext is buffered until a whole segment is available. Call
+/// [`EncryptingWriter::finish`] after the last write: it writes the final
+/// segment, and without it the stream is truncated and will not decrypt.
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    encryptor: StreamEncryptor,
+    buffer: Zeroizing<Vec<u8>>,
+    segment_size: usize,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Start a stream with [`DEFAULT_SEGMENT_SIZE`] segments and a random
+    /// nonce prefix, writing the header to `inner`
+    ///
+    /// # Errors
+    /// Returns `StreamError::Crypto` if the key length is invalid or
+    /// `StreamError::Io` if the header cannot be written
+    pub fn new(inner: W, key: &[u8], aad: &[u8]) -> StreamResult<Self> {
+        Self::with_segment_size(inner, key, aad, DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// Like [`EncryptingWriter::new`] with a custom segment size
+    ///
+    /// # Errors
+    /// Additionally returns `StreamError::InvalidSegmentSize` if
+    /// `segment_size` is zero or above [`MAX_SEGMENT_SIZE`]
+    pub fn with_segment_size(
+        mut inner: W,
+        key: &[u8],
+        aad: &[u8],
+        segment_size: usize,
+    ) -> StreamResult<Self> {
+        let mut prefix = [0u8; NONCE_PREFIX_LENGTH];
+        rand::rngs::OsRng.try_fill_bytes(&mut prefix).unwrap(); // CSPRNG
+        let header = Header::new(segment_size, prefix)?;
+        let encryptor = StreamEncryptor::new(key, &prefix, &header.segment_aad(aad))?;
+        inner.write_all(&header.bytes)?;
+        Ok(EncryptingWriter {
+            inner,
+            encryptor,
+            buffer: Zeroizing::new(Vec::with_capacity(segment_size)),
+            segment_size,
+        })
+    }
+
+    /// Write the final segment and return the inner writer
+    ///
+    /// # Errors
+    /// Returns `StreamError::Io` if writing fails
+    pub fn finish(mut self) -> StreamResult<W> {
+        let ciphertext = self.encryptor.encrypt_last(&self.buffer)?;
+        self.inner.write_all(&ciphertext)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // A full buffer is only written once more data arrives, because
+        // until then it might be the final segment
+        if self.buffer.len() == self.segment_size {
+            let ciphertext = self.encryptor.encrypt_next(&self.buffer)?;
+            self.inner.write_all(&ciphertext)?;
+            self.buffer.clear();
+        }
+        let len = buf.len().min(self.segment_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    /// Flushes the inner writer; buffered plaintext stays buffered until a
+    /// segment is complete or [`EncryptingWriter::finish`] is called
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Read until `buf` is full or the input ends; returns the bytes read
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Options for [`DecryptingReader::with_options`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecryptorOptions {
+    drain_on_failure: bool,
+}
+
+impl DecryptorOptions {
+    /// The options used by [`DecryptingReader::new`]: stop reading at the
+    /// first segment that fails
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// After a segment fails authentication, read and discard the rest of
+    /// the input before returning the error
+    ///
+    /// Stopping at once shows whoever supplies the input how far into the
+    /// stream it was tampered with; draining it hides that, at the cost of
+    /// reading input that will never be used. The error is the same either
+    /// way.
+    pub fn drain_on_failure(mut self, drain: bool) -> Self {
+        self.drain_on_failure = drain;
+        self
+    }
+}
+
+/// An `io::Read` adapter that decrypts a stream written by [`EncryptingWriter`]
+///
+/// Each segment is authenticated before any of its plaintext is returned.
+/// Reaching the end (`Ok(0)`) means the final segment authenticated; a
+/// truncated stream is an error, never a silent early end. A segment that
+/// fails authentication ends the stream: every later read returns the same
+/// error.
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    options: DecryptorOptions,
+    /// `None` once the final segment was decrypted or any segment failed
+    decryptor: Option<StreamDecryptor>,
+    /// Why a segment failed, so later reads keep failing
+    failure: Option<StreamError>,
+    segment_size: usize,
+    /// Ciphertext of the segment being read
+    ciphertext: Vec<u8>,
+    /// First byte of the following segment, read to tell whether the
+    /// current one is the last
+    lookahead: Option<u8>,
+    plaintext: Zeroizing<Vec<u8>>,
+    position: usize,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Read the stream header from `inner` and prepare to decrypt
+    ///
+    /// # Errors
+    /// Returns `StreamError::NotAStream` or `StreamError::InvalidSegmentSize`
+    /// for a bad header, `StreamError::Crypto` for an invalid key, or
+    /// `StreamError::Io` if reading fails
+    pub fn new(inner: R, key: &[u8], aad: &[u8]) -> StreamResult<Self> {
+        Self::with_options(inner, key, aad, DecryptorOptions::new())
+    }
+
+    /// Like [`DecryptingReader::new`] with non-default options
+    ///
+    /// # Errors
+    /// As for [`DecryptingReader::new`]
+    pub fn with_options(
+        mut inner: R,
+        key: &[u8],
+        aad: &[u8],
+        options: DecryptorOptions,
+    ) -> StreamResult<Self> {
+        let mut bytes = [0u8; HEADER_LENGTH];
+        if read_full(&mut inner, &mut bytes)? < HEADER_LENGTH {
+            return Err(StreamError::NotAStream);
+        }
+        let header = Header::parse(bytes)?;
+        let decryptor = StreamDecryptor::new(key, &header.prefix, &header.segment_aad(aad))?;
+        Ok(DecryptingReader {
+            inner,
+            options,
+            decryptor: Some(decryptor),
+            failure: None,
+            segment_size: header.segment_size,
+            ciphertext: Vec::with_capacity(header.segment_size + TAG_LENGTH),
+            lookahead: None,
+            plaintext: Zeroizing::new(Vec::new()),
+            position: 0,
+        })
+    }
+
+    /// Read and decrypt the next segment into `self.plaintext`
+    fn next_segment(&mut self) -> StreamResult<()> {
+        let segment_length = self.segment_size + TAG_LENGTH;
+        self.ciphertext.clear();
+        self.ciphertext.extend(self.lookahead.take());
+        let start = self.ciphertext.len();
+        self.ciphertext.resize(segment_length, 0);
+        let filled = start + read_full(&mut self.inner, &mut self.ciphertext[start..])?;
+        self.ciphertext.truncate(filled);
+
+        let mut next = [0u8; 1];
+        let last = filled < segment_length || read_full(&mut self.inner, &mut next)? == 0;
+        if !last {
+            self.lookahead = Some(next[0]);
+        }
+
+        let Some(mut decryptor) = self.decryptor.take() else {
+            return Ok(());
+        };
+        let plaintext = if filled == 0 {
+            // Even an empty final segment holds its tag
+            Err(StreamError::Truncated)
+        } else if last {
+            decryptor.decrypt_last(&self.ciphertext)
+        } else {
+            decryptor
+                .decrypt_next(&self.ciphertext)
+                .inspect(|_| self.decryptor = Some(decryptor))
+        };
+        if plaintext.is_err() && self.options.drain_on_failure {
+            // The input is of no more use, so a failure to read it changes
+            // nothing about the error
+            self.lookahead = None;
+            let _ = io::copy(&mut self.inner, &mut io::sink());
+        }
+        // The failed segment has been read past, so carrying on with the next
+        // one would silently skip it
+        *self.plaintext = plaintext.inspect_err(|e| self.failure = e.repeat())?;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if let Some(e) = self.failure.as_ref().and_then(StreamError::repeat) {
+                return Err(e.into());
+            }
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            self.plaintext.clear();
+            self.position = 0;
+            self.next_segment()?;
+        }
+        let len = buf.len().min(self.plaintext.len() - self.position);
+        buf[..len].copy_from_slice(&self.plaintext[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Whether `data` starts like a stream written by [`EncryptingWriter`] (its
+/// magic)
+pub fn is_stream(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
@@ -0,0 +1,54 @@
+//! Tests for backend detection and hardware requirements
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // `backend` is part of the std API
+
+use aes_gcm_siv_impl::{
+    backend_info, classify_io_error, BackendError, BackendInfo, BackendRequirement, ErrorClass,
+};
+
+const HARDWARE: BackendInfo = BackendInfo {
+    hardware_aes: true,
+    hardware_polyval: true,
+};
+const SOFTWARE: BackendInfo = BackendInfo {
+    hardware_aes: false,
+    hardware_polyval: false,
+};
+const PARTIAL: BackendInfo = BackendInfo {
+    hardware_aes: true,
+    hardware_polyval: false,
+};
+
+#[test]
+fn test_requirement_any_always_passes() {
+    for info in [HARDWARE, SOFTWARE, PARTIAL] {
+        assert_eq!(BackendRequirement::Any.check(&info), Ok(()));
+    }
+    assert_eq!(BackendRequirement::default(), BackendRequirement::Any);
+}
+
+#[test]
+fn test_requirement_hardware_refuses_software() {
+    assert_eq!(BackendRequirement::HardwareAes.check(&HARDWARE), Ok(()));
+    assert_eq!(
+        BackendRequirement::HardwareAes.check(&SOFTWARE),
+        Err(BackendError::HardwareAesUnavailable(SOFTWARE))
+    );
+    let err = BackendRequirement::HardwareAes.check(&PARTIAL).unwrap_err();
+    assert_eq!(err, BackendError::HardwareAesUnavailable(PARTIAL));
+    assert_eq!(err.classify(), ErrorClass::Resource);
+    assert_eq!(classify_io_error(&err.clone().into()), ErrorClass::Resource);
+    assert_eq!(
+        err.to_string(),
+        "Hardware AES acceleration is not available (AES: hardware, POLYVAL: software)"
+    );
+}
+
+#[test]
+fn test_backend_info_display() {
+    assert_eq!(SOFTWARE.to_string(), "AES: software, POLYVAL: software");
+    assert_eq!(PARTIAL.to_string(), "AES: hardware, POLYVAL: software");
+    // Detection itself must not panic on any CPU
+    let _ = backend_info().to_string();
+}
@@ -0,0 +1,191 @@
+//! Tests for the encrypted block file
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // `block_file` is part of the std API
+
+use aes_gcm_siv_impl::block_file::BLOCK_SIZE;
+use aes_gcm_siv_impl::{BlockCipherFile, BlockFileError, ErrorClass};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const KEY: [u8; 32] = [0x42; 32];
+
+fn block(fill: u8) -> Vec<u8> {
+    vec![fill; BLOCK_SIZE]
+}
+
+/// The range of bytes that differ between two snapshots of a file
+fn changed_range(before: &[u8], after: &[u8]) -> std::ops::Range<usize> {
+    let start = before.iter().zip(after).position(|(a, b)| a != b).unwrap();
+    let end = before.len()
+        - before
+            .iter()
+            .rev()
+            .zip(after.iter().rev())
+            .position(|(a, b)| a != b)
+            .unwrap();
+    start..end
+}
+
+fn read_all(path: &Path) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    std::fs::File::open(path)
+        .unwrap()
+        .read_to_end(&mut bytes)
+        .unwrap();
+    bytes
+}
+
+fn overwrite(path: &Path, offset: usize, bytes: &[u8]) {
+    let mut file = OpenOptions::new().write(true).open(path).unwrap();
+    file.seek(SeekFrom::Start(offset as u64)).unwrap();
+    file.write_all(bytes).unwrap();
+}
+
+#[test]
+fn test_write_read_rewrite_and_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blocks.bin");
+
+    let mut file = BlockCipherFile::create(&path, &KEY, 8).unwrap();
+    assert_eq!(file.block_count(), 8);
+    assert_eq!(file.read_block(5).unwrap(), block(0));
+
+    file.write_block(2, &block(0xaa)).unwrap();
+    file.write_block(5, &block(0xbb)).unwrap();
+    file.write_block(2, &block(0xcc)).unwrap();
+    assert_eq!(file.read_block(2).unwrap(), block(0xcc));
+    assert_eq!(file.generation(2), Some(2));
+    drop(file);
+
+    let mut file = BlockCipherFile::open(&path, &KEY).unwrap();
+    assert_eq!(file.read_block(2).unwrap(), block(0xcc));
+    assert_eq!(file.read_block(5).unwrap(), block(0xbb));
+    assert_eq!(file.read_block(0).unwrap(), block(0));
+    assert_eq!(file.generation(2), Some(2));
+    assert_eq!(file.generation(5), Some(1));
+}
+
+#[test]
+fn test_invalid_arguments() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blocks.bin");
+    let mut file = BlockCipherFile::create(&path, &KEY, 2).unwrap();
+
+    assert!(matches!(
+        file.read_block(2),
+        Err(BlockFileError::BlockOutOfRange {
+            index: 2,
+            block_count: 2
+        })
+    ));
+    assert!(matches!(
+        file.write_block(0, &[0u8; 10]),
+        Err(BlockFileError::InvalidBlockLength(10))
+    ));
+    assert!(matches!(
+        BlockCipherFile::open(&path, &[0x24; 32]),
+        Err(BlockFileError::CorruptSuperblock)
+    ));
+
+    std::fs::write(dir.path().join("other.bin"), b"not a block file").unwrap();
+    assert!(matches!(
+        BlockCipherFile::open(dir.path().join("other.bin"), &KEY),
+        Err(BlockFileError::NotABlockFile)
+    ));
+}
+
+#[test]
+fn test_torn_write_reported_for_that_block_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blocks.bin");
+    let mut file = BlockCipherFile::create(&path, &KEY, 4).unwrap();
+    for index in 0..4 {
+        file.write_block(index, &block(index as u8 + 1)).unwrap();
+    }
+
+    // Rewrite block 1, then put its previous ciphertext back, as if the
+    // block write never reached the disk after the superblock update
+    let before = read_all(&path);
+    file.write_block(1, &block(0xee)).unwrap();
+    drop(file);
+    let slot = before.len() - 3 * (BLOCK_SIZE + 16);
+    overwrite(&path, slot, &before[slot..slot + BLOCK_SIZE + 16]);
+
+    let mut file = BlockCipherFile::open(&path, &KEY).unwrap();
+    let err = file.read_block(1).unwrap_err();
+    assert!(matches!(err, BlockFileError::TornWrite(1)), "{}", err);
+    assert_eq!(err.classify(), ErrorClass::AuthFailure);
+    for index in [0, 2, 3] {
+        assert_eq!(file.read_block(index).unwrap(), block(index as u8 + 1));
+    }
+
+    // Rewriting the block repairs it
+    file.write_block(1, &block(0x11)).unwrap();
+    assert_eq!(file.read_block(1).unwrap(), block(0x11));
+}
+
+#[test]
+fn test_corrupt_block_reported_for_that_block_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blocks.bin");
+    let mut file = BlockCipherFile::create(&path, &KEY, 4).unwrap();
+    for index in 0..4 {
+        file.write_block(index, &block(index as u8 + 1)).unwrap();
+    }
+    drop(file);
+
+    let len = read_all(&path).len();
+    let block_2 = len - 2 * (BLOCK_SIZE + 16);
+    overwrite(&path, block_2 + 100, b"\xff\xff");
+
+    let mut file = BlockCipherFile::open(&path, &KEY).unwrap();
+    assert!(matches!(
+        file.read_block(2),
+        Err(BlockFileError::CorruptBlock(2))
+    ));
+    for index in [0, 1, 3] {
+        assert_eq!(file.read_block(index).unwrap(), block(index as u8 + 1));
+    }
+}
+
+#[test]
+fn test_damaged_superblock_copy_falls_back_to_the_other() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blocks.bin");
+    let mut file = BlockCipherFile::create(&path, &KEY, 4).unwrap();
+    file.write_block(0, &block(0x01)).unwrap();
+    let before = read_all(&path);
+    file.write_block(3, &block(0x03)).unwrap();
+    drop(file);
+
+    // The last update went to the copy that changed; tear it
+    let after = read_all(&path);
+    let torn = changed_range(&before, &after).start;
+    overwrite(&path, torn, b"\x00\x00\x00\x00");
+
+    let mut file = BlockCipherFile::open(&path, &KEY).unwrap();
+    assert_eq!(file.read_block(0).unwrap(), block(0x01));
+    // The surviving copy predates the write to block 3
+    assert_eq!(file.generation(3), Some(0));
+}
+
+#[test]
+fn test_hostile_block_count_is_rejected_before_allocating() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hostile.bin");
+    for block_count in [u32::MAX, u32::MAX - 1, 1 << 31] {
+        let mut header = b"AGSVBLK1".to_vec();
+        header.extend_from_slice(&(BLOCK_SIZE as u32).to_le_bytes());
+        header.extend_from_slice(&block_count.to_le_bytes());
+        header.extend_from_slice(&[0u8; 4]);
+        header.resize(4096, 0);
+        std::fs::write(&path, &header).unwrap();
+
+        assert!(matches!(
+            BlockCipherFile::open(&path, &KEY),
+            Err(BlockFileError::NotABlockFile | BlockFileError::LengthOverflow)
+        ));
+    }
+}
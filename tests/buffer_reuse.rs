@@ -0,0 +1,69 @@
+//! Tests for buffer-reusing decryption
+
+#![forbid(unsafe_code)]
+
+use aes_gcm_siv_impl::{Cipher, CryptoError};
+use hex_literal::hex;
+
+const KEY: [u8; 32] = hex!("0100000000000000000000000000000000000000000000000000000000000000");
+const NONCE: [u8; 12] = hex!("030000000000000000000000");
+
+#[test]
+fn test_decrypt_into_buf_reuses_buffer() {
+    let cipher = Cipher::new(&KEY).unwrap();
+    let mut buf = Vec::new();
+
+    let long = cipher.encrypt(&NONCE, &[0x11; 256], b"").unwrap();
+    assert_eq!(
+        cipher.decrypt_into_buf(&NONCE, &long, b"", &mut buf),
+        Ok(256)
+    );
+    assert_eq!(buf, [0x11; 256]);
+    let capacity = buf.capacity();
+
+    let short = cipher.encrypt(&NONCE, b"short", b"aad").unwrap();
+    assert_eq!(
+        cipher.decrypt_into_buf(&NONCE, &short, b"aad", &mut buf),
+        Ok(5)
+    );
+    assert_eq!(buf, b"short");
+    assert_eq!(buf.capacity(), capacity, "buffer should not reallocate");
+}
+
+#[test]
+fn test_decrypt_into_buf_zeroizes_on_auth_failure() {
+    let cipher = Cipher::new(&KEY).unwrap();
+    let mut buf = Vec::new();
+    let ciphertext = cipher.encrypt(&NONCE, b"previous record", b"").unwrap();
+    cipher
+        .decrypt_into_buf(&NONCE, &ciphertext, b"", &mut buf)
+        .unwrap();
+
+    let mut tampered = ciphertext.clone();
+    tampered[0] ^= 1;
+    let result = cipher.decrypt_into_buf(&NONCE, &tampered, b"", &mut buf);
+    assert_eq!(result, Err(CryptoError::Auth));
+    assert!(buf.is_empty(), "no stale plaintext may remain visible");
+}
+
+// The free function validates the key itself, so it has an error path of its
+// own before any `Cipher` exists
+#[test]
+#[allow(deprecated)]
+fn test_decrypt_into_buf_zeroizes_on_invalid_input() {
+    let mut buf = b"stale plaintext".to_vec();
+    let result = aes_gcm_siv_impl::decrypt_into_buf(&KEY[..12], &NONCE, &[0; 32], b"", &mut buf);
+    assert_eq!(result, Err(CryptoError::InvalidKeySize));
+    assert!(buf.is_empty());
+
+    let mut buf = b"stale plaintext".to_vec();
+    let result = aes_gcm_siv_impl::decrypt_into_buf(&KEY, &NONCE[..8], &[0; 32], b"", &mut buf);
+    assert_eq!(result, Err(CryptoError::InvalidNonceSize));
+    assert!(buf.is_empty());
+
+    let cipher = Cipher::new(&KEY).unwrap();
+    let mut buf = b"stale plaintext".to_vec();
+    let result = cipher.decrypt_into_buf(&NONCE[..8], &[0; 32], b"", &mut buf);
+    assert_eq!(result, Err(CryptoError::InvalidNonceSize));
+    assert!(buf.is_empty());
+}
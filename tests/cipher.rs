@@ -0,0 +1,133 @@
+//! Tests for the reusable `Cipher` type
+
+#![forbid(unsafe_code)]
+
+use aes_gcm_siv_impl::{Cipher, CryptoError, KeySize, TAG_LENGTH};
+
+const KEY_128: [u8; 16] = [0x11; 16];
+const KEY_256: [u8; 32] = [0x22; 32];
+const NONCE: [u8; 12] = [0x33; 12];
+
+#[test]
+#[allow(deprecated)] // compares against the free functions on purpose
+fn test_matches_one_shot_functions() {
+    for key in [&KEY_128[..], &KEY_256[..]] {
+        let cipher = Cipher::new(key).unwrap();
+        assert_eq!(cipher.key_size(), KeySize::from_key_len(key.len()).unwrap());
+        for len in [0, 1, 16, 100] {
+            let plaintext = vec![0xa5; len];
+            let ciphertext = cipher.encrypt(&NONCE, &plaintext, b"aad").unwrap();
+            assert_eq!(
+                ciphertext,
+                aes_gcm_siv_impl::encrypt(key, &NONCE, &plaintext, b"aad").unwrap()
+            );
+            assert_eq!(
+                cipher.decrypt(&NONCE, &ciphertext, b"aad").unwrap(),
+                plaintext
+            );
+            assert_eq!(
+                aes_gcm_siv_impl::decrypt(key, &NONCE, &ciphertext, b"aad").unwrap(),
+                plaintext
+            );
+        }
+    }
+}
+
+#[test]
+fn test_in_place_reuses_the_buffer() {
+    let cipher = Cipher::new(&KEY_256).unwrap();
+    let mut buf = Vec::with_capacity(64 + TAG_LENGTH);
+    let capacity = buf.capacity();
+
+    for len in [64, 10, 0, 64] {
+        let plaintext = vec![len as u8; len];
+        buf.clear();
+        buf.extend_from_slice(&plaintext);
+        cipher.encrypt_in_place(&NONCE, b"", &mut buf).unwrap();
+        assert_eq!(buf, cipher.encrypt(&NONCE, &plaintext, b"").unwrap());
+
+        cipher.decrypt_in_place(&NONCE, b"", &mut buf).unwrap();
+        assert_eq!(buf, plaintext);
+        assert_eq!(buf.capacity(), capacity);
+    }
+}
+
+#[test]
+fn test_in_place_failure_clears_the_buffer() {
+    let cipher = Cipher::new(&KEY_256).unwrap();
+    let mut buf = cipher.encrypt(&NONCE, b"Secret message", b"").unwrap();
+    buf[0] ^= 1;
+    assert_eq!(
+        cipher.decrypt_in_place(&NONCE, b"", &mut buf),
+        Err(CryptoError::Auth)
+    );
+    assert!(buf.is_empty());
+
+    let mut buf = b"data".to_vec();
+    assert_eq!(
+        cipher.encrypt_in_place(&NONCE[..11], b"", &mut buf),
+        Err(CryptoError::InvalidNonceSize)
+    );
+    assert_eq!(buf, b"data");
+    assert_eq!(
+        cipher.decrypt_in_place(&NONCE[..11], b"", &mut buf),
+        Err(CryptoError::InvalidNonceSize)
+    );
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_invalid_sizes_and_debug() {
+    assert_eq!(
+        Cipher::new(&KEY_256[..24]).unwrap_err(),
+        CryptoError::InvalidKeySize
+    );
+    let cipher = Cipher::new(&KEY_128).unwrap();
+    assert_eq!(
+        cipher.encrypt(&[0; 8], b"", b""),
+        Err(CryptoError::InvalidNonceSize)
+    );
+    assert_eq!(
+        cipher.decrypt(&[0; 16], &[0; TAG_LENGTH], b""),
+        Err(CryptoError::InvalidNonceSize)
+    );
+
+    // No key material in the debug output
+    assert_eq!(format!("{:?}", cipher), "Cipher { key_size: Aes128, .. }");
+}
+
+#[test]
+#[allow(deprecated)] // compares against the free function on purpose
+fn test_decrypt_into_buf_matches_free_function() {
+    let cipher = Cipher::new(&KEY_256).unwrap();
+    let mut buf = Vec::new();
+    let mut free_buf = Vec::new();
+
+    for plaintext in [&[0x5a; 100][..], b"short", b""] {
+        let ciphertext = cipher.encrypt(&NONCE, plaintext, b"aad").unwrap();
+        assert_eq!(
+            cipher.decrypt_into_buf(&NONCE, &ciphertext, b"aad", &mut buf),
+            Ok(plaintext.len())
+        );
+        assert_eq!(buf, plaintext);
+        assert_eq!(
+            aes_gcm_siv_impl::decrypt_into_buf(
+                &KEY_256,
+                &NONCE,
+                &ciphertext,
+                b"aad",
+                &mut free_buf
+            ),
+            Ok(plaintext.len())
+        );
+        assert_eq!(free_buf, plaintext);
+    }
+
+    let mut tampered = cipher.encrypt(&NONCE, b"data", b"").unwrap();
+    tampered[0] ^= 1;
+    assert_eq!(
+        cipher.decrypt_into_buf(&NONCE, &tampered, b"", &mut buf),
+        Err(CryptoError::Auth)
+    );
+    assert!(buf.is_empty());
+}
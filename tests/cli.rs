@@ -0,0 +1,1692 @@
+//! Integration tests for the command-line interface
+
+#![forbid(unsafe_code)]
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const KEY: &str = "000102030405060708090a0b0c0d0e0f000102030405060708090a0b0c0d0e0f";
+const NONCE: &str = "0123456789abcdef01234567";
+
+fn cli() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_aes-gcm-siv-impl"))
+}
+
+fn run(args: &[&str]) -> Output {
+    cli().args(args).output().expect("failed to run CLI")
+}
+
+fn encrypt_file(dir: &Path, aad_args: &[&str]) -> Output {
+    let input = dir.join("plain.txt");
+    let output = dir.join("cipher.bin");
+    let mut args = vec![
+        "encrypt",
+        input.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--key",
+        KEY,
+        "--nonce",
+        NONCE,
+    ];
+    args.extend_from_slice(aad_args);
+    run(&args)
+}
+
+fn decrypt_file(dir: &Path, aad_args: &[&str]) -> Output {
+    let input = dir.join("cipher.bin");
+    let output = dir.join("decrypted.txt");
+    let mut args = vec![
+        "decrypt",
+        input.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--key",
+        KEY,
+    ];
+    args.extend_from_slice(aad_args);
+    run(&args)
+}
+
+fn setup() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("plain.txt"), b"Secret message").unwrap();
+    dir
+}
+
+#[test]
+fn test_missing_and_empty_aad_are_equivalent() {
+    // Pin all four combinations: no flag and `--aad ""` both mean empty AAD.
+    let empty: &[&str] = &["--aad", ""];
+    let none: &[&str] = &[];
+    for (enc, dec) in [(none, none), (none, empty), (empty, none), (empty, empty)] {
+        let dir = setup();
+        assert!(encrypt_file(dir.path(), enc).status.success());
+        let out = decrypt_file(dir.path(), dec);
+        assert!(out.status.success(), "enc {:?} / dec {:?}", enc, dec);
+        assert_eq!(
+            fs::read(dir.path().join("decrypted.txt")).unwrap(),
+            b"Secret message"
+        );
+    }
+}
+
+#[test]
+fn test_aad_mismatch_fails() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &["--aad", "context"])
+        .status
+        .success());
+    assert!(!decrypt_file(dir.path(), &[]).status.success());
+    assert!(!decrypt_file(dir.path(), &["--aad", "other"])
+        .status
+        .success());
+    assert!(decrypt_file(dir.path(), &["--aad", "context"])
+        .status
+        .success());
+}
+
+fn inspect(dir: &Path, input: &str) -> Output {
+    run(&["inspect", dir.join(input).to_str().unwrap()])
+}
+
+#[test]
+fn test_inspect_reports_whether_aad_is_required() {
+    // All four combinations of empty and non-empty AAD at encrypt and decrypt
+    let context: &[&str] = &["--aad", "context"];
+    let none: &[&str] = &[];
+    for (enc, dec, recorded, decrypts) in [
+        (none, none, "AAD: none", true),
+        (none, context, "AAD: none", false),
+        (context, none, "AAD: required, 7 bytes", false),
+        (context, context, "AAD: required, 7 bytes", true),
+    ] {
+        let dir = setup();
+        assert!(encrypt_file(dir.path(), enc).status.success());
+
+        let out = inspect(dir.path(), "cipher.bin");
+        assert!(out.status.success());
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(stdout.contains(recorded), "enc {:?}: {}", enc, stdout);
+
+        let out = decrypt_file(dir.path(), dec);
+        if decrypts {
+            assert!(out.status.success(), "enc {:?} / dec {:?}", enc, dec);
+        } else {
+            assert_eq!(out.status.code(), Some(3), "enc {:?} / dec {:?}", enc, dec);
+        }
+        if enc == context && dec == none {
+            assert!(String::from_utf8_lossy(&out.stderr).contains("pass --aad"));
+        }
+    }
+}
+
+#[test]
+fn test_inspect_prints_the_header() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &[]).status.success());
+    let out = inspect(dir.path(), "cipher.bin");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("format: envelope v1"), "{}", stdout);
+    assert!(stdout.contains("aes256-gcm-siv"));
+    assert!(stdout.contains("key ID: none"));
+    assert!(stdout.contains(&format!("nonce: {}", NONCE)));
+
+    assert!(stdout.contains(&format!(
+        "producer: aes-gcm-siv-impl {}",
+        env!("CARGO_PKG_VERSION")
+    )));
+    let out = inspect(dir.path(), "plain.txt");
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not an envelope"));
+}
+
+#[test]
+fn test_future_format_version_says_what_to_upgrade() {
+    let dir = setup();
+    fs::write(
+        dir.path().join("cipher.bin"),
+        include_bytes!("golden/envelope-v3.bin"),
+    )
+    .unwrap();
+
+    for out in [
+        decrypt_file(dir.path(), &[]),
+        inspect(dir.path(), "cipher.bin"),
+    ] {
+        assert_eq!(out.status.code(), Some(2));
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains(
+                "this file requires format v3 (produced by aes-gcm-siv-impl 2.1); \
+                 this binary supports up to v1"
+            ),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("upgrade"));
+    }
+}
+
+#[test]
+fn test_aad_flag_requires_value() {
+    let dir = setup();
+    let out = encrypt_file(dir.path(), &["--aad"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn test_aad_hex_and_file_match_string() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &["--aad", "context"])
+        .status
+        .success());
+    assert!(
+        decrypt_file(dir.path(), &["--aad-hex", &hex::encode("context")])
+            .status
+            .success()
+    );
+
+    let aad_path = dir.path().join("aad.bin");
+    fs::write(&aad_path, b"context").unwrap();
+    assert!(
+        decrypt_file(dir.path(), &["--aad-file", aad_path.to_str().unwrap()])
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn test_binary_aad() {
+    let dir = setup();
+    let aad_path = dir.path().join("aad.bin");
+    fs::write(&aad_path, [0xff, 0xfe, 0x00, 0x80]).unwrap();
+    assert!(
+        encrypt_file(dir.path(), &["--aad-file", aad_path.to_str().unwrap()])
+            .status
+            .success()
+    );
+    assert!(decrypt_file(dir.path(), &["--aad-hex", "fffe0080"])
+        .status
+        .success());
+}
+
+#[test]
+fn test_aad_options_are_mutually_exclusive() {
+    let dir = setup();
+    let out = encrypt_file(dir.path(), &["--aad", "a", "--aad-hex", "61"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn test_algorithm_defaults_to_aes256() {
+    let dir = setup();
+    let out = encrypt_file(dir.path(), &[]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("aes256-gcm-siv"));
+}
+
+#[test]
+fn test_algorithm_follows_key_length_by_default() {
+    let dir = setup();
+    let input = dir.path().join("plain.txt");
+    let output = dir.path().join("cipher.bin");
+    let decrypted = dir.path().join("decrypted.txt");
+
+    // A 128-bit key without --algorithm encrypts as before the option existed
+    let out = run(&[
+        "encrypt",
+        input.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--key",
+        &KEY[..32],
+    ]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("aes128-gcm-siv"));
+
+    let out = run(&[
+        "decrypt",
+        output.to_str().unwrap(),
+        decrypted.to_str().unwrap(),
+        "--key",
+        &KEY[..32],
+    ]);
+    assert!(out.status.success());
+    assert_eq!(fs::read(&input).unwrap(), fs::read(&decrypted).unwrap());
+}
+
+#[test]
+fn test_algorithm_key_length_mismatch() {
+    let dir = setup();
+    let input = dir.path().join("plain.txt");
+    let output = dir.path().join("cipher.bin");
+    let key_128 = &KEY[..32];
+
+    let out = run(&[
+        "encrypt",
+        input.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--key",
+        key_128,
+        "--algorithm",
+        "aes256-gcm-siv",
+    ]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("requires a 32-byte key"));
+
+    let out = run(&[
+        "encrypt",
+        input.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--key",
+        KEY,
+        "--algorithm",
+        "aes128-gcm-siv",
+    ]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("requires a 16-byte key"));
+
+    let out = run(&[
+        "encrypt",
+        input.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--key",
+        key_128,
+        "--algorithm",
+        "aes128-gcm-siv",
+    ]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("aes128-gcm-siv"));
+}
+
+const KEY_B: &str = "1f1e1d1c1b1a191817161514131211101f1e1d1c1b1a19181716151413121110";
+const KEY_C: &str = "a0a1a2a3a4a5a6a7a8a9aaabacadaeaf";
+const KEY_UNKNOWN: &str = "ffeeddccbbaa99887766554433221100ffeeddccbbaa99887766554433221100";
+
+fn fingerprint(hex_key: &str) -> String {
+    aes_gcm_siv_impl::key_fingerprint(&hex::decode(hex_key).unwrap())
+        .unwrap()
+        .to_string()
+}
+
+/// A keyring directory with three valid keys plus files that must be skipped
+fn keyring_dir(dir: &Path) -> std::path::PathBuf {
+    let keys = dir.join("keys");
+    fs::create_dir(&keys).unwrap();
+    fs::write(keys.join("2024q1.key"), format!("{}\n", KEY)).unwrap();
+    fs::write(keys.join("2024q2.key"), KEY_B).unwrap();
+    fs::write(keys.join("2024q3.key"), KEY_C).unwrap();
+    fs::write(keys.join("broken.key"), "not hex").unwrap();
+    fs::write(keys.join("short.key"), "0011").unwrap();
+    fs::write(keys.join("README.txt"), "ignored").unwrap();
+    keys
+}
+
+fn encrypt_with(dir: &Path, key: &str, algorithm: &str, output: &str) {
+    let out = run(&[
+        "encrypt",
+        dir.join("plain.txt").to_str().unwrap(),
+        dir.join(output).to_str().unwrap(),
+        "--key",
+        key,
+        "--nonce",
+        NONCE,
+        "--algorithm",
+        algorithm,
+    ]);
+    assert!(out.status.success());
+}
+
+fn decrypt_with_keyring(dir: &Path, keys: &Path, input: &str, extra: &[&str]) -> Output {
+    cli()
+        .arg("decrypt")
+        .arg(dir.join(input))
+        .arg(dir.join("decrypted.txt"))
+        .arg("--keyring-dir")
+        .arg(keys)
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_keyring_dir_tries_all_keys() {
+    let dir = setup();
+    let keys = keyring_dir(dir.path());
+    encrypt_with(dir.path(), KEY_B, "aes256-gcm-siv", "b.bin");
+    encrypt_with(dir.path(), KEY_C, "aes128-gcm-siv", "c.bin");
+
+    for (input, key) in [("b.bin", KEY_B), ("c.bin", KEY_C)] {
+        let out = decrypt_with_keyring(dir.path(), &keys, input, &["--try-all-keys"]);
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        assert!(String::from_utf8_lossy(&out.stdout).contains(&fingerprint(key)));
+        assert_eq!(
+            fs::read(dir.path().join("decrypted.txt")).unwrap(),
+            b"Secret message"
+        );
+
+        // Malformed key files are reported but do not abort loading
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains("warning: skipping") && stderr.contains("broken.key"));
+        assert!(stderr.contains("short.key"));
+        assert!(!stderr.contains("README.txt"));
+    }
+}
+
+#[test]
+fn test_keyring_dir_unknown_key_lists_fingerprints() {
+    let dir = setup();
+    let keys = keyring_dir(dir.path());
+    encrypt_with(dir.path(), KEY_UNKNOWN, "aes256-gcm-siv", "unknown.bin");
+
+    let out = decrypt_with_keyring(dir.path(), &keys, "unknown.bin", &["--try-all-keys"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    for key in [KEY, KEY_B, KEY_C] {
+        assert!(stderr.contains(&fingerprint(key)), "{}", stderr);
+    }
+    assert!(!stderr.contains(&fingerprint(KEY_UNKNOWN)));
+}
+
+#[test]
+fn test_keyring_dir_requires_try_all_keys_for_raw_input() {
+    let dir = setup();
+    let keys = keyring_dir(dir.path());
+    encrypt_with(dir.path(), KEY_B, "aes256-gcm-siv", "b.bin");
+
+    let out = decrypt_with_keyring(dir.path(), &keys, "b.bin", &[]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--try-all-keys"));
+}
+
+#[test]
+fn test_keyring_dir_picks_the_recorded_key_id() {
+    let dir = setup();
+    let keys = keyring_dir(dir.path());
+    let keyring = dir.path().join("keyring.toml");
+    let encrypt = |id: &str, key: &str, output: &str| {
+        fs::write(&keyring, format!("[keys]\n{} = \"{}\"\n", id, key)).unwrap();
+        let out = run(&[
+            "encrypt",
+            dir.path().join("plain.txt").to_str().unwrap(),
+            dir.path().join(output).to_str().unwrap(),
+            "--keyring",
+            keyring.to_str().unwrap(),
+        ]);
+        assert!(out.status.success());
+    };
+
+    // No --try-all-keys needed: the ID names the key file
+    encrypt("2024q2", KEY_B, "b.bin");
+    let out = decrypt_with_keyring(dir.path(), &keys, "b.bin", &[]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains(&fingerprint(KEY_B)) && stdout.contains("2024q2.key"));
+
+    // The named key is the only one tried
+    encrypt("2024q1", KEY_B, "wrong.bin");
+    let out = decrypt_with_keyring(dir.path(), &keys, "wrong.bin", &["--try-all-keys"]);
+    assert_eq!(out.status.code(), Some(3));
+
+    encrypt("2025q1", KEY_UNKNOWN, "unknown.bin");
+    let out = decrypt_with_keyring(dir.path(), &keys, "unknown.bin", &[]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("no 2025q1.key"));
+}
+
+#[test]
+fn test_keyring_dir_keeps_every_name_of_a_repeated_key() {
+    let dir = setup();
+    let keys = keyring_dir(dir.path());
+    // The 2024q2 key carried over into 2025q1 under a new name
+    fs::write(keys.join("2025q1.key"), KEY_B).unwrap();
+    let keyring = dir.path().join("keyring.toml");
+    fs::write(&keyring, format!("[keys]\n2025q1 = \"{}\"\n", KEY_B)).unwrap();
+    let out = run(&[
+        "encrypt",
+        dir.path().join("plain.txt").to_str().unwrap(),
+        dir.path().join("b.bin").to_str().unwrap(),
+        "--keyring",
+        keyring.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+
+    let out = decrypt_with_keyring(dir.path(), &keys, "b.bin", &[]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("2025q1.key"));
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("2025q1.key"));
+
+    // The repeated key is listed once
+    encrypt_with(dir.path(), KEY_UNKNOWN, "aes256-gcm-siv", "unknown.bin");
+    let out = decrypt_with_keyring(dir.path(), &keys, "unknown.bin", &["--try-all-keys"]);
+    assert_eq!(out.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert_eq!(stderr.matches(&fingerprint(KEY_B)).count(), 1, "{}", stderr);
+}
+
+#[cfg(unix)]
+fn exec_args<'a>(dir: &'a Path, command: &[&'a str]) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "exec",
+        "--input",
+        dir.join("cipher.bin").to_str().unwrap(),
+        "--key",
+        KEY,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    args.push("--".to_string());
+    args.extend(command.iter().map(|s| s.to_string()));
+    args
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exec_exposes_plaintext_on_fd() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &[]).status.success());
+
+    let out = cli()
+        .args(exec_args(dir.path(), &["cat", "/dev/fd/3"]))
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(out.stdout, b"Secret message");
+
+    let out = cli()
+        .args(["exec", "--fd", "5"])
+        .args(&exec_args(dir.path(), &["cat", "/dev/fd/5"])[1..])
+        .output()
+        .unwrap();
+    assert_eq!(out.stdout, b"Secret message");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exec_propagates_exit_status() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &[]).status.success());
+
+    let out = cli()
+        .args(exec_args(dir.path(), &["sh", "-c", "exit 7"]))
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(7));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exec_child_that_never_reads() {
+    // Larger than any pipe buffer, so the writer must notice the closed pipe
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("plain.txt"), vec![0x5a; 4 << 20]).unwrap();
+    assert!(encrypt_file(dir.path(), &[]).status.success());
+
+    let out = cli()
+        .args(exec_args(dir.path(), &["true"]))
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exec_does_not_run_command_on_auth_failure() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &["--aad", "context"])
+        .status
+        .success());
+
+    let marker = dir.path().join("ran");
+    let out = cli()
+        .args(exec_args(dir.path(), &["touch", marker.to_str().unwrap()]))
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(!marker.exists());
+}
+
+fn decrypt_with_pin(dir: &Path, input: &str, key: &str, extra: &[&str]) -> Output {
+    let pins = dir.join("pins.toml");
+    cli()
+        .arg("decrypt")
+        .arg(dir.join(input))
+        .arg(dir.join("decrypted.txt"))
+        .args(["--key", key])
+        .arg("--pin-file")
+        .arg(&pins)
+        .args(["--pin-name", "partnerA"])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_pin_first_use_then_match() {
+    let dir = setup();
+    encrypt_with(dir.path(), KEY, "aes256-gcm-siv", "a.bin");
+
+    let out = decrypt_with_pin(dir.path(), "a.bin", KEY, &[]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Pinned key"));
+    let pins = fs::read_to_string(dir.path().join("pins.toml")).unwrap();
+    assert!(pins.contains("partnerA") && pins.contains(&fingerprint(KEY)));
+
+    let out = decrypt_with_pin(dir.path(), "a.bin", KEY, &[]);
+    assert!(out.status.success());
+    assert!(!String::from_utf8_lossy(&out.stdout).contains("Pinned key"));
+}
+
+#[test]
+fn test_pin_mismatch_refused_and_update() {
+    let dir = setup();
+    encrypt_with(dir.path(), KEY, "aes256-gcm-siv", "a.bin");
+    encrypt_with(dir.path(), KEY_B, "aes256-gcm-siv", "b.bin");
+    assert!(decrypt_with_pin(dir.path(), "a.bin", KEY, &[])
+        .status
+        .success());
+    fs::remove_file(dir.path().join("decrypted.txt")).unwrap();
+
+    // The substituted key decrypts fine, but its fingerprint is not the pinned one
+    let out = decrypt_with_pin(dir.path(), "b.bin", KEY_B, &[]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains(&fingerprint(KEY)), "{}", stderr);
+    assert!(stderr.contains(&fingerprint(KEY_B)), "{}", stderr);
+    assert!(
+        !dir.path().join("decrypted.txt").exists(),
+        "no plaintext may be written on a pin mismatch"
+    );
+
+    let out = decrypt_with_pin(dir.path(), "b.bin", KEY_B, &["--update-pin"]);
+    assert!(out.status.success());
+    let pins = fs::read_to_string(dir.path().join("pins.toml")).unwrap();
+    assert!(pins.contains(&fingerprint(KEY_B)) && !pins.contains(&fingerprint(KEY)));
+
+    assert!(!decrypt_with_pin(dir.path(), "a.bin", KEY, &[])
+        .status
+        .success());
+}
+
+fn encrypt_with_history(dir: &Path, key: &str, nonce: &str, extra: &[&str]) -> Output {
+    cli()
+        .arg("encrypt")
+        .arg(dir.join("plain.txt"))
+        .arg(dir.join("cipher.bin"))
+        .args(["--key", key, "--nonce", nonce])
+        .arg("--nonce-history")
+        .arg(dir.join("nonces.txt"))
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_nonce_history_refuses_reuse() {
+    let dir = setup();
+    assert!(encrypt_with_history(dir.path(), KEY, NONCE, &[])
+        .status
+        .success());
+
+    let out = encrypt_with_history(dir.path(), KEY, NONCE, &[]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("already used") && stderr.contains(&fingerprint(KEY)));
+
+    // The same nonce under a different key is a different pair
+    assert!(encrypt_with_history(dir.path(), KEY_B, NONCE, &[])
+        .status
+        .success());
+}
+
+#[test]
+fn test_nonce_history_retry_after_failed_encrypt() {
+    let dir = setup();
+    let plain = dir.path().join("plain.txt");
+    fs::rename(&plain, dir.path().join("elsewhere.txt")).unwrap();
+
+    // The input is missing: nothing was encrypted, so nothing is recorded
+    let out = encrypt_with_history(dir.path(), KEY, NONCE, &[]);
+    assert_eq!(out.status.code(), Some(4));
+    fs::rename(dir.path().join("elsewhere.txt"), &plain).unwrap();
+
+    // The output cannot be written
+    fs::create_dir(dir.path().join("cipher.bin")).unwrap();
+    assert!(!encrypt_with_history(dir.path(), KEY, NONCE, &[])
+        .status
+        .success());
+    fs::remove_dir(dir.path().join("cipher.bin")).unwrap();
+
+    let out = encrypt_with_history(dir.path(), KEY, NONCE, &[]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(!encrypt_with_history(dir.path(), KEY, NONCE, &[])
+        .status
+        .success());
+}
+
+#[test]
+fn test_nonce_history_override() {
+    let dir = setup();
+    assert!(encrypt_with_history(dir.path(), KEY, NONCE, &[])
+        .status
+        .success());
+    let out = encrypt_with_history(dir.path(), KEY, NONCE, &["--i-know-what-im-doing"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("warning"));
+}
+
+#[test]
+fn test_nonce_history_rotation_keeps_file_bounded() {
+    let dir = setup();
+    let nonces: Vec<String> = (0..5u8).map(|i| hex::encode([i; 12])).collect();
+    for nonce in &nonces {
+        let out = encrypt_with_history(dir.path(), KEY, nonce, &["--nonce-history-limit", "3"]);
+        assert!(out.status.success());
+    }
+
+    let history = fs::read_to_string(dir.path().join("nonces.txt")).unwrap();
+    let entries: Vec<&str> = history.lines().filter(|l| !l.starts_with('#')).collect();
+    assert_eq!(entries.len(), 3);
+    assert!(!history.contains(&nonces[0]) && !history.contains(&nonces[1]));
+    assert!(history.contains(&nonces[4]));
+
+    // A rotated-out pair is forgotten, a retained one is still refused
+    let limit = ["--nonce-history-limit", "3"];
+    assert!(encrypt_with_history(dir.path(), KEY, &nonces[0], &limit)
+        .status
+        .success());
+    assert!(!encrypt_with_history(dir.path(), KEY, &nonces[4], &limit)
+        .status
+        .success());
+}
+
+#[test]
+fn test_nonce_history_limit_must_keep_something() {
+    let dir = setup();
+    let out = encrypt_with_history(dir.path(), KEY, NONCE, &["--nonce-history-limit", "0"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--nonce-history-limit"));
+
+    for limit in ["0", "-1"] {
+        let config = profiles_config(
+            dir.path(),
+            &format!("[profiles.backup]\nnonce-history-limit = {}\n", limit),
+        );
+        let out = run_with_profiles(&config, &["profile", "show", "backup"]);
+        assert_eq!(out.status.code(), Some(2), "{}", limit);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains("`nonce-history-limit` must be at least 1"),
+            "{}",
+            stderr
+        );
+    }
+    assert!(!dir.path().join("cipher.bin").exists());
+}
+
+#[test]
+fn test_nonce_history_is_replaced_not_rewritten() {
+    let dir = setup();
+    let history = dir.path().join("nonces.txt");
+    assert!(encrypt_with_history(dir.path(), KEY, NONCE, &[])
+        .status
+        .success());
+    let before = fs::read_to_string(&history).unwrap();
+
+    // What a crash while writing the next history leaves behind
+    fs::write(dir.path().join(".nonces.txt.partial"), "# aes-gcm").unwrap();
+    assert_eq!(fs::read_to_string(&history).unwrap(), before);
+
+    // ...is replaced by the next update, which keeps the earlier pair
+    let other = hex::encode([0x11; 12]);
+    assert!(encrypt_with_history(dir.path(), KEY, &other, &[])
+        .status
+        .success());
+    let after = fs::read_to_string(&history).unwrap();
+    assert!(
+        after.starts_with(&before) && after.contains(&other),
+        "{}",
+        after
+    );
+    assert!(!dir.path().join(".nonces.txt.partial").exists());
+    assert!(dir.path().join(".nonces.txt.lock").exists());
+    assert!(!encrypt_with_history(dir.path(), KEY, NONCE, &[])
+        .status
+        .success());
+
+    // On Unix the history is a fresh file each time, never the one that was
+    // there before
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let inode = fs::metadata(&history).unwrap().ino();
+        let third = hex::encode([0x22; 12]);
+        assert!(encrypt_with_history(dir.path(), KEY, &third, &[])
+            .status
+            .success());
+        assert_ne!(fs::metadata(&history).unwrap().ino(), inode);
+    }
+}
+
+fn encrypt_on_this_cpu(dir: &Path, extra: &[&str]) -> Output {
+    cli()
+        // Not an override: nothing may make the binary believe in hardware
+        // AES the CPU does not have
+        .env("AES_GCM_SIV_SIMULATE_BACKEND", "hardware")
+        .arg("encrypt")
+        .arg(dir.join("plain.txt"))
+        .arg(dir.join("cipher.bin"))
+        .args(["--key", KEY])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+// The binary always checks the real CPU. The refusal logic is tested against
+// stub `BackendInfo` values in tests/backend.rs; this checks that the CLI
+// applies it, on whichever backend the test machine has.
+#[test]
+fn test_require_hardware_aes_follows_the_real_backend() {
+    let hardware = aes_gcm_siv_impl::backend_info().is_hardware_accelerated();
+    let dir = setup();
+
+    let out = encrypt_on_this_cpu(dir.path(), &["--require-hardware-aes"]);
+    assert_eq!(out.status.success(), hardware);
+    if !hardware {
+        assert_eq!(out.status.code(), Some(5));
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains("--allow-software-aes"), "{}", stderr);
+        assert!(!dir.path().join("cipher.bin").exists());
+    }
+
+    let out = encrypt_on_this_cpu(
+        dir.path(),
+        &["--require-hardware-aes", "--allow-software-aes"],
+    );
+    assert!(out.status.success());
+
+    // Without the requirement any backend is used silently
+    assert!(encrypt_on_this_cpu(dir.path(), &[]).status.success());
+}
+
+#[test]
+fn test_version_reports_the_backend() {
+    let out = run(&["--version"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let backend = aes_gcm_siv_impl::backend_info().to_string();
+    assert!(
+        stdout.contains(&format!("{} ({})", env!("CARGO_PKG_VERSION"), backend)),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_exit_codes_follow_error_class() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &["--aad", "context"])
+        .status
+        .success());
+
+    // Authentication failure
+    assert_eq!(decrypt_file(dir.path(), &[]).status.code(), Some(3));
+
+    // Invalid input
+    let out = run(&[
+        "encrypt",
+        dir.path().join("plain.txt").to_str().unwrap(),
+        dir.path().join("other.bin").to_str().unwrap(),
+        "--key",
+        &KEY[..30],
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+
+    // I/O failure
+    let out = run(&[
+        "encrypt",
+        dir.path().join("missing.txt").to_str().unwrap(),
+        dir.path().join("other.bin").to_str().unwrap(),
+        "--key",
+        KEY,
+    ]);
+    assert_eq!(out.status.code(), Some(4));
+
+    // Missing hardware support (5) is checked where the CPU allows, in
+    // test_require_hardware_aes_follows_the_real_backend
+}
+
+/// Write `profiles` as the profiles file of a config directory under `dir`
+fn profiles_config(dir: &Path, profiles: &str) -> std::path::PathBuf {
+    let config = dir.join("config");
+    fs::create_dir_all(config.join("aes-gcm-siv")).unwrap();
+    fs::write(config.join("aes-gcm-siv/profiles.toml"), profiles).unwrap();
+    config
+}
+
+fn run_with_profiles(config: &Path, args: &[&str]) -> Output {
+    cli()
+        .env("XDG_CONFIG_HOME", config)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_profile_defaults_and_precedence() {
+    let dir = setup();
+    let key_file = dir.path().join("backup.key");
+    fs::write(&key_file, format!("{}\n", &KEY[..32])).unwrap();
+    let config = profiles_config(
+        dir.path(),
+        &format!(
+            "[profiles.backup]\nkey-file = {:?}\nalgorithm = \"aes128-gcm-siv\"\naad = \"nightly\"\n",
+            key_file.to_str().unwrap()
+        ),
+    );
+    let plain = dir.path().join("plain.txt");
+    let cipher = dir.path().join("cipher.bin");
+    let decrypted = dir.path().join("decrypted.txt");
+    let (plain, cipher, decrypted) = (
+        plain.to_str().unwrap(),
+        cipher.to_str().unwrap(),
+        decrypted.to_str().unwrap(),
+    );
+
+    let out = run_with_profiles(
+        &config,
+        &[
+            "encrypt",
+            plain,
+            cipher,
+            "--nonce",
+            NONCE,
+            "--profile",
+            "backup",
+        ],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("aes128-gcm-siv"));
+
+    // The profile's key and AAD are used...
+    assert!(run(&[
+        "decrypt",
+        cipher,
+        decrypted,
+        "--key",
+        &KEY[..32],
+        "--aad",
+        "nightly",
+    ])
+    .status
+    .success());
+
+    // ...and explicit flags win over them
+    let out = run_with_profiles(
+        &config,
+        &[
+            "encrypt",
+            plain,
+            cipher,
+            "--nonce",
+            NONCE,
+            "--profile",
+            "backup",
+            "--key",
+            KEY,
+            "--algorithm",
+            "aes256-gcm-siv",
+            "--aad",
+            "manual",
+        ],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(
+        run(&["decrypt", cipher, decrypted, "--key", KEY, "--aad", "manual",])
+            .status
+            .success()
+    );
+
+    let out = run_with_profiles(&config, &["profile", "list"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "backup\n");
+    let out = run_with_profiles(&config, &["profile", "show", "backup"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("algorithm = \"aes128-gcm-siv\""));
+}
+
+#[test]
+fn test_profile_unknown_setting_rejected() {
+    let dir = setup();
+    let config = profiles_config(dir.path(), "[profiles.backup]\nchunk-sise = 65536\n");
+    for args in [&["profile", "list"][..], &["profile", "show", "backup"]] {
+        let out = run_with_profiles(&config, args);
+        assert_eq!(out.status.code(), Some(2));
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains("unknown setting `chunk-sise`"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn test_profile_refuses_secrets() {
+    let dir = setup();
+    for profile in [
+        format!("[profiles.backup]\nkey = \"{}\"\n", KEY),
+        "[profiles.backup]\npassword = \"hunter2\"\n".to_string(),
+        format!("[profiles.backup]\nkey-env = \"{}\"\n", KEY),
+        format!("[profiles.backup]\nkey-file = \"{}\"\n", &KEY[..32]),
+    ] {
+        let config = profiles_config(dir.path(), &profile);
+        let out = run_with_profiles(
+            &config,
+            &[
+                "encrypt",
+                dir.path().join("plain.txt").to_str().unwrap(),
+                dir.path().join("cipher.bin").to_str().unwrap(),
+                "--profile",
+                "backup",
+            ],
+        );
+        assert!(!out.status.success(), "{}", profile);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains("must not contain secrets"), "{}", stderr);
+        assert!(!stderr.contains(KEY), "{}", stderr);
+        assert!(!dir.path().join("cipher.bin").exists());
+    }
+}
+
+fn gui_decrypt(dir: &Path, input: &str, extra: &[&str]) -> Output {
+    cli()
+        .arg("gui-decrypt")
+        .arg(dir.join(input))
+        .args(extra)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_gui_decrypt_never_overwrites() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &[]).status.success());
+    fs::rename(
+        dir.path().join("cipher.bin"),
+        dir.path().join("notes.txt.agsiv"),
+    )
+    .unwrap();
+    fs::write(dir.path().join("notes.txt"), b"keep me").unwrap();
+
+    let args = ["--key", KEY];
+    for expected in ["notes (1).txt", "notes (2).txt"] {
+        let out = gui_decrypt(dir.path(), "notes.txt.agsiv", &args);
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        assert_eq!(
+            fs::read(dir.path().join(expected)).unwrap(),
+            b"Secret message"
+        );
+    }
+    assert_eq!(fs::read(dir.path().join("notes.txt")).unwrap(), b"keep me");
+
+    // Without the encrypted extension the output gets a suffix instead
+    fs::copy(dir.path().join("notes.txt.agsiv"), dir.path().join("blob")).unwrap();
+    assert!(gui_decrypt(dir.path(), "blob", &args).status.success());
+    assert!(dir.path().join("blob.decrypted").exists());
+}
+
+#[test]
+fn test_gui_decrypt_without_terminal_fails_cleanly() {
+    let dir = setup();
+    assert!(encrypt_file(dir.path(), &[]).status.success());
+    fs::rename(
+        dir.path().join("cipher.bin"),
+        dir.path().join("notes.txt.agsiv"),
+    )
+    .unwrap();
+
+    let out = gui_decrypt(dir.path(), "notes.txt.agsiv", &[]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not a terminal"));
+
+    // Only raw input needs a nonce
+    let out = gui_decrypt(dir.path(), "notes.txt.agsiv", &["--key", KEY, "--raw"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    // A wrong key leaves nothing behind
+    let out = gui_decrypt(dir.path(), "notes.txt.agsiv", &["--key", KEY_B]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(!dir.path().join("notes.txt").exists());
+}
+
+fn encrypt_with_receipt(dir: &Path) -> Output {
+    cli()
+        .current_dir(dir)
+        .env("SOURCE_DATE_EPOCH", "1700000000")
+        .args(["encrypt", "plain.txt", "cipher.bin", "--key", KEY])
+        .args(["--nonce", NONCE, "--aad", "context"])
+        .args([
+            "--receipt",
+            "cipher.bin.receipt.json",
+            "--receipt-plaintext-hash",
+        ])
+        .output()
+        .unwrap()
+}
+
+fn verify(dir: &Path, extra: &[&str]) -> Output {
+    cli()
+        .current_dir(dir)
+        .args([
+            "verify",
+            "cipher.bin",
+            "--receipt",
+            "cipher.bin.receipt.json",
+        ])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+fn edit_receipt(dir: &Path, edit: impl FnOnce(&mut serde_json::Value)) {
+    let path = dir.join("cipher.bin.receipt.json");
+    let mut receipt: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    edit(&mut receipt);
+    fs::write(&path, serde_json::to_vec(&receipt).unwrap()).unwrap();
+}
+
+#[test]
+fn test_receipt_matches_golden_file() {
+    let dir = setup();
+    let out = encrypt_with_receipt(dir.path());
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let written: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.path().join("cipher.bin.receipt.json")).unwrap())
+            .unwrap();
+    let mut golden: serde_json::Value =
+        serde_json::from_str(include_str!("golden/receipt-v1.json")).unwrap();
+    golden["tool"]["version"] = env!("CARGO_PKG_VERSION").into();
+    // The envelope records the producer, so its bytes change with the version
+    let envelope = aes_gcm_siv_impl::envelope::seal_with_nonce(
+        &hex::decode(KEY).unwrap(),
+        &hex::decode(NONCE).unwrap(),
+        b"Secret message",
+        b"context",
+    )
+    .unwrap();
+    golden["output"]["sha256"] = hex::encode(Sha256::digest(&envelope)).into();
+    golden["output"]["size"] = envelope.len().into();
+    assert_eq!(written, golden);
+
+    // Nothing secret ends up in the receipt
+    let text = fs::read_to_string(dir.path().join("cipher.bin.receipt.json")).unwrap();
+    assert!(!text.contains(KEY));
+    assert!(!text.contains("Secret message"));
+    assert!(!text.contains("\"context\""));
+}
+
+#[test]
+fn test_verify_receipt() {
+    let dir = setup();
+    assert!(encrypt_with_receipt(dir.path()).status.success());
+
+    let out = verify(dir.path(), &[]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("ciphertext only"));
+
+    let out = verify(dir.path(), &["--key", KEY, "--aad", "context"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(!String::from_utf8_lossy(&out.stdout).contains("ciphertext only"));
+}
+
+#[test]
+fn test_verify_receipt_with_keyring_dir() {
+    let dir = setup();
+    assert!(encrypt_with_receipt(dir.path()).status.success());
+    let keys = keyring_dir(dir.path());
+
+    // The key is picked by the receipt's fingerprint, not by file name
+    let out = verify(
+        dir.path(),
+        &["--keyring-dir", keys.to_str().unwrap(), "--aad", "context"],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains(&fingerprint(KEY)) && stdout.contains("2024q1.key"));
+    assert!(!stdout.contains("ciphertext only"));
+
+    // The key still has to match the rest of the receipt
+    let out = verify(dir.path(), &["--keyring-dir", keys.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(3));
+
+    fs::remove_file(keys.join("2024q1.key")).unwrap();
+    let out = verify(dir.path(), &["--keyring-dir", keys.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains(&fingerprint(KEY)), "{}", stderr);
+    assert!(stderr.contains(&fingerprint(KEY_B)) && stderr.contains(&fingerprint(KEY_C)));
+}
+
+/// Damage done to the encrypted file or its receipt before verifying
+type Tamper = fn(&Path);
+
+#[test]
+fn test_verify_receipt_reports_each_mismatch() {
+    let full = ["--key", KEY, "--aad", "context"];
+    let cases: [(&str, &[&str], Tamper); 6] = [
+        ("output.sha256", &[], |dir| {
+            let mut ciphertext = fs::read(dir.join("cipher.bin")).unwrap();
+            ciphertext[0] ^= 1;
+            fs::write(dir.join("cipher.bin"), ciphertext).unwrap();
+        }),
+        (
+            "key_fingerprint",
+            &["--key", KEY_B, "--aad", "context"],
+            |_| {},
+        ),
+        ("aad_sha256", &["--key", KEY, "--aad", "other"], |_| {}),
+        ("algorithm", &full, |dir| {
+            edit_receipt(dir, |r| r["algorithm"] = "aes128-gcm-siv".into())
+        }),
+        // The envelope records the nonce, so no key is needed to check it
+        ("nonce", &[], |dir| {
+            edit_receipt(dir, |r| r["nonce"] = "000000000000000000000000".into())
+        }),
+        ("input.sha256", &full, |dir| {
+            edit_receipt(dir, |r| r["input"]["sha256"] = "00".repeat(32).into())
+        }),
+    ];
+
+    for (field, args, tamper) in cases {
+        let dir = setup();
+        assert!(encrypt_with_receipt(dir.path()).status.success());
+        tamper(dir.path());
+
+        let out = verify(dir.path(), args);
+        assert_eq!(out.status.code(), Some(3), "{}", field);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains(&format!("  {}", field)),
+            "{}: {}",
+            field,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn test_verify_refuses_newer_receipt_version() {
+    let dir = setup();
+    assert!(encrypt_with_receipt(dir.path()).status.success());
+    edit_receipt(dir.path(), |r| r["receipt_version"] = 2.into());
+
+    let out = verify(dir.path(), &[]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("receipt version 2 is newer"));
+}
+
+#[test]
+fn test_stream_roundtrip_and_no_partial_output() {
+    let dir = setup();
+    // Several segments, not a multiple of the segment size
+    let plaintext: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(dir.path().join("plain.txt"), &plaintext).unwrap();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+    let out = run(&[
+        "encrypt",
+        &path("plain.txt"),
+        &path("cipher.bin"),
+        "--key",
+        KEY,
+        "--aad",
+        "context",
+        "--stream",
+    ]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(!String::from_utf8_lossy(&out.stdout).contains("nonce"));
+
+    let decrypt = |extra: &[&str]| {
+        let mut args = vec!["decrypt", "--stream", "--key", KEY, "--aad", "context"];
+        args.extend_from_slice(extra);
+        run(&args)
+    };
+    let out = decrypt(&[&path("cipher.bin"), &path("decrypted.txt")]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        fs::read(dir.path().join("decrypted.txt")).unwrap(),
+        plaintext
+    );
+
+    // Truncated input fails without leaving any plaintext behind
+    let ciphertext = fs::read(dir.path().join("cipher.bin")).unwrap();
+    fs::write(
+        dir.path().join("cut.bin"),
+        &ciphertext[..ciphertext.len() / 2],
+    )
+    .unwrap();
+    let out = decrypt(&[&path("cut.bin"), &path("cut.txt")]);
+    assert_eq!(out.status.code(), Some(3));
+    assert_eq!(
+        fs::read_dir(dir.path()).unwrap().count(),
+        4,
+        "only plain.txt, cipher.bin, decrypted.txt and cut.bin remain"
+    );
+
+    // A raw nonce makes no sense for streams
+    let out = decrypt(&["--nonce", NONCE, &path("cipher.bin"), &path("x.txt")]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn test_stream_input_is_detected() {
+    let dir = setup();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+    let out = run(&[
+        "encrypt",
+        &path("plain.txt"),
+        &path("cipher.bin"),
+        "--key",
+        KEY,
+        "--stream",
+    ]);
+    assert!(out.status.success());
+
+    // --stream is optional: the header says what the input is
+    let out = decrypt_file(dir.path(), &[]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("streamed"));
+    assert_eq!(
+        fs::read(dir.path().join("decrypted.txt")).unwrap(),
+        b"Secret message"
+    );
+
+    // Where streams are not supported, the error says what the input is
+    let keyring = dir.path().join("keyring.toml");
+    fs::write(&keyring, format!("[keys]\n2026q4 = \"{}\"\n", KEY)).unwrap();
+    let out = run(&[
+        "decrypt",
+        &path("cipher.bin"),
+        &path("out.txt"),
+        "--keyring",
+        keyring.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("input is a stream"), "{}", stderr);
+    assert!(!stderr.contains("--raw"));
+}
+
+#[test]
+fn test_raw_format_still_supported() {
+    let dir = setup();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+    let out = run(&[
+        "encrypt",
+        &path("plain.txt"),
+        &path("raw.bin"),
+        "--key",
+        KEY,
+        "--raw",
+    ]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let nonce = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Generated nonce: "))
+        .expect("raw output prints the generated nonce");
+    assert_eq!(fs::metadata(path("raw.bin")).unwrap().len(), 14 + 16);
+
+    let out = run(&[
+        "decrypt",
+        &path("raw.bin"),
+        &path("decrypted.txt"),
+        "--key",
+        KEY,
+        "--raw",
+        "--nonce",
+        nonce,
+    ]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        fs::read(dir.path().join("decrypted.txt")).unwrap(),
+        b"Secret message"
+    );
+
+    // Without --raw, bare ciphertext is refused with a pointer to it
+    let out = run(&["decrypt", &path("raw.bin"), &path("x.txt"), "--key", KEY]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--raw --nonce"));
+
+    // A nonce implies raw input, as in versions before envelopes
+    let out = run(&[
+        "decrypt",
+        &path("raw.bin"),
+        &path("baseline.txt"),
+        "--key",
+        KEY,
+        "--nonce",
+        nonce,
+    ]);
+    assert!(out.status.success());
+    assert_eq!(
+        fs::read(dir.path().join("baseline.txt")).unwrap(),
+        b"Secret message"
+    );
+}
+
+#[test]
+fn test_envelope_is_the_default() {
+    let dir = setup();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+    let out = run(&[
+        "encrypt",
+        &path("plain.txt"),
+        &path("cipher.bin"),
+        "--key",
+        KEY,
+    ]);
+    assert!(out.status.success());
+    assert!(!String::from_utf8_lossy(&out.stdout).contains("nonce"));
+    assert!(fs::read(path("cipher.bin"))
+        .unwrap()
+        .starts_with(b"AGSVENV"));
+
+    let out = run(&[
+        "decrypt",
+        &path("cipher.bin"),
+        &path("out.txt"),
+        "--key",
+        KEY,
+    ]);
+    assert!(out.status.success());
+    assert_eq!(fs::read(path("out.txt")).unwrap(), b"Secret message");
+
+    // A missing AAD is named as such
+    let out = run(&[
+        "encrypt",
+        &path("plain.txt"),
+        &path("cipher.bin"),
+        "--key",
+        KEY,
+        "--aad",
+        "context",
+    ]);
+    assert!(out.status.success());
+    let out = run(&[
+        "decrypt",
+        &path("cipher.bin"),
+        &path("out.txt"),
+        "--key",
+        KEY,
+    ]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("pass --aad"));
+}
+
+#[test]
+fn test_password_roundtrip() {
+    let dir = setup();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+    fs::write(path("password.txt"), "correct horse\n").unwrap();
+    fs::write(path("wrong.txt"), "battery staple\n").unwrap();
+
+    let out = run(&[
+        "encrypt",
+        &path("plain.txt"),
+        &path("cipher.bin"),
+        "--password-file",
+        &path("password.txt"),
+        "--kdf-memory",
+        "1",
+        "--kdf-iterations",
+        "1",
+    ]);
+    assert!(out.status.success());
+    assert!(fs::read(path("cipher.bin"))
+        .unwrap()
+        .starts_with(b"AGSVPWD"));
+
+    let decrypt = |password_file: &str| {
+        run(&[
+            "decrypt",
+            &path("cipher.bin"),
+            &path("out.txt"),
+            "--password-file",
+            password_file,
+        ])
+    };
+    assert!(decrypt(&path("password.txt")).status.success());
+    assert_eq!(fs::read(path("out.txt")).unwrap(), b"Secret message");
+    assert_eq!(decrypt(&path("wrong.txt")).status.code(), Some(3));
+
+    // A key instead of the password is pointed at --password
+    let out = run(&[
+        "decrypt",
+        &path("cipher.bin"),
+        &path("out.txt"),
+        "--key",
+        KEY,
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--password"));
+
+    // Passwords only come with envelopes
+    for extra in [&["--raw"][..], &["--stream"], &["--key", KEY]] {
+        let mut args = vec![
+            "encrypt",
+            &path("plain.txt"),
+            &path("other.bin"),
+            "--password-file",
+            &path("password.txt"),
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+        args.extend(extra.iter().map(|s| s.to_string()));
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        assert!(!run(&args).status.success(), "{:?}", extra);
+    }
+}
+
+#[test]
+fn test_gen_key() {
+    let out = run(&["gen-key", "--algorithm", "aes128-gcm-siv"]);
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout).trim().len(), 32);
+
+    let dir = tempfile::tempdir().unwrap();
+    let key_file = dir.path().join("new.key");
+    let key_path = key_file.to_str().unwrap();
+    assert!(run(&["gen-key", "--output", key_path]).status.success());
+    assert_eq!(fs::read_to_string(&key_file).unwrap().trim().len(), 64);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&key_file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // An existing file is never overwritten
+    let before = fs::read(&key_file).unwrap();
+    assert!(!run(&["gen-key", "--output", key_path]).status.success());
+    assert_eq!(fs::read(&key_file).unwrap(), before);
+
+    // The key works with --key-file
+    fs::write(dir.path().join("plain.txt"), b"data").unwrap();
+    let input = dir.path().join("plain.txt");
+    let output = dir.path().join("cipher.bin");
+    assert!(run(&[
+        "encrypt",
+        input.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--key-file",
+        key_path,
+    ])
+    .status
+    .success());
+}
+
+#[test]
+fn test_keyring_file() {
+    let dir = setup();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+    const NEW_KEY: &str = "0f0e0d0c0b0a090807060504030201000f0e0d0c0b0a09080706050403020100";
+
+    fs::write(
+        path("keyring.toml"),
+        format!("[keys]\n2026q3 = \"{}\"\n", KEY),
+    )
+    .unwrap();
+    let encrypt = |output: &str| {
+        run(&[
+            "encrypt",
+            &path("plain.txt"),
+            &path(output),
+            "--keyring",
+            &path("keyring.toml"),
+        ])
+    };
+    let decrypt = |input: &str| {
+        run(&[
+            "decrypt",
+            &path(input),
+            &path("out.txt"),
+            "--keyring",
+            &path("keyring.toml"),
+        ])
+    };
+    assert!(encrypt("old.bin").status.success());
+
+    // Rotate: encrypt with the new key, decrypt old files with the old one
+    fs::write(
+        path("keyring.toml"),
+        format!(
+            "primary = \"2026q4\"\n[keys]\n2026q3 = \"{}\"\n2026q4 = \"{}\"\n",
+            KEY, NEW_KEY
+        ),
+    )
+    .unwrap();
+    let out = encrypt("new.bin");
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("key 2026q4"));
+    for input in ["old.bin", "new.bin"] {
+        let out = decrypt(input);
+        assert!(out.status.success(), "{}", input);
+        assert_eq!(fs::read(path("out.txt")).unwrap(), b"Secret message");
+    }
+    assert!(String::from_utf8_lossy(&decrypt("old.bin").stdout).contains("Using key 2026q3"));
+
+    // A dropped key is reported by ID, distinct from a failed authentication
+    fs::write(
+        path("keyring.toml"),
+        format!("[keys]\n2026q4 = \"{}\"\n", NEW_KEY),
+    )
+    .unwrap();
+    let out = decrypt("old.bin");
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("`2026q3`") && stderr.contains("2026q4"),
+        "{}",
+        stderr
+    );
+
+    fs::write(
+        path("keyring.toml"),
+        format!("[keys]\n2026q3 = \"{}\"\n", NEW_KEY),
+    )
+    .unwrap();
+    assert_eq!(decrypt("old.bin").status.code(), Some(3));
+
+    // With several keys the primary has to be named
+    fs::write(
+        path("keyring.toml"),
+        format!("[keys]\na = \"{}\"\nb = \"{}\"\n", KEY, NEW_KEY),
+    )
+    .unwrap();
+    let out = encrypt("other.bin");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("primary"));
+}
@@ -0,0 +1,71 @@
+//! Compatibility tests for the original free-function API
+//!
+//! These call sites are written the way existing users write them. They must
+//! keep compiling and behaving the same until the next major version, whatever
+//! typed APIs are added alongside. With the `strict-deprecations` feature the
+//! free functions superseded by `Cipher` are `#[deprecated]`; calling them is
+//! this file's whole purpose, so the lint is allowed here and the call sites
+//! still compile cleanly:
+//!
+//! ```bash
+//! cargo test --features strict-deprecations --test compat
+//! ```
+
+#![forbid(unsafe_code)]
+#![allow(deprecated)]
+
+#[cfg(feature = "std")]
+use aes_gcm_siv_impl::generate_nonce;
+use aes_gcm_siv_impl::{decrypt, encrypt, CryptoError, CryptoResult, NONCE_LENGTH, TAG_LENGTH};
+
+type EncryptFn = fn(&[u8], &[u8], &[u8], &[u8]) -> CryptoResult<Vec<u8>>;
+type DecryptFn = fn(&[u8], &[u8], &[u8], &[u8]) -> CryptoResult<Vec<u8>>;
+
+#[test]
+fn test_signatures_are_unchanged() {
+    let _: EncryptFn = encrypt;
+    let _: DecryptFn = decrypt;
+    #[cfg(feature = "std")]
+    let _: fn() -> Vec<u8> = generate_nonce;
+    let _: usize = NONCE_LENGTH;
+    let _: usize = TAG_LENGTH;
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_old_style_round_trip() {
+    // Arrays, vectors and slices all coerce to the `&[u8]` parameters
+    let key = [7u8; 32];
+    let nonce = generate_nonce();
+    let plaintext = b"legacy".to_vec();
+    let aad: &[u8] = b"";
+
+    let ciphertext = encrypt(&key, &nonce, &plaintext, aad).unwrap();
+    assert_eq!(ciphertext.len(), plaintext.len() + TAG_LENGTH);
+    let decrypted = decrypt(&key, &nonce, &ciphertext, aad).unwrap();
+    assert_eq!(decrypted, plaintext);
+}
+
+#[test]
+fn test_old_style_error_matching() {
+    fn open(key: &[u8], nonce: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+        match decrypt(key, nonce, data, b"") {
+            Ok(plaintext) => Ok(plaintext),
+            Err(CryptoError::Auth) => Err("tampered".into()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    let key = [7u8; 16];
+    let nonce = [0u8; NONCE_LENGTH];
+    let ciphertext = encrypt(&key, &nonce, b"data", b"").unwrap();
+    assert_eq!(open(&key, &nonce, &ciphertext).unwrap(), b"data");
+    assert_eq!(
+        open(&[8u8; 16], &nonce, &ciphertext).unwrap_err(),
+        "tampered"
+    );
+    assert_eq!(
+        open(&key[..5], &nonce, &ciphertext).unwrap_err(),
+        "Invalid key size"
+    );
+}
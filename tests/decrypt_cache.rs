@@ -0,0 +1,141 @@
+//! Tests for the opt-in decryption cache
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // `cache` is part of the std API
+
+use aes_gcm_siv_impl::{key_fingerprint, Cipher, CryptoError, CryptoResult, DecryptCache};
+use hex_literal::hex;
+
+const KEY_A: [u8; 32] = hex!("0100000000000000000000000000000000000000000000000000000000000000");
+const KEY_B: [u8; 32] = hex!("0200000000000000000000000000000000000000000000000000000000000000");
+const NONCE: [u8; 12] = hex!("030000000000000000000000");
+
+fn encrypt(key: &[u8], nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> CryptoResult<Vec<u8>> {
+    Cipher::new(key)?.encrypt(nonce, plaintext, aad)
+}
+
+fn cache(key: &[u8], max_entries: usize, max_bytes: usize) -> DecryptCache {
+    DecryptCache::new(
+        Cipher::new(key).unwrap(),
+        key_fingerprint(key).unwrap(),
+        max_entries,
+        max_bytes,
+    )
+}
+
+#[test]
+fn test_hit_and_miss() {
+    let mut cache = cache(&KEY_A, 16, 4096);
+    let ciphertext = encrypt(&KEY_A, &NONCE, b"hot record", b"aad").unwrap();
+
+    assert_eq!(
+        cache.decrypt(&NONCE, &ciphertext, b"aad").unwrap(),
+        b"hot record"
+    );
+    assert_eq!(
+        cache.decrypt(&NONCE, &ciphertext, b"aad").unwrap(),
+        b"hot record"
+    );
+    assert_eq!(
+        cache.decrypt(&NONCE, &ciphertext, b"aad").unwrap(),
+        b"hot record"
+    );
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (2, 1));
+    assert!((stats.hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(cache.len(), 1);
+
+    // Different AAD is a different entry, and must still authenticate
+    assert_eq!(
+        cache.decrypt(&NONCE, &ciphertext, b"other"),
+        Err(CryptoError::Auth)
+    );
+    assert_eq!(cache.stats().misses, 2);
+
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(cache.cached_bytes(), 0);
+    cache.decrypt(&NONCE, &ciphertext, b"aad").unwrap();
+    assert_eq!(cache.stats().misses, 3);
+}
+
+#[test]
+fn test_eviction_under_byte_budget() {
+    let mut cache = cache(&KEY_A, 16, 250);
+    let records: Vec<Vec<u8>> = (0..3u8)
+        .map(|i| encrypt(&KEY_A, &NONCE, &[i; 100], b"").unwrap())
+        .collect();
+
+    cache.decrypt(&NONCE, &records[0], b"").unwrap();
+    cache.decrypt(&NONCE, &records[1], b"").unwrap();
+    // Touch record 0 so record 1 becomes the least recently used
+    cache.decrypt(&NONCE, &records[0], b"").unwrap();
+    cache.decrypt(&NONCE, &records[2], b"").unwrap();
+
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.cached_bytes(), 200);
+    assert_eq!(cache.stats().evictions, 1);
+
+    let hits = cache.stats().hits;
+    cache.decrypt(&NONCE, &records[0], b"").unwrap();
+    assert_eq!(
+        cache.stats().hits,
+        hits + 1,
+        "record 0 should still be cached"
+    );
+    cache.decrypt(&NONCE, &records[1], b"").unwrap();
+    assert_eq!(
+        cache.stats().hits,
+        hits + 1,
+        "record 1 should have been evicted"
+    );
+}
+
+#[test]
+fn test_entry_count_bound_and_oversized_plaintext() {
+    let mut cache = cache(&KEY_A, 2, 1000);
+    for i in 0..5u8 {
+        let ciphertext = encrypt(&KEY_A, &NONCE, &[i; 10], b"").unwrap();
+        cache.decrypt(&NONCE, &ciphertext, b"").unwrap();
+    }
+    assert_eq!(cache.len(), 2);
+
+    let big = encrypt(&KEY_A, &NONCE, &[0xff; 2000], b"").unwrap();
+    assert_eq!(cache.decrypt(&NONCE, &big, b"").unwrap(), vec![0xff; 2000]);
+    assert_eq!(cache.len(), 2, "oversized plaintext must not be cached");
+}
+
+#[test]
+fn test_cross_key_isolation() {
+    let mut cache_a = cache(&KEY_A, 16, 4096);
+    let mut cache_b = cache(&KEY_B, 16, 4096);
+    assert_ne!(cache_a.key_fingerprint(), cache_b.key_fingerprint());
+
+    let ciphertext = encrypt(&KEY_A, &NONCE, b"record", b"").unwrap();
+    cache_a.decrypt(&NONCE, &ciphertext, b"").unwrap();
+
+    // The identical (nonce, ciphertext, aad) under another key never hits
+    assert_eq!(
+        cache_b.decrypt(&NONCE, &ciphertext, b""),
+        Err(CryptoError::Auth)
+    );
+    assert_eq!(cache_b.stats().hits, 0);
+}
+
+#[test]
+fn test_auth_failures_are_not_cached() {
+    let mut cache = cache(&KEY_A, 16, 4096);
+    let mut ciphertext = encrypt(&KEY_A, &NONCE, b"record", b"").unwrap();
+    ciphertext[0] ^= 1;
+
+    for _ in 0..3 {
+        assert_eq!(
+            cache.decrypt(&NONCE, &ciphertext, b""),
+            Err(CryptoError::Auth)
+        );
+    }
+    assert!(cache.is_empty());
+    assert_eq!(cache.stats().hits, 0);
+    assert_eq!(cache.stats().misses, 3);
+}
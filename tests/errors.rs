@@ -0,0 +1,100 @@
+//! Tests for error classification
+
+#![forbid(unsafe_code)]
+
+#[cfg(feature = "std")]
+use aes_gcm_siv_impl::classify_io_error;
+use aes_gcm_siv_impl::{Cipher, CryptoError, ErrorClass};
+#[cfg(feature = "std")]
+use std::io;
+
+/// Every variant with its expected class
+///
+/// `CryptoError` is `#[non_exhaustive]`, so a new variant only fails to
+/// compile inside the crate (`classify`, `as_str`); add it here and to
+/// `ALL_ERRORS` too.
+fn expected_class(err: &CryptoError) -> ErrorClass {
+    match err {
+        CryptoError::Auth => ErrorClass::AuthFailure,
+        CryptoError::InvalidKeySize => ErrorClass::InvalidInput,
+        CryptoError::InvalidNonceSize => ErrorClass::InvalidInput,
+        _ => panic!("no expected class for {:?}", err),
+    }
+}
+
+const ALL_ERRORS: [CryptoError; 3] = [
+    CryptoError::Auth,
+    CryptoError::InvalidKeySize,
+    CryptoError::InvalidNonceSize,
+];
+
+#[test]
+fn test_every_variant_is_classified() {
+    for err in ALL_ERRORS {
+        let class = expected_class(&err);
+        assert_eq!(err.classify(), class, "{:?}", err);
+        assert_eq!(err.is_auth(), class == ErrorClass::AuthFailure);
+        assert_eq!(err.is_invalid_input(), class == ErrorClass::InvalidInput);
+        assert_eq!(err.is_transient(), class.is_transient());
+    }
+    assert!(!ErrorClass::AuthFailure.is_transient());
+    assert!(!ErrorClass::InvalidInput.is_transient());
+    assert!(ErrorClass::Io.is_transient());
+}
+
+#[test]
+fn test_library_errors_classify_without_matching() {
+    let key = [0u8; 32];
+    let nonce = [0u8; 12];
+    let cipher = Cipher::new(&key).unwrap();
+    let ciphertext = cipher.encrypt(&nonce, b"data", b"aad").unwrap();
+
+    assert!(cipher
+        .decrypt(&nonce, &ciphertext, b"other")
+        .unwrap_err()
+        .is_auth());
+    assert!(Cipher::new(&key[..5]).unwrap_err().is_invalid_input());
+    assert!(cipher
+        .decrypt(&nonce[..5], &ciphertext, b"aad")
+        .unwrap_err()
+        .is_invalid_input());
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_io_errors_keep_the_crypto_class() {
+    for err in ALL_ERRORS {
+        let class = err.classify();
+        let io_err: io::Error = err.into();
+        assert_eq!(classify_io_error(&io_err), class);
+    }
+
+    let by_kind = |kind| classify_io_error(&io::Error::from(kind));
+    assert_eq!(by_kind(io::ErrorKind::NotFound), ErrorClass::Io);
+    assert_eq!(by_kind(io::ErrorKind::PermissionDenied), ErrorClass::Io);
+    assert_eq!(
+        by_kind(io::ErrorKind::InvalidData),
+        ErrorClass::InvalidInput
+    );
+    assert_eq!(by_kind(io::ErrorKind::OutOfMemory), ErrorClass::Resource);
+}
+
+#[test]
+fn test_error_codes_are_unique_short_snake_case() {
+    let mut seen = std::collections::BTreeSet::new();
+    for err in ALL_ERRORS {
+        let code = err.as_str();
+        assert!(seen.insert(code), "duplicate code {}", code);
+        assert!(code.len() <= 24, "{} is longer than 24 bytes", code);
+        assert!(
+            !code.is_empty()
+                && !code.starts_with('_')
+                && !code.ends_with('_')
+                && !code.contains("__")
+                && code.bytes().all(|b| b.is_ascii_lowercase() || b == b'_'),
+            "{} is not snake_case",
+            code
+        );
+    }
+    assert_eq!(CryptoError::Auth.as_str(), "auth_failed");
+}
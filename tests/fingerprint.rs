@@ -0,0 +1,36 @@
+//! Tests for key fingerprints
+
+#![forbid(unsafe_code)]
+
+use aes_gcm_siv_impl::{key_fingerprint, CryptoError};
+use hex_literal::hex;
+
+#[test]
+fn test_fingerprint_is_stable() {
+    let key = hex!("0100000000000000000000000000000000000000000000000000000000000000");
+    let fingerprint = key_fingerprint(&key).unwrap();
+    assert_eq!(fingerprint.to_string(), "f2319f5d91bfbf0a");
+    assert_eq!(fingerprint.to_string().len(), 16);
+}
+
+#[test]
+fn test_fingerprint_distinguishes_keys() {
+    let key_a = hex!("0100000000000000000000000000000000000000000000000000000000000000");
+    let key_b = hex!("0200000000000000000000000000000000000000000000000000000000000000");
+    assert_ne!(
+        key_fingerprint(&key_a).unwrap(),
+        key_fingerprint(&key_b).unwrap()
+    );
+    assert_ne!(
+        key_fingerprint(&key_a).unwrap(),
+        key_fingerprint(&key_a[..16]).unwrap()
+    );
+}
+
+#[test]
+fn test_fingerprint_rejects_invalid_key_size() {
+    assert_eq!(
+        key_fingerprint(&[0u8; 12]),
+        Err(CryptoError::InvalidKeySize)
+    );
+}
@@ -0,0 +1,180 @@
+//! Tests that no output format can be mistaken for another
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // Streams and block files are part of the std API
+
+use aes_gcm_siv_impl::block_file::{is_block_file, BLOCK_SIZE};
+use aes_gcm_siv_impl::envelope::{inspect, is_envelope, seal_with_nonce};
+use aes_gcm_siv_impl::kdf::is_password_encrypted;
+use aes_gcm_siv_impl::stream::is_stream;
+use aes_gcm_siv_impl::{
+    decrypt_with_password, encrypt_with_password, BlockCipherFile, BlockFileError, Cipher,
+    DecryptingReader, EncryptingWriter, EnvelopeError, KdfError, KdfParams, KeySize, Keyring,
+    StreamError,
+};
+use std::io::Write;
+
+const KEY: [u8; 32] = [0x42; 32];
+const NONCE: [u8; 12] = [0x24; 12];
+
+/// Cheap parameters, so the tests stay fast in debug builds
+const PARAMS: KdfParams = KdfParams {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Envelope,
+    Password,
+    Stream,
+    BlockFile,
+}
+
+/// The formats whose magic `data` starts with
+fn detect(data: &[u8]) -> Vec<Format> {
+    [
+        (Format::Envelope, is_envelope(data)),
+        (Format::Password, is_password_encrypted(data)),
+        (Format::Stream, is_stream(data)),
+        (Format::BlockFile, is_block_file(data)),
+    ]
+    .into_iter()
+    .filter_map(|(format, detected)| detected.then_some(format))
+    .collect()
+}
+
+/// Pseudo-random bytes (SplitMix64), seeded so failures reproduce
+fn payload(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            (z ^ (z >> 31)) as u8
+        })
+        .collect()
+}
+
+/// The output of every producer of a headered format for `plaintext`
+fn produce(plaintext: &[u8], aad: &[u8]) -> Vec<(Format, Vec<u8>)> {
+    let mut keyring = Keyring::new();
+    keyring.add("2026q4", &KEY).unwrap();
+
+    let mut writer = EncryptingWriter::new(Vec::new(), &KEY, aad).unwrap();
+    writer.write_all(plaintext).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blocks.bin");
+    let mut block = plaintext.to_vec();
+    block.resize(BLOCK_SIZE, 0);
+    BlockCipherFile::create(&path, &KEY, 2)
+        .unwrap()
+        .write_block(0, &block)
+        .unwrap();
+
+    vec![
+        (
+            Format::Envelope,
+            seal_with_nonce(&KEY, &NONCE, plaintext, aad).unwrap(),
+        ),
+        (Format::Envelope, keyring.encrypt(plaintext, aad).unwrap()),
+        (
+            Format::Password,
+            encrypt_with_password(b"hunter2", plaintext, aad, &PARAMS, KeySize::Aes128).unwrap(),
+        ),
+        (Format::Stream, writer.finish().unwrap()),
+        (Format::BlockFile, std::fs::read(&path).unwrap()),
+    ]
+}
+
+#[test]
+fn test_every_producer_is_detected_as_its_own_format_only() {
+    for seed in 0..32 {
+        let plaintext = payload(seed, (seed as usize * 37) % 300);
+        let aad = payload(seed + 1000, seed as usize % 3);
+        for (format, data) in produce(&plaintext, &aad) {
+            assert_eq!(detect(&data), [format], "seed {}", seed);
+        }
+
+        // Raw ciphertext has no header: it must not look like any format
+        // (for random data the chance of starting with a magic is 2^-56)
+        let raw = Cipher::new(&KEY)
+            .unwrap()
+            .encrypt(&NONCE, &plaintext, &aad)
+            .unwrap();
+        assert_eq!(detect(&raw), [], "seed {}", seed);
+        assert_eq!(detect(&payload(seed, 32)), [], "seed {}", seed);
+    }
+}
+
+#[test]
+fn test_magic_is_checked_in_full() {
+    for (format, data) in produce(b"data", b"") {
+        let magic_length = match format {
+            Format::Envelope | Format::Password => 7,
+            Format::Stream | Format::BlockFile => 8,
+        };
+
+        // A cut-down magic is recognised by nothing (and since every output
+        // above is detected as one format only, no magic is a prefix of
+        // another)
+        for cut in 0..magic_length {
+            assert_eq!(detect(&data[..cut]), [], "{:?} cut at {}", format, cut);
+        }
+
+        // Changing any single byte of the magic makes the parsers refuse
+        // the data as not theirs
+        for offset in 0..magic_length {
+            let mut changed = data.clone();
+            changed[offset] ^= 0x20;
+            assert_eq!(detect(&changed), [], "{:?} offset {}", format, offset);
+            match format {
+                Format::Envelope => {
+                    assert_eq!(inspect(&changed), Err(EnvelopeError::NotAnEnvelope))
+                }
+                Format::Password => assert_eq!(
+                    decrypt_with_password(b"hunter2", &changed, b""),
+                    Err(KdfError::NotPasswordEncrypted)
+                ),
+                Format::Stream => assert!(matches!(
+                    DecryptingReader::new(&changed[..], &KEY, b""),
+                    Err(StreamError::NotAStream)
+                )),
+                Format::BlockFile => {
+                    let dir = tempfile::tempdir().unwrap();
+                    let path = dir.path().join("blocks.bin");
+                    std::fs::write(&path, &changed).unwrap();
+                    assert!(matches!(
+                        BlockCipherFile::open(&path, &KEY),
+                        Err(BlockFileError::NotABlockFile)
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_formats_do_not_parse_as_each_other() {
+    for (format, data) in produce(b"data", b"") {
+        if format != Format::Envelope {
+            assert_eq!(inspect(&data), Err(EnvelopeError::NotAnEnvelope));
+        }
+        if format != Format::Password {
+            assert_eq!(
+                decrypt_with_password(b"hunter2", &data, b""),
+                Err(KdfError::NotPasswordEncrypted)
+            );
+        }
+        if format != Format::Stream {
+            assert!(matches!(
+                DecryptingReader::new(&data[..], &KEY, b""),
+                Err(StreamError::NotAStream)
+            ));
+        }
+    }
+}
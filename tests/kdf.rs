@@ -0,0 +1,156 @@
+//! Tests for password-based encryption
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // Sealing picks a random salt
+
+use aes_gcm_siv_impl::envelope::inspect;
+use aes_gcm_siv_impl::kdf::{derive_key, SALT_LENGTH};
+use aes_gcm_siv_impl::{
+    classify_io_error, decrypt_with_password, encrypt_with_password, CryptoError, EnvelopeError,
+    ErrorClass, KdfError, KdfParams, KeySize,
+};
+
+/// Cheap parameters, so the tests stay fast in debug builds
+const PARAMS: KdfParams = KdfParams {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+};
+
+/// Offset of the first parameter (memory) in the header
+const PARAMS_OFFSET: usize = 8;
+
+/// Length of the header in front of the envelope
+const PASSWORD_HEADER_LENGTH: usize = PARAMS_OFFSET + 12 + SALT_LENGTH;
+
+fn seal(password: &[u8], plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+    encrypt_with_password(password, plaintext, aad, &PARAMS, KeySize::Aes256).unwrap()
+}
+
+#[test]
+fn test_roundtrip() {
+    for key_size in [KeySize::Aes128, KeySize::Aes256] {
+        for aad in [&b""[..], b"context"] {
+            let data = encrypt_with_password(b"hunter2", b"Secret message", aad, &PARAMS, key_size)
+                .unwrap();
+            assert_eq!(
+                decrypt_with_password(b"hunter2", &data, aad).unwrap(),
+                b"Secret message"
+            );
+        }
+    }
+
+    // A fresh salt each time
+    assert_ne!(seal(b"pw", b"same", b""), seal(b"pw", b"same", b""));
+}
+
+#[test]
+fn test_wrong_password_or_aad_fails_authentication() {
+    let data = seal(b"hunter2", b"Secret message", b"context");
+
+    let err = decrypt_with_password(b"hunter3", &data, b"context").unwrap_err();
+    assert_eq!(
+        err,
+        KdfError::Envelope(EnvelopeError::Crypto(CryptoError::Auth))
+    );
+    assert_eq!(err.classify(), ErrorClass::AuthFailure);
+
+    // AAD lengths are the caller's, without the password header
+    assert_eq!(
+        decrypt_with_password(b"hunter2", &data, b"other"),
+        Err(KdfError::Envelope(EnvelopeError::AadLengthMismatch {
+            envelope: 7,
+            aad: 5
+        }))
+    );
+    assert!(decrypt_with_password(b"hunter2", &data, b"CONTEXT").is_err());
+
+    // The header records that the caller's AAD was not empty
+    assert_eq!(
+        decrypt_with_password(b"hunter2", &data, b""),
+        Err(KdfError::Envelope(EnvelopeError::AadRequired))
+    );
+    let without = seal(b"hunter2", b"Secret message", b"");
+    assert!(
+        !inspect(&without[PASSWORD_HEADER_LENGTH..])
+            .unwrap()
+            .aad_required
+    );
+
+    // The parameters are authenticated: lowering them fails
+    let mut tampered = data.clone();
+    tampered[PARAMS_OFFSET] ^= 1;
+    assert_eq!(
+        decrypt_with_password(b"hunter2", &tampered, b"context")
+            .unwrap_err()
+            .classify(),
+        ErrorClass::AuthFailure
+    );
+}
+
+#[test]
+fn test_derive_key() {
+    let salt = [0x5a; SALT_LENGTH];
+    let key = derive_key(b"hunter2", &salt, &PARAMS, KeySize::Aes128).unwrap();
+    assert_eq!(key.len(), 16);
+    assert_eq!(
+        key,
+        derive_key(b"hunter2", &salt, &PARAMS, KeySize::Aes128).unwrap()
+    );
+    assert_ne!(
+        key,
+        derive_key(b"hunter2", &[0xa5; SALT_LENGTH], &PARAMS, KeySize::Aes128).unwrap()
+    );
+    assert_ne!(
+        key,
+        derive_key(b"hunter3", &salt, &PARAMS, KeySize::Aes128).unwrap()
+    );
+
+    assert_eq!(
+        derive_key(b"", &salt, &PARAMS, KeySize::Aes128),
+        Err(KdfError::EmptyPassword)
+    );
+    assert!(matches!(
+        derive_key(b"pw", &salt[..4], &PARAMS, KeySize::Aes128),
+        Err(KdfError::InvalidParams(_))
+    ));
+    let too_little_memory = KdfParams {
+        memory_kib: 1,
+        ..PARAMS
+    };
+    assert!(matches!(
+        derive_key(b"pw", &salt, &too_little_memory, KeySize::Aes128),
+        Err(KdfError::InvalidParams(_))
+    ));
+}
+
+#[test]
+fn test_bad_headers() {
+    let data = seal(b"pw", b"data", b"");
+
+    assert_eq!(
+        decrypt_with_password(b"pw", b"not password-encrypted", b""),
+        Err(KdfError::NotPasswordEncrypted)
+    );
+    assert_eq!(
+        decrypt_with_password(b"pw", &data[..20], b""),
+        Err(KdfError::Malformed)
+    );
+
+    let mut newer = data.clone();
+    newer[7] = 2;
+    assert_eq!(
+        decrypt_with_password(b"pw", &newer, b""),
+        Err(KdfError::UnsupportedVersion(2))
+    );
+
+    // Hostile parameters are refused before any memory is allocated
+    let mut hostile = data.clone();
+    hostile[PARAMS_OFFSET..PARAMS_OFFSET + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    let err = decrypt_with_password(b"pw", &hostile, b"").unwrap_err();
+    assert!(matches!(err, KdfError::InvalidParams(_)));
+    assert_eq!(
+        classify_io_error(&std::io::Error::from(err)),
+        ErrorClass::InvalidInput
+    );
+}
@@ -0,0 +1,81 @@
+//! Tests for the two-party key confirmation helper
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // Challenges are random
+
+use aes_gcm_siv_impl::confirm::{constant_time_eq, Challenge, Response, CHALLENGE_LENGTH};
+use aes_gcm_siv_impl::{key_confirmation_challenge, key_confirmation_respond, Cipher, CryptoError};
+use hex_literal::hex;
+
+const KEY_A: [u8; 32] = hex!("0100000000000000000000000000000000000000000000000000000000000000");
+const KEY_B: [u8; 32] = hex!("0200000000000000000000000000000000000000000000000000000000000000");
+
+#[test]
+fn test_matching_keys_confirm() {
+    let (challenge, expected) = key_confirmation_challenge(&KEY_A).unwrap();
+
+    // Round trip through the wire encoding, as a real peer would
+    let received = Challenge::from_bytes(challenge.to_bytes());
+    let response = key_confirmation_respond(&KEY_A, &received).unwrap();
+    let response = Response::from_bytes(response.to_bytes());
+
+    assert!(expected.verify(&response));
+}
+
+#[test]
+fn test_mismatched_keys_do_not_confirm() {
+    let (challenge, expected) = key_confirmation_challenge(&KEY_A).unwrap();
+    let response = key_confirmation_respond(&KEY_B, &challenge).unwrap();
+    assert!(!expected.verify(&response));
+
+    // A 128-bit key sharing a prefix with the 256-bit key is a different key
+    let response = key_confirmation_respond(&KEY_A[..16], &challenge).unwrap();
+    assert!(!expected.verify(&response));
+}
+
+#[test]
+fn test_responses_are_not_replayable() {
+    let (first, _) = key_confirmation_challenge(&KEY_A).unwrap();
+    let (second, expected) = key_confirmation_challenge(&KEY_A).unwrap();
+    assert_ne!(first, second);
+
+    let old_response = key_confirmation_respond(&KEY_A, &first).unwrap();
+    assert!(!expected.verify(&old_response));
+}
+
+#[test]
+fn test_responder_never_uses_the_data_key() {
+    // Whoever sends the challenge picks its nonce and value
+    let challenge = Challenge::from_bytes([0x33; CHALLENGE_LENGTH]);
+    let response = key_confirmation_respond(&KEY_A, &challenge).unwrap();
+    let response = response.to_bytes();
+    let data_cipher = Cipher::new(&KEY_A).unwrap();
+
+    for aad in [&b"aes-gcm-siv-impl/key-confirmation/v2/response"[..], b""] {
+        assert_eq!(
+            data_cipher.decrypt(&[0x33; 12], &response, aad),
+            Err(CryptoError::Auth)
+        );
+    }
+}
+
+#[test]
+fn test_invalid_key_size() {
+    assert_eq!(
+        key_confirmation_challenge(&KEY_A[..12]).unwrap_err(),
+        CryptoError::InvalidKeySize
+    );
+    let (challenge, _) = key_confirmation_challenge(&KEY_A).unwrap();
+    assert_eq!(
+        key_confirmation_respond(&KEY_A[..12], &challenge).unwrap_err(),
+        CryptoError::InvalidKeySize
+    );
+}
+
+#[test]
+fn test_constant_time_eq() {
+    assert!(constant_time_eq(b"abc", b"abc"));
+    assert!(!constant_time_eq(b"abc", b"abd"));
+    assert!(!constant_time_eq(b"abc", b"ab"));
+    assert!(constant_time_eq(b"", b""));
+}
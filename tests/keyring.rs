@@ -0,0 +1,147 @@
+//! Tests for keyrings and key IDs in envelopes
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // `Keyring::encrypt` picks a random nonce
+
+use aes_gcm_siv_impl::envelope::{inspect, PRODUCER};
+use aes_gcm_siv_impl::keyring::MAX_KEY_ID_LENGTH;
+use aes_gcm_siv_impl::{
+    classify_io_error, open, seal, CryptoError, EnvelopeError, ErrorClass, Keyring, KeyringError,
+};
+
+const OLD_KEY: [u8; 32] = [0x11; 32];
+const NEW_KEY: [u8; 16] = [0x22; 16];
+
+fn rotated() -> Keyring {
+    let mut keyring = Keyring::new();
+    keyring.add("2026q3", &OLD_KEY).unwrap();
+    keyring.add("2026q4", &NEW_KEY).unwrap();
+    keyring
+}
+
+#[test]
+fn test_decrypt_selects_the_recorded_key() {
+    let mut keyring = Keyring::new();
+    keyring.add("2026q3", &OLD_KEY).unwrap();
+    let old = keyring.encrypt(b"old data", b"aad").unwrap();
+    assert_eq!(inspect(&old).unwrap().key_id.as_deref(), Some("2026q3"));
+
+    // The newest key becomes the primary
+    let keyring = rotated();
+    assert_eq!(keyring.primary_id(), Some("2026q4"));
+    let new = keyring.encrypt(b"new data", b"aad").unwrap();
+    assert_eq!(inspect(&new).unwrap().key_id.as_deref(), Some("2026q4"));
+
+    assert_eq!(keyring.decrypt(&old, b"aad").unwrap(), b"old data");
+    assert_eq!(keyring.decrypt(&new, b"aad").unwrap(), b"new data");
+
+    // The key ID is just a header field: the key alone still opens it
+    assert_eq!(open(&OLD_KEY, &old, b"aad").unwrap(), b"old data");
+}
+
+#[test]
+fn test_unknown_key_id_is_not_an_authentication_failure() {
+    let keyring = rotated();
+    let mut other = Keyring::new();
+    other.add("2027q1", &OLD_KEY).unwrap();
+    let envelope = other.encrypt(b"data", b"").unwrap();
+
+    let err = keyring.decrypt(&envelope, b"").unwrap_err();
+    assert_eq!(err, KeyringError::UnknownKeyId("2027q1".to_string()));
+    assert_eq!(err.classify(), ErrorClass::InvalidInput);
+
+    // A known ID whose key does not authenticate the envelope
+    let mut tampered = keyring.encrypt(b"data", b"").unwrap();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    let err = keyring.decrypt(&tampered, b"").unwrap_err();
+    assert_eq!(
+        err,
+        KeyringError::Envelope(EnvelopeError::Crypto(CryptoError::Auth))
+    );
+    assert_eq!(
+        classify_io_error(&std::io::Error::from(err)),
+        ErrorClass::AuthFailure
+    );
+
+    // Envelopes from `seal` record no ID
+    let untagged = seal(&OLD_KEY, b"data", b"").unwrap();
+    assert_eq!(inspect(&untagged).unwrap().key_id, None);
+    assert_eq!(
+        keyring.decrypt(&untagged, b""),
+        Err(KeyringError::MissingKeyId)
+    );
+}
+
+#[test]
+fn test_key_ids_and_primary() {
+    let mut keyring = Keyring::new();
+    assert_eq!(keyring.encrypt(b"", b""), Err(KeyringError::Empty));
+
+    let long = "k".repeat(MAX_KEY_ID_LENGTH + 1);
+    for id in ["", "has space", "ümlaut", &long] {
+        assert_eq!(
+            keyring.add(id, &OLD_KEY),
+            Err(KeyringError::InvalidKeyId(id.to_string())),
+            "{:?}",
+            id
+        );
+    }
+    assert!(keyring.add(&long[1..], &OLD_KEY).is_ok());
+    assert_eq!(
+        keyring.add("short", &OLD_KEY[..5]),
+        Err(KeyringError::Crypto(CryptoError::InvalidKeySize))
+    );
+
+    let mut keyring = rotated();
+    assert_eq!(
+        keyring.add("2026q3", &NEW_KEY),
+        Err(KeyringError::DuplicateKeyId("2026q3".to_string()))
+    );
+    assert_eq!(
+        keyring.set_primary("nope"),
+        Err(KeyringError::UnknownKeyId("nope".to_string()))
+    );
+    keyring.set_primary("2026q3").unwrap();
+    let envelope = keyring.encrypt(b"data", b"").unwrap();
+    assert_eq!(
+        inspect(&envelope).unwrap().key_id.as_deref(),
+        Some("2026q3")
+    );
+    assert_eq!(keyring.ids().collect::<Vec<_>>(), ["2026q3", "2026q4"]);
+
+    // No key material in the debug output
+    assert_eq!(
+        format!("{:?}", keyring),
+        r#"Keyring { ids: ["2026q3", "2026q4"], primary: Some("2026q3"), .. }"#
+    );
+}
+
+#[test]
+fn test_key_id_layout() {
+    let keyring = rotated();
+    let envelope = keyring.encrypt(b"", b"aad").unwrap();
+    // After the versions and producer: 16-byte key, all three flags, AAD
+    // length, then the length-prefixed ID
+    let at = 10 + PRODUCER.len();
+    assert_eq!(&envelope[at..at + 2], b"\x10\x07");
+    assert_eq!(&envelope[at + 2..at + 10], &3u64.to_le_bytes());
+    assert_eq!(&envelope[at + 10..at + 17], b"\x062026q4");
+
+    // The ID is authenticated
+    let mut renamed = envelope.clone();
+    renamed[at + 16] = b'3';
+    assert_eq!(
+        open(&NEW_KEY, &renamed, b"aad"),
+        Err(EnvelopeError::Crypto(CryptoError::Auth))
+    );
+
+    // A cut-short or invalid ID is malformed
+    let mut bad = envelope.clone();
+    bad[at + 11] = b' ';
+    assert_eq!(inspect(&bad), Err(EnvelopeError::Malformed));
+    let mut bad = envelope.clone();
+    bad[at + 10] = 0;
+    assert_eq!(inspect(&bad), Err(EnvelopeError::Malformed));
+    assert_eq!(inspect(&envelope[..at + 14]), Err(EnvelopeError::Malformed));
+}
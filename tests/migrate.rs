@@ -0,0 +1,160 @@
+//! Tests for migrating plaintext values to envelopes
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // `migrate` is part of the std API
+
+use aes_gcm_siv_impl::migrate::{
+    migrate_batch, migrate_value, MigrationError, MigrationOptions, MigrationProgress,
+    MigrationStep,
+};
+use aes_gcm_siv_impl::{seal, Cipher, CryptoError, EnvelopeError, ErrorClass, SealedBlob};
+
+const KEY: [u8; 32] = [0x42; 32];
+
+const FUTURE_ENVELOPE: &[u8] = include_bytes!("golden/envelope-v3.bin");
+
+fn cipher() -> Cipher {
+    Cipher::new(&KEY).unwrap()
+}
+
+/// The verified envelope `migrate_value` seals `value` into
+fn migrated(value: &[u8], aad: &[u8]) -> SealedBlob {
+    match migrate_value(&cipher(), value, aad).unwrap() {
+        MigrationStep::Migrate(replacement) => {
+            replacement.verify().unwrap();
+            replacement.into_verified().unwrap()
+        }
+        MigrationStep::AlreadyMigrated => panic!("{:?} taken for an envelope", value),
+    }
+}
+
+#[test]
+fn test_plaintext_migrates() {
+    for value in [&b"alice@example.com"[..], b"", &[0u8; 1000]] {
+        let sealed = migrated(value, b"users.email");
+        assert_eq!(sealed.open(&cipher(), b"users.email").unwrap(), value);
+    }
+}
+
+#[test]
+fn test_migrated_values_are_left_alone() {
+    let sealed = migrated(b"alice@example.com", b"users.email");
+    assert!(matches!(
+        migrate_value(&cipher(), sealed.as_ref(), b"users.email"),
+        Ok(MigrationStep::AlreadyMigrated)
+    ));
+
+    // Envelopes sealed some other way count too
+    let envelope = seal(&KEY, b"data", b"").unwrap();
+    assert!(matches!(
+        migrate_value(&cipher(), &envelope, b""),
+        Ok(MigrationStep::AlreadyMigrated)
+    ));
+}
+
+#[test]
+fn test_plaintext_resembling_an_envelope_migrates() {
+    // Starts with the magic, but does not parse as an envelope
+    for value in [
+        &b"AGSVENV"[..],
+        b"AGSVENV is the envelope magic",
+        b"AGSVENV\x01\x01\x05other\x20\x00",
+    ] {
+        let sealed = migrated(value, b"");
+        assert_eq!(sealed.open(&cipher(), b"").unwrap(), value);
+    }
+
+    // A well-formed envelope that is cut short of its tag is data too
+    let envelope = seal(&KEY, b"", b"").unwrap();
+    let cut = &envelope[..envelope.len() - 1];
+    assert_eq!(migrated(cut, b"").open(&cipher(), b"").unwrap(), cut);
+}
+
+#[test]
+fn test_envelopes_that_do_not_open_are_refused() {
+    let other_key = seal(&[0x43; 32], b"data", b"").unwrap();
+    let err = migrate_value(&cipher(), &other_key, b"").unwrap_err();
+    assert_eq!(
+        err,
+        MigrationError::ForeignEnvelope(EnvelopeError::Crypto(CryptoError::Auth))
+    );
+    assert_eq!(err.classify(), ErrorClass::AuthFailure);
+
+    let other_aad = seal(&KEY, b"data", b"users.name").unwrap();
+    assert!(matches!(
+        migrate_value(&cipher(), &other_aad, b"users.email"),
+        Err(MigrationError::ForeignEnvelope(
+            EnvelopeError::AadLengthMismatch { .. }
+        ))
+    ));
+
+    let err = migrate_value(&cipher(), FUTURE_ENVELOPE, b"").unwrap_err();
+    assert!(matches!(
+        err,
+        MigrationError::ForeignEnvelope(EnvelopeError::UnsupportedVersion { required: 3, .. })
+    ));
+    assert_eq!(err.classify(), ErrorClass::InvalidInput);
+}
+
+#[test]
+fn test_batch_migration() {
+    let cipher = cipher();
+    let foreign = seal(&[0x43; 32], b"data", b"").unwrap();
+    let mut rows = vec![
+        (1, b"alice@example.com".to_vec()),
+        (2, migrated(b"bob@example.com", b"").into_bytes()),
+        (3, foreign),
+        (4, b"carol@example.com".to_vec()),
+    ];
+
+    // A dry run reports what would happen, and hands out nothing to write
+    let mut calls = Vec::new();
+    let report = migrate_batch(
+        &cipher,
+        rows.clone(),
+        b"",
+        MigrationOptions::new().dry_run(true),
+        |progress| calls.push(*progress),
+    );
+    let expected = MigrationProgress {
+        processed: 4,
+        migrated: 2,
+        already_migrated: 1,
+        failed: 1,
+    };
+    assert_eq!(report.progress, expected);
+    assert_eq!(calls.len(), 4);
+    assert_eq!(calls[0].processed, 1);
+    assert_eq!(calls[3], expected);
+    assert!(report.replacements.is_empty());
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].0, 3);
+
+    let report = migrate_batch(&cipher, rows.clone(), b"", MigrationOptions::new(), |_| {});
+    assert_eq!(report.progress, expected);
+    let replaced: Vec<_> = report.replacements.iter().map(|(id, _)| *id).collect();
+    assert_eq!(replaced, [1, 4]);
+
+    // Write the replacements back; a rerun then finds nothing left to do
+    for (id, sealed) in report.replacements {
+        let row = rows.iter_mut().find(|(row_id, _)| *row_id == id).unwrap();
+        row.1 = sealed.into_bytes();
+    }
+    let report = migrate_batch(&cipher, rows.clone(), b"", MigrationOptions::new(), |_| {});
+    assert_eq!(
+        report.progress,
+        MigrationProgress {
+            processed: 4,
+            migrated: 0,
+            already_migrated: 3,
+            failed: 1,
+        }
+    );
+    assert_eq!(
+        SealedBlob::try_from(rows[0].1.clone())
+            .unwrap()
+            .open(&cipher, b"")
+            .unwrap(),
+        b"alice@example.com"
+    );
+}
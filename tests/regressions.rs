@@ -0,0 +1,173 @@
+//! Regression vault runner
+//!
+//! Feeds every input listed in `tests/regressions/manifest.txt` to the API it
+//! names and checks the outcome. See `tests/regressions/README.md`.
+
+#![forbid(unsafe_code)]
+
+use aes_gcm_siv_impl::{Cipher, CryptoResult};
+use hex_literal::hex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const REGRESSION_KEY_128: [u8; 16] = hex!("0f0e0d0c0b0a09080706050403020100");
+const REGRESSION_KEY_256: [u8; 32] =
+    hex!("1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100");
+const REGRESSION_NONCE: [u8; 12] = hex!("a0a1a2a3a4a5a6a7a8a9aaab");
+
+/// Plaintext sizes covered by the seed corpus
+const SEED_SIZES: &[usize] = &[0, 1, 15, 16, 17, 1024];
+
+/// Environment variable that makes the seed test rewrite the corpus files
+const REGENERATE_VAR: &str = "AES_GCM_SIV_REGENERATE_CORPUS";
+
+struct Entry {
+    name: String,
+    api: String,
+    expected: String,
+}
+
+fn vault_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions")
+}
+
+fn load_manifest() -> Vec<Entry> {
+    let manifest = fs::read_to_string(vault_dir().join("manifest.txt")).unwrap();
+    manifest
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(lineno, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(
+                fields.len(),
+                3,
+                "manifest line {}: expected `<name> <api> <expected>`",
+                lineno
+            );
+            Entry {
+                name: fields[0].to_string(),
+                api: fields[1].to_string(),
+                expected: fields[2].to_string(),
+            }
+        })
+        .collect()
+}
+
+fn outcome<T>(result: CryptoResult<T>) -> &'static str {
+    match result {
+        Ok(_) => "ok",
+        Err(e) => e.as_str(),
+    }
+}
+
+fn decrypt(key: &[u8], input: &[u8]) -> CryptoResult<Vec<u8>> {
+    Cipher::new(key)?.decrypt(&REGRESSION_NONCE, input, b"")
+}
+
+fn feed(api: &str, input: &[u8]) -> &'static str {
+    match api {
+        "decrypt-aes128" => outcome(decrypt(&REGRESSION_KEY_128, input)),
+        "decrypt-aes256" => outcome(decrypt(&REGRESSION_KEY_256, input)),
+        other => panic!("unknown regression API `{}`", other),
+    }
+}
+
+/// Deterministic seed inputs: `(name, api, expected, bytes)`
+fn seed_corpus() -> Vec<(String, &'static str, &'static str, Vec<u8>)> {
+    let mut seeds = Vec::new();
+    for (api, key) in [
+        ("decrypt-aes128", &REGRESSION_KEY_128[..]),
+        ("decrypt-aes256", &REGRESSION_KEY_256[..]),
+    ] {
+        let bits = &api["decrypt-aes".len()..];
+        for &size in SEED_SIZES {
+            let plaintext: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            let ciphertext = Cipher::new(key)
+                .unwrap()
+                .encrypt(&REGRESSION_NONCE, &plaintext, b"")
+                .unwrap();
+
+            let mut flipped = ciphertext.clone();
+            let last = flipped.len() - 1;
+            flipped[last] ^= 0x01;
+
+            let truncated = ciphertext[..ciphertext.len() - 1].to_vec();
+
+            seeds.push((
+                format!("seed-{}-valid-{}", bits, size),
+                api,
+                "ok",
+                ciphertext,
+            ));
+            seeds.push((
+                format!("seed-{}-tag-flip-{}", bits, size),
+                api,
+                "auth_failed",
+                flipped,
+            ));
+            seeds.push((
+                format!("seed-{}-truncated-{}", bits, size),
+                api,
+                "auth_failed",
+                truncated,
+            ));
+        }
+    }
+    seeds
+}
+
+#[test]
+fn test_regression_vault() {
+    let entries = load_manifest();
+    assert!(!entries.is_empty(), "regression manifest is empty");
+
+    let mut failures = Vec::new();
+    for entry in &entries {
+        let path = vault_dir()
+            .join("corpus")
+            .join(format!("{}.bin", entry.name));
+        let input =
+            fs::read(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e));
+        let actual = feed(&entry.api, &input);
+        if actual != entry.expected {
+            failures.push(format!(
+                "{} ({}): expected {}, got {}",
+                entry.name, entry.api, entry.expected, actual
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "regressions:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_seed_corpus_is_current() {
+    let regenerate = std::env::var_os(REGENERATE_VAR).is_some();
+    let entries = load_manifest();
+
+    for (name, api, expected, bytes) in seed_corpus() {
+        let path = vault_dir().join("corpus").join(format!("{}.bin", name));
+        if regenerate {
+            fs::write(&path, &bytes).unwrap();
+        } else {
+            let committed = fs::read(&path).unwrap_or_default();
+            assert!(
+                committed == bytes,
+                "{} is stale; rerun with {}=1",
+                path.display(),
+                REGENERATE_VAR
+            );
+        }
+
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.name == name && e.api == api && e.expected == expected),
+            "manifest is missing `{} {} {}`",
+            name,
+            api,
+            expected
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! SealedBlob validation, a SQLite column round trip and serde encodings
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // `SealedBlob::seal` picks a random nonce
+
+use aes_gcm_siv_impl::envelope::PRODUCER;
+use aes_gcm_siv_impl::{Cipher, CryptoError, EnvelopeError, KeySize, SealedBlob, TAG_LENGTH};
+use rusqlite::{params, Connection};
+
+const KEY: [u8; 32] = [0x42; 32];
+const NONCE: [u8; 12] = [0x24; 12];
+
+/// Header length of an envelope without AAD length or key ID
+const HEADER_LENGTH: usize = 7 + 3 + PRODUCER.len() + 2 + 12;
+
+fn cipher() -> Cipher {
+    Cipher::new(&KEY).unwrap()
+}
+
+/// A table with one encrypted column, stored and loaded through
+/// `AsRef<[u8]>` and `TryFrom<Vec<u8>>` only
+struct Users {
+    db: Connection,
+}
+
+impl Users {
+    fn new() -> Self {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email BLOB NOT NULL)",
+            [],
+        )
+        .unwrap();
+        Users { db }
+    }
+
+    fn insert(&self, id: i64, email: &SealedBlob) {
+        self.db
+            .execute(
+                "INSERT INTO users (id, email) VALUES (?1, ?2)",
+                params![id, email.as_ref()],
+            )
+            .unwrap();
+    }
+
+    fn load(&self, id: i64) -> Result<SealedBlob, EnvelopeError> {
+        let bytes: Vec<u8> = self
+            .db
+            .query_row("SELECT email FROM users WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        SealedBlob::try_from(bytes)
+    }
+
+    fn truncate(&self, id: i64, length: usize) {
+        self.db
+            .execute(
+                "UPDATE users SET email = substr(email, 1, ?2) WHERE id = ?1",
+                params![id, length as i64],
+            )
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_sqlite_column_round_trip() {
+    let cipher = cipher();
+    let users = Users::new();
+    for (id, email) in [(1, "alice@example.com"), (2, ""), (3, "bob@example.com")] {
+        let blob = SealedBlob::seal(&cipher, email.as_bytes(), b"users.email").unwrap();
+        users.insert(id, &blob);
+        assert_eq!(users.load(id).unwrap(), blob);
+    }
+    for (id, email) in [(1, "alice@example.com"), (2, ""), (3, "bob@example.com")] {
+        let loaded = users.load(id).unwrap();
+        assert_eq!(
+            loaded.open(&cipher, b"users.email").unwrap(),
+            email.as_bytes()
+        );
+    }
+}
+
+#[test]
+fn test_truncated_rows_are_caught() {
+    let cipher = cipher();
+    let users = Users::new();
+    let blob = SealedBlob::seal(&cipher, b"alice@example.com", b"users.email").unwrap();
+    let length = blob.as_ref().len();
+
+    // Cut inside the header or the tag: rejected when loading
+    for (id, cut) in (0..HEADER_LENGTH + TAG_LENGTH).enumerate() {
+        let id = id as i64;
+        users.insert(id, &blob);
+        users.truncate(id, cut);
+        let expected = if cut < 7 {
+            EnvelopeError::NotAnEnvelope
+        } else {
+            EnvelopeError::Malformed
+        };
+        assert_eq!(users.load(id), Err(expected), "cut at {}", cut);
+    }
+
+    // Cut inside the payload: still the shape of an envelope, so only
+    // authentication catches it
+    let id = length as i64;
+    users.insert(id, &blob);
+    users.truncate(id, length - 1);
+    let loaded = users.load(id).unwrap();
+    assert_eq!(
+        loaded.open(&cipher, b"users.email"),
+        Err(EnvelopeError::Crypto(CryptoError::Auth))
+    );
+}
+
+#[test]
+fn test_validation() {
+    let cipher = cipher();
+    let blob = SealedBlob::seal_with_nonce(&cipher, &NONCE, b"", b"").unwrap();
+    // An empty plaintext leaves exactly the tag after the header
+    assert_eq!(blob.as_ref().len(), HEADER_LENGTH + TAG_LENGTH);
+    assert_eq!(SealedBlob::try_from(blob.as_ref()).unwrap(), blob);
+
+    assert_eq!(
+        SealedBlob::try_from(b"plain text".to_vec()),
+        Err(EnvelopeError::NotAnEnvelope)
+    );
+    let raw = cipher.encrypt(&NONCE, b"data", b"").unwrap();
+    assert_eq!(SealedBlob::try_from(raw), Err(EnvelopeError::NotAnEnvelope));
+
+    let header = blob.header();
+    assert_eq!(header.key_size, KeySize::Aes256);
+    assert_eq!(header.nonce, NONCE);
+    assert_eq!(Vec::from(blob.clone()), blob.into_bytes());
+}
+
+#[test]
+fn test_open_checks_key_and_aad() {
+    let cipher = cipher();
+    let blob = SealedBlob::seal(&cipher, b"data", b"aad").unwrap();
+    assert_eq!(blob.open(&cipher, b""), Err(EnvelopeError::AadRequired));
+    assert_eq!(
+        blob.open(&Cipher::new(&[0x42; 16]).unwrap(), b"aad"),
+        Err(EnvelopeError::KeySizeMismatch {
+            envelope: KeySize::Aes256,
+            key: KeySize::Aes128,
+        })
+    );
+    assert_eq!(
+        blob.open(&Cipher::new(&[0x43; 32]).unwrap(), b"aad"),
+        Err(EnvelopeError::Crypto(CryptoError::Auth))
+    );
+
+    // Interchangeable with the free functions
+    assert_eq!(
+        aes_gcm_siv_impl::open(&KEY, blob.as_ref(), b"aad").unwrap(),
+        b"data"
+    );
+    let sealed = aes_gcm_siv_impl::seal(&KEY, b"data", b"aad").unwrap();
+    let blob = SealedBlob::try_from(sealed).unwrap();
+    assert_eq!(blob.open(&cipher, b"aad").unwrap(), b"data");
+}
+
+#[cfg(feature = "serde")]
+mod serde {
+    use super::*;
+    use serde_test::{assert_de_tokens_error, assert_tokens, Configure, Token};
+
+    fn blob() -> SealedBlob {
+        SealedBlob::seal_with_nonce(&cipher(), &NONCE, b"data", b"").unwrap()
+    }
+
+    #[test]
+    fn test_binary_formats_get_bytes() {
+        let blob = blob();
+        let bytes: &'static [u8] = blob.as_ref().to_vec().leak();
+        assert_tokens(&blob.compact(), &[Token::Bytes(bytes)]);
+    }
+
+    #[test]
+    fn test_human_readable_formats_get_base64() {
+        let blob = blob();
+        let json = serde_json::to_string(&blob).unwrap();
+        assert!(json.starts_with("\"QUdTVkVOV"), "{}", json);
+        assert_eq!(serde_json::from_str::<SealedBlob>(&json).unwrap(), blob);
+    }
+
+    #[test]
+    fn test_deserializing_validates() {
+        let blob = blob();
+        let truncated: &'static [u8] = blob.as_ref()[..HEADER_LENGTH].to_vec().leak();
+        assert_de_tokens_error::<serde_test::Compact<SealedBlob>>(
+            &[Token::Bytes(truncated)],
+            "Malformed envelope header",
+        );
+        assert_de_tokens_error::<serde_test::Readable<SealedBlob>>(
+            &[Token::Str("not base64!")],
+            "invalid value: string \"not base64!\", \
+             expected envelope bytes or a base64 string of them",
+        );
+        assert_de_tokens_error::<serde_test::Readable<SealedBlob>>(
+            &[Token::Str("cGxhaW4gdGV4dA==")],
+            "Not an envelope",
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Tests for segmented streaming encryption
+
+#![forbid(unsafe_code)]
+#![cfg(feature = "std")] // `stream` is part of the std API
+
+use aes_gcm_siv_impl::stream::{
+    is_stream, StreamDecryptor, StreamEncryptor, HEADER_LENGTH, MAX_SEGMENT_SIZE,
+};
+use aes_gcm_siv_impl::{
+    classify_io_error, DecryptingReader, DecryptorOptions, EncryptingWriter, ErrorClass,
+    StreamError, TAG_LENGTH,
+};
+use std::io::{Read, Write};
+
+const KEY: [u8; 32] = [0x42; 32];
+const SEGMENT_SIZE: usize = 64;
+
+fn seal(plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut writer =
+        EncryptingWriter::with_segment_size(Vec::new(), &KEY, aad, SEGMENT_SIZE).unwrap();
+    // Uneven write sizes, so segments straddle writes
+    for chunk in plaintext.chunks(23) {
+        writer.write_all(chunk).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn open(stream: &[u8], aad: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut reader = DecryptingReader::new(stream, &KEY, aad)?;
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+fn stream_error(err: &std::io::Error) -> &StreamError {
+    err.get_ref()
+        .and_then(|e| e.downcast_ref::<StreamError>())
+        .unwrap()
+}
+
+#[test]
+fn test_roundtrip_at_segment_boundaries() {
+    for len in [
+        0,
+        1,
+        SEGMENT_SIZE - 1,
+        SEGMENT_SIZE,
+        SEGMENT_SIZE + 1,
+        3 * SEGMENT_SIZE,
+        3 * SEGMENT_SIZE + 7,
+    ] {
+        let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let stream = seal(&plaintext, b"aad");
+
+        let segments = len.div_ceil(SEGMENT_SIZE).max(1);
+        assert_eq!(
+            stream.len(),
+            HEADER_LENGTH + len + segments * TAG_LENGTH,
+            "{}",
+            len
+        );
+        assert_eq!(open(&stream, b"aad").unwrap(), plaintext, "{}", len);
+    }
+}
+
+#[test]
+fn test_truncation_is_an_error() {
+    let plaintext = vec![0x5a; 3 * SEGMENT_SIZE];
+    let stream = seal(&plaintext, b"");
+    let segment = SEGMENT_SIZE + TAG_LENGTH;
+
+    // Cut at a segment boundary: every remaining segment authenticates, but
+    // none is marked as the last
+    let err = open(&stream[..HEADER_LENGTH + 2 * segment], b"").unwrap_err();
+    assert!(matches!(stream_error(&err), StreamError::Truncated));
+    assert_eq!(classify_io_error(&err), ErrorClass::AuthFailure);
+
+    // Cut inside a segment
+    let err = open(&stream[..stream.len() - 5], b"").unwrap_err();
+    assert!(matches!(stream_error(&err), StreamError::CorruptSegment(2)));
+
+    // Nothing after the header
+    let err = open(&stream[..HEADER_LENGTH], b"").unwrap_err();
+    assert!(matches!(stream_error(&err), StreamError::Truncated));
+}
+
+#[test]
+fn test_failed_segment_ends_the_stream() {
+    let plaintext: Vec<u8> = (0..2 * SEGMENT_SIZE).map(|i| i as u8).collect();
+    let stream = seal(&plaintext, b"");
+    let segment = SEGMENT_SIZE + TAG_LENGTH;
+
+    // A garbage segment injected between the first and the second
+    let mut injected = stream[..HEADER_LENGTH + segment].to_vec();
+    injected.extend_from_slice(&[0xee; SEGMENT_SIZE + TAG_LENGTH]);
+    injected.extend_from_slice(&stream[HEADER_LENGTH + segment..]);
+
+    let mut reader = DecryptingReader::new(&injected[..], &KEY, b"").unwrap();
+    let mut buf = vec![0u8; SEGMENT_SIZE];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, plaintext[..SEGMENT_SIZE]);
+
+    // Retrying does not move on to the genuine second segment
+    for _ in 0..3 {
+        let err = reader.read(&mut buf).unwrap_err();
+        assert!(matches!(stream_error(&err), StreamError::CorruptSegment(1)));
+    }
+    assert!(reader.read_to_end(&mut Vec::new()).is_err());
+}
+
+/// Reads from a slice, counting the bytes read
+struct CountingReader<'a> {
+    data: &'a [u8],
+    read: usize,
+}
+
+impl Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = (&self.data[self.read..]).read(buf)?;
+        self.read += len;
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_drain_on_failure_reads_the_whole_input() {
+    let plaintext: Vec<u8> = (0..8 * SEGMENT_SIZE).map(|i| i as u8).collect();
+    let mut tampered = seal(&plaintext, b"");
+    tampered[HEADER_LENGTH + SEGMENT_SIZE + TAG_LENGTH + 3] ^= 1;
+
+    for drain in [false, true] {
+        let mut input = CountingReader {
+            data: &tampered,
+            read: 0,
+        };
+        let options = DecryptorOptions::new().drain_on_failure(drain);
+        let mut reader = DecryptingReader::with_options(&mut input, &KEY, b"", options).unwrap();
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(stream_error(&err), StreamError::CorruptSegment(1)));
+        // The stream stays failed
+        let err = reader.read(&mut [0u8; 1]).unwrap_err();
+        assert!(matches!(stream_error(&err), StreamError::CorruptSegment(1)));
+        drop(reader);
+
+        if drain {
+            assert_eq!(input.read, tampered.len());
+        } else {
+            assert!(input.read < tampered.len() / 2, "{}", input.read);
+        }
+    }
+
+    // Draining changes nothing for an intact stream
+    let stream = seal(&plaintext, b"");
+    let options = DecryptorOptions::new().drain_on_failure(true);
+    let mut reader = DecryptingReader::with_options(&stream[..], &KEY, b"", options).unwrap();
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted).unwrap();
+    assert_eq!(decrypted, plaintext);
+}
+
+#[test]
+fn test_reordered_or_tampered_segments_fail() {
+    let plaintext: Vec<u8> = (0..3 * SEGMENT_SIZE).map(|i| i as u8).collect();
+    let stream = seal(&plaintext, b"");
+    let segment = SEGMENT_SIZE + TAG_LENGTH;
+    let (header, body) = stream.split_at(HEADER_LENGTH);
+
+    let mut swapped = header.to_vec();
+    swapped.extend_from_slice(&body[segment..2 * segment]);
+    swapped.extend_from_slice(&body[..segment]);
+    swapped.extend_from_slice(&body[2 * segment..]);
+    let err = open(&swapped, b"").unwrap_err();
+    assert!(matches!(stream_error(&err), StreamError::CorruptSegment(0)));
+
+    // Plaintext of segments before the damaged one is still returned
+    let mut tampered = stream.clone();
+    tampered[HEADER_LENGTH + segment + 3] ^= 1;
+    let mut reader = DecryptingReader::new(&tampered[..], &KEY, b"").unwrap();
+    let mut first = vec![0u8; SEGMENT_SIZE];
+    reader.read_exact(&mut first).unwrap();
+    assert_eq!(first, plaintext[..SEGMENT_SIZE]);
+    let err = reader.read(&mut first).unwrap_err();
+    assert!(matches!(stream_error(&err), StreamError::CorruptSegment(1)));
+
+    // The header and the AAD are authenticated by every segment
+    let mut tampered = stream.clone();
+    tampered[HEADER_LENGTH - 1] ^= 1;
+    assert!(open(&tampered, b"").is_err());
+    assert!(open(&stream, b"other").is_err());
+    assert!(DecryptingReader::new(&stream[..], &[0x24; 32], b"")
+        .and_then(|mut r| Ok(r.read_to_end(&mut Vec::new())?))
+        .is_err());
+}
+
+#[test]
+fn test_bad_headers() {
+    assert!(matches!(
+        DecryptingReader::new(&b"not a stream at all"[..], &KEY, b""),
+        Err(StreamError::NotAStream)
+    ));
+    assert!(matches!(
+        DecryptingReader::new(&b"AGSV"[..], &KEY, b""),
+        Err(StreamError::NotAStream)
+    ));
+    assert!(is_stream(&seal(b"data", b"")));
+    assert!(!is_stream(b"AGSV"));
+
+    // A hostile segment size is rejected before anything is allocated
+    let mut stream = seal(b"data", b"");
+    stream[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(
+        DecryptingReader::new(&stream[..], &KEY, b""),
+        Err(StreamError::InvalidSegmentSize(_))
+    ));
+
+    for size in [0, MAX_SEGMENT_SIZE + 1] {
+        assert!(matches!(
+            EncryptingWriter::with_segment_size(Vec::new(), &KEY, b"", size),
+            Err(StreamError::InvalidSegmentSize(_))
+        ));
+    }
+}
+
+#[test]
+fn test_segment_api() {
+    let prefix = [7u8; 7];
+    let mut encryptor = StreamEncryptor::new(&KEY, &prefix, b"aad").unwrap();
+    let first = encryptor.encrypt_next(b"first").unwrap();
+    let second = encryptor.encrypt_next(b"second").unwrap();
+    let last = encryptor.encrypt_last(b"last").unwrap();
+
+    let mut decryptor = StreamDecryptor::new(&KEY, &prefix, b"aad").unwrap();
+    assert_eq!(decryptor.decrypt_next(&first).unwrap(), b"first");
+    // A failed segment is not consumed
+    assert!(matches!(
+        decryptor.decrypt_next(&last),
+        Err(StreamError::CorruptSegment(1))
+    ));
+    assert_eq!(decryptor.decrypt_next(&second).unwrap(), b"second");
+    assert_eq!(decryptor.decrypt_last(&last).unwrap(), b"last");
+
+    let mut decryptor = StreamDecryptor::new(&KEY, &prefix, b"aad").unwrap();
+    assert_eq!(decryptor.decrypt_next(&first).unwrap(), b"first");
+    assert!(matches!(
+        decryptor.decrypt_last(&second),
+        Err(StreamError::Truncated)
+    ));
+
+    assert!(StreamEncryptor::new(&KEY[..5], &prefix, b"").is_err());
+}
@@ -0,0 +1,165 @@
+//! Peak-allocation tests for the streaming API
+//!
+//! A counting global allocator tracks the heap use of the test thread while
+//! a large generated file is encrypted and decrypted through the file
+//! streaming path, and the peak must stay within a small multiple of the
+//! segment size. An implementation that buffers the whole input fails.
+//!
+//! Unlike the other test files this one needs `unsafe`, for the
+//! `GlobalAlloc` impl, which only forwards to the system allocator.
+
+#![cfg(feature = "std")] // `stream` is part of the std API
+
+use aes_gcm_siv_impl::stream::DEFAULT_SEGMENT_SIZE;
+use aes_gcm_siv_impl::{DecryptingReader, DecryptorOptions, EncryptingWriter};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read};
+
+const KEY: [u8; 32] = [0x42; 32];
+
+/// Size of the generated input: far above the budget, yet quick to
+/// encrypt in a debug build
+const INPUT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Size of the input for the release-mode run in CI
+const LARGE_INPUT_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Peak allocation allowed for a streaming pass
+const BUDGET: usize = 4 * DEFAULT_SEGMENT_SIZE;
+
+/// Forwards to the system allocator, counting the current thread's bytes
+struct CountingAllocator;
+
+thread_local! {
+    static CURRENT: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_alloc(size: usize) {
+    // try_with: the thread-local may already be gone while a thread exits
+    let _ = CURRENT.try_with(|current| {
+        let now = current.get() + size;
+        current.set(now);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(now)));
+    });
+}
+
+fn record_dealloc(size: usize) {
+    // Memory allocated on another thread may be freed on this one
+    let _ = CURRENT.try_with(|current| current.set(current.get().saturating_sub(size)));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_alloc(layout.size());
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record_dealloc(layout.size());
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_dealloc(layout.size());
+        record_alloc(new_size);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run `f` and return its result together with the peak of the bytes it
+/// allocated on this thread on top of what was already allocated
+fn peak_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let start = CURRENT.with(Cell::get);
+    PEAK.with(|peak| peak.set(start));
+    let result = f();
+    (result, PEAK.with(Cell::get) - start)
+}
+
+/// Generates `remaining` bytes of plaintext without holding them anywhere
+struct Generated {
+    remaining: u64,
+}
+
+impl Read for Generated {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = (self.remaining as usize).wrapping_sub(i) as u8;
+        }
+        self.remaining -= len as u64;
+        Ok(len)
+    }
+}
+
+/// Encrypt and decrypt `size` generated bytes through files, checking the
+/// peak allocation of each pass
+fn check_round_trip(size: u64) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.bin");
+
+    let ((), encrypt_peak) = peak_allocation(|| {
+        let file = BufWriter::new(File::create(&path).unwrap());
+        let mut writer = EncryptingWriter::new(file, &KEY, b"aad").unwrap();
+        let mut input = Generated { remaining: size };
+        io::copy(&mut input, &mut writer).unwrap();
+        writer.finish().unwrap();
+    });
+    assert!(
+        encrypt_peak <= BUDGET,
+        "encrypting allocated {} bytes at peak",
+        encrypt_peak
+    );
+
+    for options in [
+        DecryptorOptions::new(),
+        DecryptorOptions::new().drain_on_failure(true),
+    ] {
+        let (decrypted, decrypt_peak) = peak_allocation(|| {
+            let file = BufReader::new(File::open(&path).unwrap());
+            let mut reader = DecryptingReader::with_options(file, &KEY, b"aad", options).unwrap();
+            io::copy(&mut reader, &mut io::sink()).unwrap()
+        });
+        assert_eq!(decrypted, size);
+        assert!(
+            decrypt_peak <= BUDGET,
+            "decrypting ({:?}) allocated {} bytes at peak",
+            options,
+            decrypt_peak
+        );
+    }
+}
+
+#[test]
+fn test_streaming_file_round_trip_stays_within_budget() {
+    check_round_trip(INPUT_SIZE);
+}
+
+/// Run in CI with `cargo test --release --test stream_memory -- --ignored`
+#[test]
+#[ignore = "slow in debug builds"]
+fn test_large_streaming_file_round_trip_stays_within_budget() {
+    check_round_trip(LARGE_INPUT_SIZE);
+}
+
+#[test]
+fn test_the_counter_catches_whole_file_buffering() {
+    // The same kind of pass, but reading everything into memory first
+    let (_, peak) = peak_allocation(|| {
+        let mut plaintext = Vec::new();
+        Generated {
+            remaining: 8 * BUDGET as u64,
+        }
+        .read_to_end(&mut plaintext)
+        .unwrap();
+        plaintext
+    });
+    assert!(peak > BUDGET, "{}", peak);
+}
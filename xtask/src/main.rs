@@ -0,0 +1,166 @@
+//! Repository maintenance tasks, run with `cargo xtask <task>`
+//!
+//! - `feature-matrix`: lint every supported combination of cargo features
+//!   (and the `no_std` targets) with clippy, then run the test suite for the
+//!   combinations users actually build. Feature-gated items referenced from
+//!   code that is not gated, and deprecation warnings that only appear with
+//!   `strict-deprecations`, fail here instead of in a downstream build.
+//!   Pass `--no-tests` to lint only.
+
+#![forbid(unsafe_code)]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+/// Package whose features are checked
+const PACKAGE: &str = "aes-gcm-siv-impl";
+
+/// One combination of cargo features, optionally for a cross target
+struct Combination {
+    /// Short name for the report
+    name: &'static str,
+    /// Feature selection arguments passed to cargo
+    features: &'static [&'static str],
+    /// Target triple; `None` for the host, where every target is checked
+    target: Option<&'static str>,
+    /// Whether to run the tests too, not just clippy
+    test: bool,
+}
+
+/// The curated combinations: the defaults, each feature on its own on top
+/// of the `no_std` core, everything at once, and the `no_std` targets
+const COMBINATIONS: &[Combination] = &[
+    Combination {
+        name: "default",
+        features: &[],
+        target: None,
+        test: true,
+    },
+    Combination {
+        name: "no-default-features",
+        features: &["--no-default-features"],
+        target: None,
+        test: true,
+    },
+    Combination {
+        name: "std",
+        features: &["--no-default-features", "--features", "std"],
+        target: None,
+        test: false,
+    },
+    Combination {
+        name: "strict-deprecations",
+        features: &["--no-default-features", "--features", "strict-deprecations"],
+        target: None,
+        test: false,
+    },
+    Combination {
+        name: "serde",
+        features: &["--no-default-features", "--features", "serde"],
+        target: None,
+        test: false,
+    },
+    Combination {
+        name: "all-features",
+        features: &["--all-features"],
+        target: None,
+        test: true,
+    },
+    Combination {
+        name: "thumbv7em-none-eabihf",
+        features: &["--no-default-features"],
+        target: Some("thumbv7em-none-eabihf"),
+        test: false,
+    },
+    Combination {
+        name: "wasm32-unknown-unknown",
+        features: &["--no-default-features"],
+        target: Some("wasm32-unknown-unknown"),
+        test: false,
+    },
+];
+
+/// Directory holding the workspace `Cargo.toml`
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask lives in the workspace")
+        .to_path_buf()
+}
+
+/// Run `cargo <subcommand>` for `combination`; `Ok(false)` if it failed.
+fn cargo(subcommand: &str, combination: &Combination, extra: &[&str]) -> std::io::Result<bool> {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut command = Command::new(cargo);
+    command
+        .current_dir(workspace_root())
+        .args([subcommand, "--package", PACKAGE])
+        .args(combination.features);
+    match combination.target {
+        // Only the library builds without `std`
+        Some(target) => command.args(["--lib", "--target", target]),
+        None if subcommand == "clippy" => command.arg("--all-targets"),
+        None => &mut command,
+    };
+    if subcommand == "clippy" {
+        command.args(["--", "-D", "warnings"]);
+    }
+    command.args(extra);
+
+    eprintln!("==> {}: cargo {}", combination.name, {
+        let args: Vec<_> = command.get_args().map(|a| a.to_string_lossy()).collect();
+        args.join(" ")
+    });
+    Ok(command.status()?.success())
+}
+
+/// Check every combination, returning the names of the failed steps.
+fn feature_matrix(run_tests: bool) -> std::io::Result<Vec<String>> {
+    let mut failures = Vec::new();
+    for combination in COMBINATIONS {
+        if !cargo("clippy", combination, &[])? {
+            failures.push(format!("{} (clippy)", combination.name));
+            continue;
+        }
+        if run_tests && combination.test && !cargo("test", combination, &[])? {
+            failures.push(format!("{} (test)", combination.name));
+        }
+    }
+    Ok(failures)
+}
+
+fn usage() -> ExitCode {
+    eprintln!("usage: cargo xtask feature-matrix [--no-tests]");
+    ExitCode::from(2)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let run_tests = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["feature-matrix"] => true,
+        ["feature-matrix", "--no-tests"] => false,
+        _ => return usage(),
+    };
+
+    match feature_matrix(run_tests) {
+        Ok(failures) if failures.is_empty() => {
+            eprintln!(
+                "feature matrix: all {} combinations pass",
+                COMBINATIONS.len()
+            );
+            ExitCode::SUCCESS
+        }
+        Ok(failures) => {
+            eprintln!("feature matrix: {} failed:", failures.len());
+            for failure in &failures {
+                eprintln!("  {}", failure);
+            }
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("error: could not run cargo: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}